@@ -0,0 +1,159 @@
+use std::rc::Rc;
+
+use clap::ValueEnum;
+
+use crate::ast::{FunctionTree, OpNode, OpValue, Piece};
+use crate::errors::{self, CalcError};
+
+
+/// How the derivatives of abstract functions are written in the output
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum DerivativeNotation {
+    /// `f'(x)`, or `f_1(x, y)` for the partial derivative with respect to the first argument
+    #[default]
+    Prime,
+    /// `df(x) / dx`, or `d^2 f(x, y) / dx dy` for partial derivatives
+    Leibniz,
+}
+
+
+/// The derivative of an abstract function, a function without a definition like `f` in `f(x) * g(x)`
+#[derive(Clone, Default)]
+pub struct Derivative {
+    /// The index of every argument the function is derived with respect to, in increasing order.
+    /// An index is repeated for higher order derivatives, and there are none for the function itself
+    pub partials: Vec<usize>,
+    pub notation: DerivativeNotation,
+}
+
+impl Derivative {
+
+    /// The derivative of this derivative with respect to the argument at `index`
+    pub fn with_respect_to(&self, index: usize) -> Derivative {
+        let mut partials = self.partials.clone();
+        let position = partials.partition_point(|other| *other <= index);
+        partials.insert(position, index);
+        Derivative { partials, notation: self.notation }
+    }
+
+
+    pub fn order(&self) -> usize {
+        self.partials.len()
+    }
+
+
+    /// Whether the derivative is written as a quotient, like `df(x) / dx`
+    pub fn is_leibniz(&self) -> bool {
+        matches!(self.notation, DerivativeNotation::Leibniz) && !self.partials.is_empty()
+    }
+
+
+    /// What is written after the name of the function: nothing in Leibniz notation, where the derivative is a quotient instead
+    pub fn suffix(&self, arity: usize) -> String {
+        if self.is_leibniz() {
+            String::new()
+        } else {
+            self.prime_suffix(arity)
+        }
+    }
+
+
+    /// The primes or subscripts written after the name of the function in prime notation, like `''` in `f''(x)` or `_12` in `f_12(x, y)`
+    pub fn prime_suffix(&self, arity: usize) -> String {
+        if self.partials.is_empty() {
+            return String::new();
+        }
+        match arity {
+            1 => "'".repeat(self.order()),
+            // Functions rarely have more than 9 arguments, so the indices are simply concatenated
+            _ => format!("_{}", self.partials.iter().map(|index| (index + 1).to_string()).collect::<String>()),
+        }
+    }
+
+
+    /// Every argument the function is derived with respect to, along with how many times, for the denominator of the Leibniz notation
+    pub fn groups(&self) -> Vec<(usize, usize)> {
+        let mut groups: Vec<(usize, usize)> = Vec::new();
+        for index in &self.partials {
+            match groups.last_mut() {
+                Some((last, count)) if last == index => *count += 1,
+                _ => groups.push((*index, 1)),
+            }
+        }
+        groups
+    }
+
+}
+
+
+/// The first call to an abstract function in the subtree, if any
+pub fn find<'n, 'a>(node: &'n OpNode<'a>) -> Option<&'n OpNode<'a>> {
+    match &node.value {
+        OpValue::Number(_) |
+        OpValue::Variable(_)
+            => None,
+        OpValue::Add { left, right } |
+        OpValue::Sub { left, right } |
+        OpValue::Mul { left, right } |
+        OpValue::Div { left, right } |
+        OpValue::Pow { left, right } |
+        OpValue::Log { base: left, arg: right }
+            => find(left).or_else(|| find(right)),
+        OpValue::Function { func: _, arg } => find(arg),
+        OpValue::MultiFunction { func: _, args } => args.iter().find_map(|arg| find(arg)),
+        OpValue::Piecewise { pieces } => pieces.iter().flat_map(Piece::operands).find_map(|operand| find(operand)),
+        OpValue::Call { .. } => Some(node),
+    }
+}
+
+
+/// Abstract functions have no value, so a function containing one can be derived but not evaluated
+pub fn require_defined(function: &FunctionTree) -> Result<(), CalcError> {
+    match find(&function.root).map(|node| &node.value) {
+        Some(OpValue::Call { name, .. }) => Err(errors::invalid_input(
+            format!("`{}` has no definition, so it can only be derived. Define it like `{}(t) = t^2 + 1` to evaluate it.", name, name).as_str()
+        )),
+        _ => Ok(())
+    }
+}
+
+
+/// Write the derivatives of abstract functions in the given notation
+pub fn rewrite<'a>(tree: &FunctionTree<'a>, notation: DerivativeNotation) -> FunctionTree<'a> {
+    FunctionTree {
+        root: rewrite_node(&tree.root, notation)
+    }
+}
+
+
+fn rewrite_node<'a>(node: &Rc<OpNode<'a>>, notation: DerivativeNotation) -> Rc<OpNode<'a>> {
+
+    if find(node).is_none() {
+        return Rc::clone(node);
+    }
+
+    let value = match &node.value {
+        OpValue::Add { left, right } => OpValue::Add { left: rewrite_node(left, notation), right: rewrite_node(right, notation) },
+        OpValue::Sub { left, right } => OpValue::Sub { left: rewrite_node(left, notation), right: rewrite_node(right, notation) },
+        OpValue::Mul { left, right } => OpValue::Mul { left: rewrite_node(left, notation), right: rewrite_node(right, notation) },
+        OpValue::Div { left, right } => OpValue::Div { left: rewrite_node(left, notation), right: rewrite_node(right, notation) },
+        OpValue::Pow { left, right } => OpValue::Pow { left: rewrite_node(left, notation), right: rewrite_node(right, notation) },
+        OpValue::Function { func, arg } => OpValue::Function { func: *func, arg: rewrite_node(arg, notation) },
+        OpValue::Log { base, arg } => OpValue::Log { base: rewrite_node(base, notation), arg: rewrite_node(arg, notation) },
+        OpValue::MultiFunction { func, args } => OpValue::MultiFunction { func: *func, args: args.iter().map(|arg| rewrite_node(arg, notation)).collect() },
+        OpValue::Piecewise { pieces } => OpValue::Piecewise { pieces: pieces.iter().map(|piece| piece.map(|operand| rewrite_node(operand, notation))).collect() },
+        OpValue::Call { name, args, derivative } => OpValue::Call {
+            name,
+            args: args.iter().map(|arg| rewrite_node(arg, notation)).collect(),
+            derivative: Derivative { partials: derivative.partials.clone(), notation }
+        },
+        OpValue::Number(_) |
+        OpValue::Variable(_)
+            => unreachable!("Numbers and variables don't contain abstract functions"),
+    };
+
+    Rc::new(OpNode {
+        source: Rc::clone(&node.source),
+        value
+    })
+}
@@ -1,8 +1,22 @@
 use std::fmt;
 use std::rc::Rc;
+use std::f64::consts;
+use std::iter;
 
 use crate::tokenizer::SourceToken;
-use crate::functions::Functions;
+use crate::functions::{Functions, MultiFunctions};
+use crate::notation::{self, Notation};
+use crate::abstract_functions::Derivative;
+
+
+/// Value of the variable names that are treated as mathematical constants
+pub fn constant_value(name: &str) -> Option<f64> {
+    match name {
+        "e" => Some(consts::E),
+        "pi" => Some(consts::PI),
+        _ => None
+    }
+}
 
 
 #[derive(Clone)]
@@ -16,10 +30,22 @@ pub enum OpValue<'a> {
     Pow { left: Rc<OpNode<'a>>, right: Rc<OpNode<'a>> },
     Variable (&'a str),
 
-    // Here Box<[]> must be used because Rc does not include the size of the slice
     /// A one-argument math function
     Function { func: Functions, arg: Rc<OpNode<'a>> },
 
+    // Here Box<[]> must be used because Rc does not include the size of the slice
+    /// A math function of several arguments, as many as its arity
+    MultiFunction { func: MultiFunctions, args: Box<[Rc<OpNode<'a>>]> },
+    /// Logarithm of `arg` in the given base
+    Log { base: Rc<OpNode<'a>>, arg: Rc<OpNode<'a>> },
+
+    /// The value of the first piece whose condition holds, like `{x^2 if x < 0; sin(x) otherwise}`
+    Piecewise { pieces: Box<[Piece<'a>]> },
+
+    /// A call to a function defined by the user, like `g(x)`, replaced by the definition right after parsing.
+    /// A function without a definition is abstract: it is kept as is, and can be derived but not evaluated
+    Call { name: &'a str, args: Box<[Rc<OpNode<'a>>]>, derivative: Derivative },
+
 }
 
 impl fmt::Display for OpValue<'_> {
@@ -33,6 +59,67 @@ impl fmt::Display for OpValue<'_> {
             OpValue::Pow { left, right } => write!(f, "({} ^ {})", left.value, right.value),
             OpValue::Variable(name) => write!(f, "{}", name),
             OpValue::Function { func, arg } => write!(f, "{}({})", func, arg.value),
+            OpValue::Log { base, arg } => write!(f, "log({}, {})", base.value, arg.value),
+            OpValue::MultiFunction { func, args } => {
+                write!(f, "{}(", func)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg.value)?;
+                }
+                write!(f, ")")
+            },
+            OpValue::Call { name, args, derivative } => {
+                if derivative.is_leibniz() {
+                    write!(f, "(d")?;
+                    if derivative.order() > 1 {
+                        write!(f, "^{} ", derivative.order())?;
+                    }
+                }
+                write!(f, "{}{}(", name, derivative.suffix(args.len()))?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg.value)?;
+                }
+                write!(f, ")")?;
+                if derivative.is_leibniz() {
+                    write!(f, " /")?;
+                    for (index, count) in derivative.groups() {
+                        // Operations are already in parentheses
+                        match args[index].value {
+                            OpValue::Variable(_) |
+                            OpValue::Add { .. } |
+                            OpValue::Sub { .. } |
+                            OpValue::Mul { .. } |
+                            OpValue::Div { .. } |
+                            OpValue::Pow { .. }
+                                => write!(f, " d{}", args[index].value)?,
+                            _ => write!(f, " d({})", args[index].value)?,
+                        }
+                        if count > 1 {
+                            write!(f, "^{}", count)?;
+                        }
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            },
+            OpValue::Piecewise { pieces } => {
+                write!(f, "{{")?;
+                for (i, piece) in pieces.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, "; ")?;
+                    }
+                    match &piece.condition {
+                        Some(condition) => write!(f, "{} if {} {} {}", piece.value.value, condition.left.value, condition.comparison, condition.right.value)?,
+                        None => write!(f, "{} otherwise", piece.value.value)?,
+                    }
+                }
+                write!(f, "}}")
+            },
         }
     }
 }
@@ -86,6 +173,35 @@ impl OpValue<'_> {
                 writeln!(f, "{}()", func)?;
                 arg.value.fmt_indented(indent, f)?;
             },
+            OpValue::Log { base, arg } => {
+                writeln!(f, "log()")?;
+                base.value.fmt_indented(indent, f)?;
+                writeln!(f)?;
+                arg.value.fmt_indented(indent, f)?;
+            },
+            OpValue::MultiFunction { func, args } => {
+                write!(f, "{}()", func)?;
+                for arg in args.iter() {
+                    writeln!(f)?;
+                    arg.value.fmt_indented(indent, f)?;
+                }
+            },
+            OpValue::Call { name, args, derivative } => {
+                write!(f, "{}{}()", name, derivative.suffix(args.len()))?;
+                for arg in args.iter() {
+                    writeln!(f)?;
+                    arg.value.fmt_indented(indent, f)?;
+                }
+            },
+            OpValue::Piecewise { pieces } => {
+                write!(f, "{{}}")?;
+                for piece in pieces.iter() {
+                    for operand in piece.operands() {
+                        writeln!(f)?;
+                        operand.value.fmt_indented(indent, f)?;
+                    }
+                }
+            },
         }
 
         Ok(())
@@ -107,6 +223,122 @@ pub struct OpNode<'a> {
 
 }
 
+impl OpNode<'_> {
+
+    /// Whether the variable appears anywhere in the subtree
+    pub fn contains_variable(&self, name: &str) -> bool {
+        match &self.value {
+            OpValue::Number(_) => false,
+            OpValue::Variable(var) => *var == name,
+            OpValue::Add { left, right } |
+            OpValue::Sub { left, right } |
+            OpValue::Mul { left, right } |
+            OpValue::Div { left, right } |
+            OpValue::Pow { left, right } |
+            OpValue::Log { base: left, arg: right }
+                => left.contains_variable(name) || right.contains_variable(name),
+            OpValue::Function { func: _, arg } => arg.contains_variable(name),
+            OpValue::MultiFunction { func: _, args } |
+            OpValue::Call { name: _, args, derivative: _ }
+                => args.iter().any(|arg| arg.contains_variable(name)),
+            OpValue::Piecewise { pieces } => pieces.iter().flat_map(Piece::operands).any(|operand| operand.contains_variable(name)),
+        }
+    }
+
+}
+
+
+/// The first piece whose condition holds, according to `holds`, or `None` if no condition holds
+pub fn active_piece<'p, 'a, E>(pieces: &'p [Piece<'a>], mut holds: impl FnMut(&Condition<'a>) -> Result<bool, E>) -> Result<Option<&'p Piece<'a>>, E> {
+    for piece in pieces {
+        match &piece.condition {
+            Some(condition) if !holds(condition)? => (),
+            _ => return Ok(Some(piece)),
+        }
+    }
+    Ok(None)
+}
+
+
+/// Comparison operators, only used in the conditions of piecewise functions
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Comparison::Less => write!(f, "<"),
+            Comparison::LessOrEqual => write!(f, "<="),
+            Comparison::Greater => write!(f, ">"),
+            Comparison::GreaterOrEqual => write!(f, ">="),
+        }
+    }
+}
+
+impl Comparison {
+
+    pub fn holds(&self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+
+}
+
+
+/// Two functions that are equal, like `g(t) = t^2 + 1`
+pub struct Equation<'a> {
+    pub left: FunctionTree<'a>,
+    pub right: FunctionTree<'a>,
+}
+
+
+/// `left < right` and the like
+#[derive(Clone)]
+pub struct Condition<'a> {
+    pub left: Rc<OpNode<'a>>,
+    pub comparison: Comparison,
+    pub right: Rc<OpNode<'a>>,
+}
+
+
+/// A piece of a piecewise function. Only the last piece may have no condition, written `otherwise`
+#[derive(Clone)]
+pub struct Piece<'a> {
+    pub value: Rc<OpNode<'a>>,
+    pub condition: Option<Condition<'a>>,
+}
+
+impl<'a> Piece<'a> {
+
+    /// The value of the piece and both sides of its condition
+    pub fn operands(&self) -> impl Iterator<Item = &Rc<OpNode<'a>>> {
+        iter::once(&self.value).chain(self.condition.iter().flat_map(|condition| [&condition.left, &condition.right]))
+    }
+
+
+    /// The same piece, with `f` applied to the value and to both sides of the condition
+    pub fn map(&self, mut f: impl FnMut(&Rc<OpNode<'a>>) -> Rc<OpNode<'a>>) -> Piece<'a> {
+        Piece {
+            value: f(&self.value),
+            condition: self.condition.as_ref().map(|condition| Condition {
+                left: f(&condition.left),
+                comparison: condition.comparison,
+                right: f(&condition.right),
+            })
+        }
+    }
+
+}
+
 
 pub struct FunctionTree<'a> {
 
@@ -130,149 +362,460 @@ impl<'a> FunctionTree<'a> {
 
     pub fn simplify(&self) -> FunctionTree<'a> {
         FunctionTree {
-            root: simplify_node(&self.root)
+            root: simplify_node(&self.root, &mut None)
+        }
+    }
+
+
+    /// Simplify the function, recording every simplification applied
+    pub fn simplify_explained(&self) -> (FunctionTree<'a>, Vec<SimplificationStep>) {
+
+        let mut steps = Some(Vec::new());
+
+        let simplified = FunctionTree {
+            root: simplify_node(&self.root, &mut steps)
+        };
+
+        (simplified, steps.unwrap_or_default())
+    }
+
+
+    /// Replace every occurrence of the variable with the replacement subtree
+    pub fn substitute(&self, name: &str, replacement: &Rc<OpNode<'a>>) -> FunctionTree<'a> {
+        self.substitute_all(&[(name, Rc::clone(replacement))])
+    }
+
+
+    /// Replace every occurrence of each variable with its replacement subtree, all at once,
+    /// so that a replacement is never substituted again
+    pub fn substitute_all(&self, replacements: &[(&str, Rc<OpNode<'a>>)]) -> FunctionTree<'a> {
+        FunctionTree {
+            root: substitute_node(&self.root, replacements)
         }
     }
 
 }
 
 
-fn simplify_node<'a>(node: &Rc<OpNode<'a>>) -> Rc<OpNode<'a>> {
-    match &node.value {
+fn substitute_node<'a>(node: &Rc<OpNode<'a>>, replacements: &[(&str, Rc<OpNode<'a>>)]) -> Rc<OpNode<'a>> {
+
+    if !replacements.iter().any(|(name, _)| node.contains_variable(name)) {
+        return Rc::clone(node);
+    }
+
+    let value = match &node.value {
+        OpValue::Variable(name) => return replacements.iter()
+            .find(|(var, _)| var == name)
+            .map(|(_, replacement)| Rc::clone(replacement))
+            .expect("The variable is one of the replaced ones"),
+        OpValue::Add { left, right } => OpValue::Add { left: substitute_node(left, replacements), right: substitute_node(right, replacements) },
+        OpValue::Sub { left, right } => OpValue::Sub { left: substitute_node(left, replacements), right: substitute_node(right, replacements) },
+        OpValue::Mul { left, right } => OpValue::Mul { left: substitute_node(left, replacements), right: substitute_node(right, replacements) },
+        OpValue::Div { left, right } => OpValue::Div { left: substitute_node(left, replacements), right: substitute_node(right, replacements) },
+        OpValue::Pow { left, right } => OpValue::Pow { left: substitute_node(left, replacements), right: substitute_node(right, replacements) },
+        OpValue::Function { func, arg } => OpValue::Function { func: *func, arg: substitute_node(arg, replacements) },
+        OpValue::Log { base, arg } => OpValue::Log { base: substitute_node(base, replacements), arg: substitute_node(arg, replacements) },
+        OpValue::MultiFunction { func, args } => OpValue::MultiFunction { func: *func, args: args.iter().map(|arg| substitute_node(arg, replacements)).collect() },
+        OpValue::Piecewise { pieces } => OpValue::Piecewise { pieces: pieces.iter().map(|piece| piece.map(|operand| substitute_node(operand, replacements))).collect() },
+        OpValue::Call { name, args, derivative } => OpValue::Call { name, args: args.iter().map(|arg| substitute_node(arg, replacements)).collect(), derivative: derivative.clone() },
+        OpValue::Number(_) => unreachable!("Numbers don't contain variables"),
+    };
+
+    Rc::new(OpNode {
+        source: Rc::clone(&node.source),
+        value
+    })
+}
+
+
+/// A simplification rule
+#[derive(Clone, Copy)]
+pub enum SimplificationRule {
+    /// An operation on constants is replaced by its result, e.g. `3 + 4 -> 7`
+    ConstantFolding,
+    /// `x + 0 -> x`
+    AdditiveIdentity,
+    /// `x * 1 -> x`
+    MultiplicativeIdentity,
+    /// `x * 0 -> 0`
+    ZeroProduct,
+    /// `0 / x -> 0`
+    ZeroDividend,
+    /// `x / 1 -> x`
+    DivisionByOne,
+    /// `cosh(x)^2 - sinh(x)^2 -> 1`
+    HyperbolicIdentity,
+    /// A function applied to its inverse, e.g. `exp(ln(x)) -> x` or `log(b, b^x) -> x`
+    InverseFunctions,
+    /// A fractional exponent is written as a root, e.g. `x^(1/3) -> cbrt(x)` or `root(2, x) -> sqrt(x)`
+    Root,
+    /// Every piece has the same value, e.g. `{0 if x < 1; 0 otherwise} -> 0`
+    IdenticalPieces,
+}
+
+impl fmt::Display for SimplificationRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimplificationRule::ConstantFolding => write!(f, "constant folding"),
+            SimplificationRule::AdditiveIdentity => write!(f, "additive identity"),
+            SimplificationRule::MultiplicativeIdentity => write!(f, "multiplicative identity"),
+            SimplificationRule::ZeroProduct => write!(f, "zero product"),
+            SimplificationRule::ZeroDividend => write!(f, "zero dividend"),
+            SimplificationRule::DivisionByOne => write!(f, "division by one"),
+            SimplificationRule::HyperbolicIdentity => write!(f, "hyperbolic identity"),
+            SimplificationRule::InverseFunctions => write!(f, "inverse functions"),
+            SimplificationRule::Root => write!(f, "nth root"),
+            SimplificationRule::IdenticalPieces => write!(f, "identical pieces"),
+        }
+    }
+}
+
+
+/// A simplification applied to a subexpression, with the subexpression before and after it in infix notation
+pub struct SimplificationStep {
+    pub rule: SimplificationRule,
+    pub before: String,
+    pub after: String,
+}
+
+
+/// Simplify the children of the node first, then the node itself.
+/// If `steps` is not `None`, every simplification applied is recorded.
+fn simplify_node<'a>(node: &Rc<OpNode<'a>>, steps: &mut Option<Vec<SimplificationStep>>) -> Rc<OpNode<'a>> {
+
+    let value = match &node.value {
 
         OpValue::Number(_) |
         OpValue::Variable(_)
-            => Rc::clone(node), 
-
-        OpValue::Add { left, right } => {
-            
-            let left = simplify_node(left);
-            let right = simplify_node(right);
-
-            Rc::new(OpNode {
-                source: Rc::clone(&node.source),
-                value: match (&left.value, &right.value) {
-
-                    (OpValue::Number(0_f64), arg) |
-                    (arg, OpValue::Number(0_f64))
-                        => arg.clone(),
-                    
-
-                    (OpValue::Number(left), OpValue::Number(right))
-                        => OpValue::Number(left + right),
-                    
-                    _ => OpValue::Add { left, right }
-                }
-            })
+            => return Rc::clone(node),
+
+        OpValue::Add { left, right } => OpValue::Add { left: simplify_node(left, steps), right: simplify_node(right, steps) },
+        OpValue::Sub { left, right } => OpValue::Sub { left: simplify_node(left, steps), right: simplify_node(right, steps) },
+        OpValue::Mul { left, right } => OpValue::Mul { left: simplify_node(left, steps), right: simplify_node(right, steps) },
+        OpValue::Div { left, right } => OpValue::Div { left: simplify_node(left, steps), right: simplify_node(right, steps) },
+        OpValue::Pow { left, right } => OpValue::Pow { left: simplify_node(left, steps), right: simplify_node(right, steps) },
+        OpValue::Function { func, arg } => OpValue::Function { func: *func, arg: simplify_node(arg, steps) },
+        OpValue::Log { base, arg } => OpValue::Log { base: simplify_node(base, steps), arg: simplify_node(arg, steps) },
+        OpValue::MultiFunction { func, args } => OpValue::MultiFunction { func: *func, args: args.iter().map(|arg| simplify_node(arg, steps)).collect() },
+        OpValue::Piecewise { pieces } => OpValue::Piecewise { pieces: pieces.iter().map(|piece| piece.map(|operand| simplify_node(operand, steps))).collect() },
+        OpValue::Call { name, args, derivative } => OpValue::Call { name, args: args.iter().map(|arg| simplify_node(arg, steps)).collect(), derivative: derivative.clone() },
+    };
+
+    let node = Rc::new(OpNode {
+        source: Rc::clone(&node.source),
+        value
+    });
+
+    let Some((value, rule)) = simplify_value(&node.value) else {
+        return node;
+    };
+
+    let simplified = Rc::new(OpNode {
+        source: Rc::clone(&node.source),
+        value
+    });
+
+    if let Some(steps) = steps {
+        steps.push(SimplificationStep {
+            rule,
+            before: notation::format_node(&node, Notation::Infix),
+            after: notation::format_node(&simplified, Notation::Infix),
+        });
+    }
+
+    // A rule may produce new operations on the simplified operands, which may be simplified further
+    simplify_node(&simplified, steps)
+}
+
+
+/// Apply a simplification rule to the operation, whose operands are already simplified.
+/// Return `None` if no rule applies.
+fn simplify_value<'a>(value: &OpValue<'a>) -> Option<(OpValue<'a>, SimplificationRule)> {
+    match value {
+
+        OpValue::Add { left, right } => match (&left.value, &right.value) {
+
+            (OpValue::Number(0_f64), arg) |
+            (arg, OpValue::Number(0_f64))
+                => Some((arg.clone(), SimplificationRule::AdditiveIdentity)),
+
+            (OpValue::Number(left), OpValue::Number(right))
+                => Some((OpValue::Number(left + right), SimplificationRule::ConstantFolding)),
+
+            _ => None
         },
 
-        OpValue::Sub { left, right } => {
-            
-            let left = simplify_node(left);
-            let right = simplify_node(right);
+        OpValue::Sub { left, right } => match (&left.value, &right.value) {
 
-            if let (OpValue::Number(left), OpValue::Number(right)) = (&left.value, &right.value) {
-                Rc::new(OpNode {
-                    source: Rc::clone(&node.source),
-                    value: OpValue::Number(left - right)
-                })
-            } else {
-                Rc::new(OpNode {
-                    source: Rc::clone(&node.source),
-                    value: OpValue::Sub { left, right }
-                })
+            (OpValue::Number(left), OpValue::Number(right))
+                => Some((OpValue::Number(left - right), SimplificationRule::ConstantFolding)),
+
+            _ => match (squared_function(&left.value), squared_function(&right.value)) {
+
+                (Some((Functions::Cosh, a)), Some((Functions::Sinh, b))) if same_expression(a, b)
+                    => Some((OpValue::Number(1_f64), SimplificationRule::HyperbolicIdentity)),
+
+                (Some((Functions::Sinh, a)), Some((Functions::Cosh, b))) if same_expression(a, b)
+                    => Some((OpValue::Number(-1_f64), SimplificationRule::HyperbolicIdentity)),
+
+                _ => None
             }
         },
 
-        OpValue::Mul { left, right } => {
-            
-            let left = simplify_node(left);
-            let right = simplify_node(right);
-
-            Rc::new(OpNode {
-                source: Rc::clone(&node.source),
-                value: match (&left.value, &right.value) {
-
-                        (OpValue::Number(0_f64), _) |
-                        (_, OpValue::Number(0_f64))
-                            => OpValue::Number(0_f64),
-                        
-                        (OpValue::Number(1_f64), arg) |
-                        (arg, OpValue::Number(1_f64))
-                            => arg.clone(),
-
-                        (OpValue::Number(left), OpValue::Number(right))
-                            => OpValue::Number(left * right),
-                        
-                        _ => OpValue::Mul { left, right }
-                }
-            })
+        OpValue::Mul { left, right } => match (&left.value, &right.value) {
+
+            (OpValue::Number(0_f64), _) |
+            (_, OpValue::Number(0_f64))
+                => Some((OpValue::Number(0_f64), SimplificationRule::ZeroProduct)),
+
+            (OpValue::Number(1_f64), arg) |
+            (arg, OpValue::Number(1_f64))
+                => Some((arg.clone(), SimplificationRule::MultiplicativeIdentity)),
+
+            (OpValue::Number(left), OpValue::Number(right))
+                => Some((OpValue::Number(left * right), SimplificationRule::ConstantFolding)),
+
+            _ => None
         },
 
-        OpValue::Div { left, right } => {
-            
-            let left = simplify_node(left);
-            let right = simplify_node(right);
+        // Assuming the denominator is never zero
+        OpValue::Div { left, right } => match (&left.value, &right.value) {
 
-            // Assuming the denominator is never zero
+            (OpValue::Number(0_f64), _)
+                => Some((OpValue::Number(0_f64), SimplificationRule::ZeroDividend)),
 
-            Rc::new(OpNode {
-                source: Rc::clone(&node.source),
-                value: match (&left.value, &right.value) {
+            (arg, OpValue::Number(1_f64))
+                => Some((arg.clone(), SimplificationRule::DivisionByOne)),
 
-                    (OpValue::Number(0_f64), _)
-                        => OpValue::Number(0_f64),
+            (OpValue::Number(left), OpValue::Number(right))
+                => Some((OpValue::Number(left / right), SimplificationRule::ConstantFolding)),
 
-                    (arg, OpValue::Number(1_f64))
-                        => arg.clone(),
+            _ => None
+        },
 
-                    (OpValue::Number(left), OpValue::Number(right))
-                        => OpValue::Number(left / right),
-                    
-                    _ => OpValue::Div { left, right }
-                }
-            })
+        OpValue::Pow { left, right } => match (&left.value, &right.value) {
+
+            (OpValue::Number(left), OpValue::Number(right))
+                => Some((OpValue::Number(exec_pow(*left, *right)), SimplificationRule::ConstantFolding)),
+
+            // root(n, x)^n -> x, assuming x is not negative for even roots
+            (_, OpValue::Number(exponent)) if root_of(left).is_some_and(|(index, _)| index == *exponent)
+                => Some((root_of(left)?.1.value.clone(), SimplificationRule::InverseFunctions)),
+
+            // x^(1/n) -> root(n, x)
+            (_, OpValue::Number(exponent)) => root_index(1_f64 / exponent)
+                .map(|index| (root(&left.source, index, left), SimplificationRule::Root)),
+
+            _ => None
         },
 
-        OpValue::Pow { left, right } => {
-            
-            let left = simplify_node(left);
-            let right = simplify_node(right);
+        OpValue::Function { func, arg } => match &arg.value {
 
-            if let (OpValue::Number(left), OpValue::Number(right)) = (&left.value, &right.value) {
-                Rc::new(OpNode {
-                    source: Rc::clone(&node.source),
-                    value: OpValue::Number(left.powf(*right))
-                })
-            } else {
-                Rc::new(OpNode {
-                    source: Rc::clone(&node.source),
-                    value: OpValue::Pow { left, right }
-                })
-            }
+            OpValue::Number(arg)
+                => Some((OpValue::Number(exec_function(*func, *arg)), SimplificationRule::ConstantFolding)),
+
+            // cbrt(x^3) -> x
+            OpValue::Pow { left: base, right: exponent } if *func == Functions::CubeRoot && matches!(exponent.value, OpValue::Number(3_f64))
+                => Some((base.value.clone(), SimplificationRule::InverseFunctions)),
+
+            // Assuming the argument of the logarithm is positive
+            OpValue::Function { func: inner, arg } if inverse_functions(*func, *inner)
+                => Some((arg.value.clone(), SimplificationRule::InverseFunctions)),
+
+            // ln(e^x) -> x, log10(10^x) -> x, log2(2^x) -> x
+            OpValue::Pow { left: base, right: exponent } if logarithm_base(*func).is_some() && constant_of(base) == logarithm_base(*func)
+                => Some((exponent.value.clone(), SimplificationRule::InverseFunctions)),
+
+            _ => None
         },
 
-        OpValue::Function { func, arg } => {
+        OpValue::Log { base, arg } => match (&base.value, &arg.value) {
 
-            let arg = simplify_node(arg);
+            (OpValue::Number(base), OpValue::Number(arg))
+                => Some((OpValue::Number(exec_log(*base, *arg)), SimplificationRule::ConstantFolding)),
 
-            if let OpValue::Number(arg) = arg.value {
-                Rc::new(OpNode {
-                    source: Rc::clone(&node.source),
-                    value: OpValue::Number(exec_function(*func, arg))
-                })
-            } else {
-                Rc::new(OpNode {
-                    source: Rc::clone(&node.source),
-                    value: OpValue::Function { func: *func, arg }
+            // log(b, b) -> 1
+            _ if same_expression(base, arg)
+                => Some((OpValue::Number(1_f64), SimplificationRule::InverseFunctions)),
+
+            // log(b, b^x) -> x
+            (_, OpValue::Pow { left, right }) if same_expression(base, left)
+                => Some((right.value.clone(), SimplificationRule::InverseFunctions)),
+
+            _ => None
+        },
+
+        OpValue::MultiFunction { func: MultiFunctions::Root, args } if !matches!(args[1].value, OpValue::Number(_)) => match (&args[0].value, &args[1].value) {
+
+            // root(2, x) -> sqrt(x), root(3, x) -> cbrt(x)
+            (OpValue::Number(index @ (2_f64 | 3_f64)), _)
+                => Some((root(&args[1].source, *index, &args[1]), SimplificationRule::Root)),
+
+            // root(n, x^n) -> x, for odd n
+            (OpValue::Number(index), OpValue::Pow { left, right })
+                if matches!(right.value, OpValue::Number(exponent) if exponent == *index) && index % 2_f64 == 1_f64
+                => Some((left.value.clone(), SimplificationRule::InverseFunctions)),
+
+            _ => None
+        },
+
+        OpValue::MultiFunction { func, args } => {
+            let args = args.iter()
+                .map(|arg| match arg.value {
+                    OpValue::Number(n) => Some(n),
+                    _ => None
                 })
+                .collect::<Option<Vec<_>>>()?;
+            Some((OpValue::Number(exec_multi_function(*func, &args)), SimplificationRule::ConstantFolding))
+        },
+
+        // Pieces whose condition is constant are either dropped or become the last piece
+        OpValue::Piecewise { pieces } => {
+            let mut folded = Vec::new();
+            let mut changed = false;
+
+            for piece in pieces.iter() {
+                match piece.condition.as_ref().and_then(constant_condition) {
+                    Some(false) => changed = true,
+                    Some(true) => {
+                        changed = true;
+                        folded.push(Piece { value: Rc::clone(&piece.value), condition: None });
+                        break;
+                    },
+                    None => folded.push(piece.clone()),
+                }
+            }
+
+            match &folded[..] {
+                // No condition can ever hold
+                [] => Some((OpValue::Number(f64::NAN), SimplificationRule::ConstantFolding)),
+                [Piece { value, condition: None }] => Some((value.value.clone(), SimplificationRule::ConstantFolding)),
+                _ if changed => Some((OpValue::Piecewise { pieces: folded.into_boxed_slice() }, SimplificationRule::ConstantFolding)),
+                // With an `otherwise` piece, some piece always applies
+                [first, .., Piece { value: _, condition: None }] if folded.iter().all(|piece| same_expression(&piece.value, &first.value))
+                    => Some((first.value.value.clone(), SimplificationRule::IdenticalPieces)),
+                _ => None
             }
         },
+
+        OpValue::Number(_) |
+        OpValue::Variable(_) |
+        OpValue::Call { .. }
+            => None,
+    }
+}
+
+
+/// The index of the root, if `n` is an integer of at least 2.
+/// `n` is often the reciprocal of a folded exponent like `1/3`, so it is rounded within rounding error
+fn root_index(n: f64) -> Option<f64> {
+    let rounded = n.round();
+    ((n - rounded).abs() < 1e-9 && rounded >= 2_f64).then_some(rounded)
+}
+
+
+/// `root(n, x)`, written as `sqrt(x)` or `cbrt(x)` when possible
+fn root<'a>(source: &Rc<SourceToken<'a>>, index: f64, arg: &Rc<OpNode<'a>>) -> OpValue<'a> {
+    match index {
+        2_f64 => OpValue::Function { func: Functions::SquareRoot, arg: Rc::clone(arg) },
+        3_f64 => OpValue::Function { func: Functions::CubeRoot, arg: Rc::clone(arg) },
+        _ => OpValue::MultiFunction {
+            func: MultiFunctions::Root,
+            args: Box::new([Rc::new(OpNode { source: Rc::clone(source), value: OpValue::Number(index) }), Rc::clone(arg)])
+        }
+    }
+}
+
+
+/// If the node is a root with a constant index, like `sqrt(x)` or `root(5, x)`, return the index and the radicand
+fn root_of<'n, 'a>(node: &'n OpNode<'a>) -> Option<(f64, &'n Rc<OpNode<'a>>)> {
+    match &node.value {
+        OpValue::Function { func: Functions::SquareRoot, arg } => Some((2_f64, arg)),
+        OpValue::Function { func: Functions::CubeRoot, arg } => Some((3_f64, arg)),
+        OpValue::MultiFunction { func: MultiFunctions::Root, args } => match &args[0].value {
+            OpValue::Number(index) => Some((*index, &args[1])),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+
+/// Whether `outer(inner(x)) = x`, like `exp(ln(x))`
+fn inverse_functions(outer: Functions, inner: Functions) -> bool {
+    matches!((outer, inner), (Functions::Exp, Functions::NaturalLog) | (Functions::NaturalLog, Functions::Exp))
+}
+
+
+/// Base of the logarithm functions
+fn logarithm_base(func: Functions) -> Option<f64> {
+    match func {
+        Functions::NaturalLog => Some(consts::E),
+        Functions::Log10 => Some(10_f64),
+        Functions::Log2 => Some(2_f64),
+        _ => None
+    }
+}
+
+
+/// Value of the node if it is a number or a known constant
+fn constant_of(node: &OpNode) -> Option<f64> {
+    match &node.value {
+        OpValue::Number(n) => Some(*n),
+        OpValue::Variable(name) => constant_value(name),
+        _ => None
+    }
+}
+
+
+/// Whether the condition holds, if both of its sides are constant
+fn constant_condition(condition: &Condition) -> Option<bool> {
+    Some(condition.comparison.holds(constant_of(&condition.left)?, constant_of(&condition.right)?))
+}
+
+
+/// If the value is `f(a)^2`, return `f` and `a`
+fn squared_function<'n, 'a>(value: &'n OpValue<'a>) -> Option<(Functions, &'n OpNode<'a>)> {
+    match value {
+        OpValue::Pow { left, right } => match (&left.value, &right.value) {
+            (OpValue::Function { func, arg }, OpValue::Number(2_f64)) => Some((*func, arg)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+
+/// Whether the two expressions are written the same way, regardless of where they come from in the source
+pub fn same_expression(a: &OpNode, b: &OpNode) -> bool {
+    match (&a.value, &b.value) {
+        (OpValue::Number(a), OpValue::Number(b)) => a == b,
+        (OpValue::Variable(a), OpValue::Variable(b)) => a == b,
+        (OpValue::Add { left: a_left, right: a_right }, OpValue::Add { left: b_left, right: b_right }) |
+        (OpValue::Sub { left: a_left, right: a_right }, OpValue::Sub { left: b_left, right: b_right }) |
+        (OpValue::Mul { left: a_left, right: a_right }, OpValue::Mul { left: b_left, right: b_right }) |
+        (OpValue::Div { left: a_left, right: a_right }, OpValue::Div { left: b_left, right: b_right }) |
+        (OpValue::Pow { left: a_left, right: a_right }, OpValue::Pow { left: b_left, right: b_right })
+            => same_expression(a_left, b_left) && same_expression(a_right, b_right),
+        (OpValue::Function { func: a_func, arg: a_arg }, OpValue::Function { func: b_func, arg: b_arg })
+            => a_func == b_func && same_expression(a_arg, b_arg),
+        (OpValue::Log { base: a_base, arg: a_arg }, OpValue::Log { base: b_base, arg: b_arg })
+            => same_expression(a_base, b_base) && same_expression(a_arg, b_arg),
+        (OpValue::MultiFunction { func: a_func, args: a_args }, OpValue::MultiFunction { func: b_func, args: b_args })
+            => a_func == b_func && a_args.iter().zip(b_args.iter()).all(|(a, b)| same_expression(a, b)),
+        (OpValue::Call { name: a_name, args: a_args, derivative: a_derivative }, OpValue::Call { name: b_name, args: b_args, derivative: b_derivative })
+            => a_name == b_name && a_derivative.partials == b_derivative.partials && a_args.len() == b_args.len() && a_args.iter().zip(b_args.iter()).all(|(a, b)| same_expression(a, b)),
+        (OpValue::Piecewise { pieces: a_pieces }, OpValue::Piecewise { pieces: b_pieces })
+            => a_pieces.len() == b_pieces.len() && a_pieces.iter().zip(b_pieces.iter()).all(|(a, b)|
+                a.condition.as_ref().map(|condition| condition.comparison) == b.condition.as_ref().map(|condition| condition.comparison)
+                && a.operands().zip(b.operands()).all(|(a, b)| same_expression(a, b))
+            ),
+        _ => false
     }
 }
 
 
-fn exec_function(func: Functions, arg: f64) -> f64 {
+pub fn exec_function(func: Functions, arg: f64) -> f64 {
     match func {
 
         Functions::Sin => arg.sin(),
@@ -282,8 +825,96 @@ fn exec_function(func: Functions, arg: f64) -> f64 {
         Functions::Arccos => arg.acos(),
         Functions::Arctan => arg.atan(),
         Functions::SquareRoot => arg.sqrt(),
+        Functions::CubeRoot => arg.cbrt(),
         Functions::NaturalLog => arg.ln(),
         Functions::Secant => 1_f64 / arg.cos(), // sec(x) = 1/cos(x)
+        Functions::Cosecant => 1_f64 / arg.sin(), // csc(x) = 1/sin(x)
+        Functions::Cotangent => arg.cos() / arg.sin(), // cot(x) = cos(x)/sin(x)
+        Functions::Arcsecant => (1_f64 / arg).acos(), // asec(x) = acos(1/x)
+        Functions::Arccosecant => (1_f64 / arg).asin(), // acsc(x) = asin(1/x)
+        Functions::Arccotangent => consts::FRAC_PI_2 - arg.atan(), // acot(x) = pi/2 - atan(x), continuous at 0
+        Functions::Sinh => arg.sinh(),
+        Functions::Cosh => arg.cosh(),
+        Functions::Tanh => arg.tanh(),
+        // NaN outside of the domain, [1, ∞) for acosh and (-1, 1) for atanh
+        Functions::Arsinh => arg.asinh(),
+        Functions::Arcosh => arg.acosh(),
+        Functions::Artanh => arg.atanh(),
+        Functions::Exp => arg.exp(),
+        Functions::Log10 => arg.log10(),
+        Functions::Log2 => arg.log2(),
+        // f64::signum is 1 at 0
+        Functions::Sign => if arg == 0_f64 { 0_f64 } else { arg.signum() },
+        Functions::Abs => arg.abs(),
+        Functions::Floor => arg.floor(),
+        Functions::Ceil => arg.ceil(),
+        // Half-maximum convention, heaviside(0) = 1/2
+        Functions::Heaviside => (exec_function(Functions::Sign, arg) + 1_f64) / 2_f64,
+    }
+}
+
+
+/// Evaluate the function on as many arguments as its arity
+pub fn exec_multi_function(func: MultiFunctions, args: &[f64]) -> f64 {
+    match (func, args) {
+        (MultiFunctions::Atan2, [y, x]) => y.atan2(*x),
+        (MultiFunctions::Hypot, [a, b]) => a.hypot(*b),
+        (MultiFunctions::Min, [a, b]) => a.min(*b),
+        (MultiFunctions::Max, [a, b]) => a.max(*b),
+        (MultiFunctions::Root, [n, x]) => exec_root(*n, *x),
+        _ => unreachable!("The arity of `{}` is checked when parsing", func)
+    }
+}
+
+
+/// The `n`th root of `x`, which is real for negative numbers when `n` is odd, like `root(3, -8) = -2`
+pub fn exec_root(n: f64, x: f64) -> f64 {
+    if x < 0_f64 && n % 2_f64 == 1_f64 {
+        -(-x).powf(1_f64 / n)
+    } else {
+        x.powf(1_f64 / n)
+    }
+}
+
+
+/// Largest odd denominator of an exponent for which the power of a negative number is real
+const MAX_ROOT_DENOMINATOR: u16 = 99;
+
+
+/// `base ^ exponent`, which is also real for negative bases when the exponent is a fraction with an odd denominator,
+/// like `(-8)^(2/3) = 4`, so that `x^(1/3)` agrees with `cbrt(x)`.
+/// Exponents are floats, so the fraction is recognized within rounding error
+pub fn exec_pow(base: f64, exponent: f64) -> f64 {
+
+    if base >= 0_f64 || exponent.fract() == 0_f64 || !exponent.is_finite() {
+        return base.powf(exponent);
+    }
+
+    odd_root_numerator(exponent).map_or(f64::NAN, |numerator| {
+        // The sign is the one of the numerator power
+        let magnitude = (-base).powf(exponent);
+        if numerator % 2_f64 == 0_f64 { magnitude } else { -magnitude }
+    })
+}
+
+
+/// Numerator of the exponent written as a fraction with an odd denominator, like `2` for `2/3`, if it is not an integer.
+/// The powers of negative numbers are real for these exponents
+pub fn odd_root_numerator(exponent: f64) -> Option<f64> {
+
+    if exponent.fract() == 0_f64 || !exponent.is_finite() {
+        return None;
     }
+
+    (3..=MAX_ROOT_DENOMINATOR).step_by(2)
+        .map(|denominator| exponent * denominator as f64)
+        .find(|numerator| (numerator - numerator.round()).abs() < 1e-9)
+        .map(f64::round)
+}
+
+
+/// Logarithm of `arg` in the given base
+pub fn exec_log(base: f64, arg: f64) -> f64 {
+    arg.ln() / base.ln()
 }
 
@@ -0,0 +1,78 @@
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::errors::{self, CalcError};
+
+
+lazy_static! {
+
+    /// Trailing `; d=<var>` directive that overrides the derivation variable of a line
+    static ref DIRECTIVE_REGEX: Regex = Regex::new(
+        r#";\s*d\s*=\s*(\S*)\s*\z"#
+    ).expect("Regex failed to compile");
+
+}
+
+
+/// A function to derive, read from a batch input
+pub struct BatchLine {
+    /// 1-based line number in the batch input
+    pub number: usize,
+    pub function: String,
+    /// Derivation variable set by the line's directive, if any
+    pub variable: Option<String>,
+}
+
+
+/// Split the lines into functions and their directives.
+/// Blank lines and lines starting with `#` are skipped.
+pub fn parse_lines(lines: impl Iterator<Item = String>) -> Vec<BatchLine> {
+
+    lines.enumerate()
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .map(|(index, mut line)| {
+
+            let variable = DIRECTIVE_REGEX.captures(&line)
+                .map(|captures| (captures.get(0).unwrap().start(), captures[1].to_string()));
+
+            let variable = variable.map(|(directive_start, variable)| {
+                // Keep the function untouched so that error columns match the line
+                line.truncate(directive_start);
+                variable
+            });
+
+            line.truncate(line.trim_end().len());
+
+            BatchLine {
+                number: index + 1,
+                function: line,
+                variable
+            }
+        })
+        .collect()
+}
+
+
+pub fn read_file(path: &Path) -> Result<Vec<BatchLine>, CalcError> {
+
+    let content = fs::read_to_string(path)
+        .map_err(|error| errors::invalid_input(format!("Could not read file `{}`: {}", path.display(), error).as_str()))?;
+
+    Ok(parse_lines(content.lines().map(str::to_string)))
+}
+
+
+pub fn read_stdin() -> Result<Vec<BatchLine>, CalcError> {
+
+    let lines = io::stdin().lock().lines().collect::<Result<Vec<String>, _>>()
+        .map_err(|error| errors::invalid_input(format!("Could not read the standard input: {}", error).as_str()))?;
+
+    Ok(parse_lines(lines.into_iter()))
+}
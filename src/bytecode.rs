@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+
+use crate::ast::{self, Comparison, FunctionTree, OpNode, OpValue, Piece};
+use crate::errors::{self, CalcError};
+use crate::functions::{Functions, MultiFunctions};
+
+
+/// Index of a register in the register file of a program
+pub type Register = usize;
+
+
+#[derive(Clone, Copy)]
+pub enum Instruction {
+    Add { dest: Register, left: Register, right: Register },
+    Sub { dest: Register, left: Register, right: Register },
+    Mul { dest: Register, left: Register, right: Register },
+    Div { dest: Register, left: Register, right: Register },
+    Pow { dest: Register, left: Register, right: Register },
+    Call { dest: Register, func: Functions, arg: Register },
+    Log { dest: Register, base: Register, arg: Register },
+    /// Call of a function of two arguments
+    Call2 { dest: Register, func: MultiFunctions, left: Register, right: Register },
+    /// 1 if the comparison holds, 0 otherwise
+    Compare { dest: Register, comparison: Comparison, left: Register, right: Register },
+    /// `then` if `test` is not 0, `otherwise` if it is
+    Select { dest: Register, test: Register, then: Register, otherwise: Register },
+}
+
+
+/// Identifies a computed value, used to find common subexpressions
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum ValueKey<'a> {
+    Number(u64),
+    Variable(&'a str),
+    Add(Register, Register),
+    Sub(Register, Register),
+    Mul(Register, Register),
+    Div(Register, Register),
+    Pow(Register, Register),
+    Call(Functions, Register),
+    Log(Register, Register),
+    Call2(MultiFunctions, Register, Register),
+    Compare(Comparison, Register, Register),
+    Select(Register, Register, Register),
+}
+
+
+/// A function compiled to a linear sequence of register instructions.
+///
+/// The first registers hold the values of the variables, in the order of `variables`.
+/// The following registers hold either a constant or the result of an instruction.
+pub struct Program<'a> {
+    variables: Vec<&'a str>,
+    /// Initial content of the register file, with the constants preloaded
+    registers: Vec<f64>,
+    code: Vec<Instruction>,
+    result: Register,
+}
+
+
+struct Compiler<'a> {
+    variables: Vec<&'a str>,
+    registers: Vec<f64>,
+    code: Vec<Instruction>,
+    /// Values that have already been computed, with the register that holds them
+    values: HashMap<ValueKey<'a>, Register>,
+}
+
+
+/// Compile the function tree into a program.
+/// Identical subtrees are computed only once.
+pub fn compile<'a>(tree: &FunctionTree<'a>) -> Program<'a> {
+
+    // Variables must come first in the register file, so collect them beforehand
+    let mut variables = Vec::new();
+    collect_variables(&tree.root, &mut variables);
+
+    let mut compiler = Compiler {
+        registers: vec![0_f64; variables.len()],
+        variables,
+        code: Vec::new(),
+        values: HashMap::new(),
+    };
+
+    let result = compiler.compile_node(&tree.root);
+
+    Program {
+        variables: compiler.variables,
+        registers: compiler.registers,
+        code: compiler.code,
+        result
+    }
+}
+
+
+fn collect_variables<'a>(node: &OpNode<'a>, variables: &mut Vec<&'a str>) {
+    match &node.value {
+        OpValue::Number(_) => (),
+        OpValue::Variable(name) => {
+            if ast::constant_value(name).is_none() && !variables.contains(name) {
+                variables.push(name);
+            }
+        },
+        OpValue::Add { left, right } |
+        OpValue::Sub { left, right } |
+        OpValue::Mul { left, right } |
+        OpValue::Div { left, right } |
+        OpValue::Pow { left, right } |
+        OpValue::Log { base: left, arg: right }
+         => {
+            collect_variables(left, variables);
+            collect_variables(right, variables);
+        },
+        OpValue::Function { func: _, arg } => collect_variables(arg, variables),
+        OpValue::MultiFunction { func: _, args } |
+        OpValue::Call { name: _, args, derivative: _ }
+            => args.iter().for_each(|arg| collect_variables(arg, variables)),
+        OpValue::Piecewise { pieces } => pieces.iter().flat_map(Piece::operands).for_each(|operand| collect_variables(operand, variables)),
+    }
+}
+
+
+impl<'a> Compiler<'a> {
+
+    /// Return the register holding the value of the node, emitting the instructions to compute it if needed
+    fn compile_node(&mut self, node: &OpNode<'a>) -> Register {
+
+        let key = match &node.value {
+
+            OpValue::Number(n) => ValueKey::Number(n.to_bits()),
+
+            OpValue::Variable(name) => match ast::constant_value(name) {
+                Some(value) => ValueKey::Number(value.to_bits()),
+                None => ValueKey::Variable(name)
+            },
+
+            // Addition and multiplication are commutative, so the operands are sorted to match `b + a` with `a + b`
+            OpValue::Add { left, right } => {
+                let (left, right) = (self.compile_node(left), self.compile_node(right));
+                ValueKey::Add(left.min(right), left.max(right))
+            },
+            OpValue::Sub { left, right } => ValueKey::Sub(self.compile_node(left), self.compile_node(right)),
+            OpValue::Mul { left, right } => {
+                let (left, right) = (self.compile_node(left), self.compile_node(right));
+                ValueKey::Mul(left.min(right), left.max(right))
+            },
+            OpValue::Div { left, right } => ValueKey::Div(self.compile_node(left), self.compile_node(right)),
+            OpValue::Pow { left, right } => ValueKey::Pow(self.compile_node(left), self.compile_node(right)),
+            OpValue::Function { func, arg } => ValueKey::Call(*func, self.compile_node(arg)),
+            OpValue::Log { base, arg } => ValueKey::Log(self.compile_node(base), self.compile_node(arg)),
+            OpValue::MultiFunction { func, args } => match &args[..] {
+                [left, right] => ValueKey::Call2(*func, self.compile_node(left), self.compile_node(right)),
+                _ => unreachable!("Every function of several arguments takes 2 arguments")
+            },
+            OpValue::Piecewise { pieces } => return self.compile_pieces(pieces),
+            OpValue::Call { .. } => unreachable!("Functions containing abstract functions are rejected before being evaluated"),
+        };
+
+        self.register_of(key)
+    }
+
+
+    /// Every piece is computed, then each condition selects either the value of its piece or the value of the following pieces
+    fn compile_pieces(&mut self, pieces: &[Piece<'a>]) -> Register {
+        match pieces {
+            // No condition holds
+            [] => self.register_of(ValueKey::Number(f64::NAN.to_bits())),
+            [first, rest @ ..] => {
+                let then = self.compile_node(&first.value);
+                let Some(condition) = &first.condition else {
+                    return then;
+                };
+                let key = ValueKey::Compare(condition.comparison, self.compile_node(&condition.left), self.compile_node(&condition.right));
+                let test = self.register_of(key);
+                let otherwise = self.compile_pieces(rest);
+                self.register_of(ValueKey::Select(test, then, otherwise))
+            }
+        }
+    }
+
+
+    /// Return the register holding the value, emitting the instruction to compute it if it hasn't been computed yet
+    fn register_of(&mut self, key: ValueKey<'a>) -> Register {
+
+        if let Some(register) = self.values.get(&key) {
+            return *register;
+        }
+
+        let register = match key {
+
+            ValueKey::Variable(name) => self.variables.iter().position(|var| *var == name)
+                .expect("Variables are collected before compiling"),
+
+            ValueKey::Number(bits) => {
+                self.registers.push(f64::from_bits(bits));
+                self.registers.len() - 1
+            },
+
+            _ => {
+                self.registers.push(0_f64);
+                let dest = self.registers.len() - 1;
+
+                self.code.push(match key {
+                    ValueKey::Add(left, right) => Instruction::Add { dest, left, right },
+                    ValueKey::Sub(left, right) => Instruction::Sub { dest, left, right },
+                    ValueKey::Mul(left, right) => Instruction::Mul { dest, left, right },
+                    ValueKey::Div(left, right) => Instruction::Div { dest, left, right },
+                    ValueKey::Pow(left, right) => Instruction::Pow { dest, left, right },
+                    ValueKey::Call(func, arg) => Instruction::Call { dest, func, arg },
+                    ValueKey::Log(base, arg) => Instruction::Log { dest, base, arg },
+                    ValueKey::Call2(func, left, right) => Instruction::Call2 { dest, func, left, right },
+                    ValueKey::Compare(comparison, left, right) => Instruction::Compare { dest, comparison, left, right },
+                    ValueKey::Select(test, then, otherwise) => Instruction::Select { dest, test, then, otherwise },
+                    ValueKey::Number(_) |
+                    ValueKey::Variable(_)
+                        => unreachable!(),
+                });
+
+                dest
+            },
+        };
+
+        self.values.insert(key, register);
+
+        register
+    }
+
+}
+
+
+impl<'a> Program<'a> {
+
+    /// The variables of the program, in the order their values are expected by `run`
+    pub fn variables(&self) -> &[&'a str] {
+        &self.variables
+    }
+
+
+    /// A register file to pass to `run`
+    pub fn new_registers(&self) -> Vec<f64> {
+        self.registers.clone()
+    }
+
+
+    /// Run the program with the given variable values, in the order of `variables`.
+    /// `registers` must have been created by `new_registers`, and can be reused across runs.
+    pub fn run(&self, args: &[f64], registers: &mut [f64]) -> f64 {
+
+        registers[..args.len()].copy_from_slice(args);
+
+        for instruction in &self.code {
+            match *instruction {
+                Instruction::Add { dest, left, right } => registers[dest] = registers[left] + registers[right],
+                Instruction::Sub { dest, left, right } => registers[dest] = registers[left] - registers[right],
+                Instruction::Mul { dest, left, right } => registers[dest] = registers[left] * registers[right],
+                Instruction::Div { dest, left, right } => registers[dest] = registers[left] / registers[right],
+                Instruction::Pow { dest, left, right } => registers[dest] = ast::exec_pow(registers[left], registers[right]),
+                Instruction::Call { dest, func, arg } => registers[dest] = ast::exec_function(func, registers[arg]),
+                Instruction::Log { dest, base, arg } => registers[dest] = ast::exec_log(registers[base], registers[arg]),
+                Instruction::Call2 { dest, func, left, right } => registers[dest] = ast::exec_multi_function(func, &[registers[left], registers[right]]),
+                Instruction::Compare { dest, comparison, left, right } => registers[dest] = exec_compare(comparison, registers[left], registers[right]),
+                Instruction::Select { dest, test, then, otherwise } => registers[dest] = if registers[test] != 0_f64 { registers[then] } else { registers[otherwise] },
+            }
+        }
+
+        registers[self.result]
+    }
+
+
+    /// Run the program on every set of variable values and store the results in `output`.
+    /// `args` holds the values of each variable, in the order of `variables`, and every slice must be as long as `output`.
+    ///
+    /// The points are processed in chunks, and every instruction is applied to the whole chunk before moving to the next
+    /// instruction, so that the inner loops are simple enough to be vectorized.
+    pub fn run_batch(&self, args: &[&[f64]], output: &mut [f64]) {
+
+        // Every register is a column of `LANES` values
+        let mut registers = vec![0_f64; self.registers.len() * LANES];
+
+        for (register, value) in self.registers.iter().enumerate().skip(self.variables.len()) {
+            registers[register * LANES..][..LANES].fill(*value);
+        }
+
+        for start in (0..output.len()).step_by(LANES) {
+
+            let len = LANES.min(output.len() - start);
+
+            for (register, arg) in args.iter().enumerate() {
+                registers[register * LANES..][..len].copy_from_slice(&arg[start..][..len]);
+            }
+
+            for instruction in &self.code {
+                match *instruction {
+                    Instruction::Add { dest, left, right } => apply_binary(&mut registers, dest, left, right, len, |a, b| a + b),
+                    Instruction::Sub { dest, left, right } => apply_binary(&mut registers, dest, left, right, len, |a, b| a - b),
+                    Instruction::Mul { dest, left, right } => apply_binary(&mut registers, dest, left, right, len, |a, b| a * b),
+                    Instruction::Div { dest, left, right } => apply_binary(&mut registers, dest, left, right, len, |a, b| a / b),
+                    Instruction::Pow { dest, left, right } => apply_binary(&mut registers, dest, left, right, len, ast::exec_pow),
+                    Instruction::Call { dest, func, arg } => apply_binary(&mut registers, dest, arg, arg, len, |a, _| ast::exec_function(func, a)),
+                    Instruction::Log { dest, base, arg } => apply_binary(&mut registers, dest, base, arg, len, ast::exec_log),
+                    Instruction::Call2 { dest, func, left, right } => apply_binary(&mut registers, dest, left, right, len, |a, b| ast::exec_multi_function(func, &[a, b])),
+                    Instruction::Compare { dest, comparison, left, right } => apply_binary(&mut registers, dest, left, right, len, |a, b| exec_compare(comparison, a, b)),
+                    Instruction::Select { dest, test, then, otherwise } => apply_select(&mut registers, dest, test, then, otherwise, len),
+                }
+            }
+
+            output[start..][..len].copy_from_slice(&registers[self.result * LANES..][..len]);
+        }
+    }
+
+
+    /// Order the named values as expected by `run` and `run_batch`
+    pub fn arguments<'v, T>(&self, values: &'v [(String, T)]) -> Result<Vec<&'v T>, CalcError> {
+        self.variables.iter()
+            .map(|var| values.iter()
+                .find(|(name, _)| name == var)
+                .map(|(_, value)| value)
+                .ok_or_else(|| errors::invalid_input(format!("No value was given for variable `{}`", var).as_str()))
+            )
+            .collect()
+    }
+
+}
+
+
+/// Number of points processed at once by `Program::run_batch`, small enough for the register columns to stay in cache
+const LANES: usize = 256;
+
+
+/// Apply `op` element-wise to the first `len` values of two register columns
+fn apply_binary(registers: &mut [f64], dest: Register, left: Register, right: Register, len: usize, op: impl Fn(f64, f64) -> f64) {
+
+    // Instructions always write to a register allocated after their operands
+    let (sources, dest) = registers.split_at_mut(dest * LANES);
+
+    let left = &sources[left * LANES..][..len];
+    let right = &sources[right * LANES..][..len];
+
+    for ((dest, left), right) in dest[..len].iter_mut().zip(left).zip(right) {
+        *dest = op(*left, *right);
+    }
+}
+
+
+/// Like `apply_binary`, for `Instruction::Select`
+fn apply_select(registers: &mut [f64], dest: Register, test: Register, then: Register, otherwise: Register, len: usize) {
+
+    let (sources, dest) = registers.split_at_mut(dest * LANES);
+
+    let test = &sources[test * LANES..][..len];
+    let then = &sources[then * LANES..][..len];
+    let otherwise = &sources[otherwise * LANES..][..len];
+
+    for (((dest, test), then), otherwise) in dest[..len].iter_mut().zip(test).zip(then).zip(otherwise) {
+        *dest = if *test != 0_f64 { *then } else { *otherwise };
+    }
+}
+
+
+fn exec_compare(comparison: Comparison, left: f64, right: f64) -> f64 {
+    if comparison.holds(left, right) { 1_f64 } else { 0_f64 }
+}
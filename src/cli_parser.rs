@@ -1,16 +1,351 @@
-use clap::Parser;
+use std::fmt;
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::ast;
+use crate::codegen::Language;
+use crate::quadrature::Method;
+use crate::limits::Side;
+use crate::trigonometry::TrigStyle;
+use crate::abstract_functions::DerivativeNotation;
+
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+
+    /// Human-readable output
+    Text,
+
+    /// Stable machine-readable output, one tab-separated `key value` pair per line
+    Porcelain,
+
+    /// A JSON object with the function and its derivatives in several notations, along with their trees
+    Json,
+
+}
+
+
+/// Values assigned to variables, written as `x=1,y=2`
+#[derive(Clone)]
+pub struct Point(pub Vec<(String, f64)>);
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, value)) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+
+fn parse_point(string: &str) -> Result<Point, String> {
+    string.split(',')
+        .map(|assignment| {
+            let (name, value) = assignment.split_once('=')
+                .ok_or_else(|| format!("`{}` is not a `variable=value` assignment", assignment))?;
+            let value = value.trim().parse::<f64>()
+                .map_err(|_| format!("`{}` is not a number", value.trim()))?;
+            Ok((name.trim().to_string(), value))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Point)
+}
+
+
+/// Evenly spaced values of a variable, written as `x=start:end:count`
+#[derive(Clone)]
+pub struct GridAxis {
+    pub variable: String,
+    pub start: f64,
+    pub end: f64,
+    pub count: usize,
+}
+
+impl GridAxis {
+
+    pub fn values(&self) -> impl Iterator<Item = f64> + '_ {
+        let step = if self.count > 1 { (self.end - self.start) / (self.count - 1) as f64 } else { 0_f64 };
+        (0..self.count).map(move |i| self.start + step * i as f64)
+    }
+
+}
+
+
+fn parse_grid_axis(string: &str) -> Result<GridAxis, String> {
+
+    let (variable, range) = string.split_once('=')
+        .ok_or_else(|| format!("`{}` is not a `variable=start:end:count` range", string))?;
+
+    let bounds = range.split(':').collect::<Vec<_>>();
+    let [start, end, count] = bounds.as_slice() else {
+        return Err(format!("`{}` is not a `start:end:count` range", range));
+    };
+
+    let parse_bound = |bound: &str| bound.trim().parse::<f64>()
+        .map_err(|_| format!("`{}` is not a number", bound.trim()));
+
+    Ok(GridAxis {
+        variable: variable.trim().to_string(),
+        start: parse_bound(start)?,
+        end: parse_bound(end)?,
+        count: count.trim().parse::<usize>()
+            .map_err(|_| format!("`{}` is not a valid number of points", count.trim()))?,
+    })
+}
+
+
+fn parse_limit_point(string: &str) -> Result<f64, String> {
+    let (sign, name) = match string.trim().strip_prefix('-') {
+        Some(name) => (-1_f64, name),
+        None => (1_f64, string.trim())
+    };
+    ast::constant_value(name)
+        .map(|value| sign * value)
+        .or_else(|| string.trim().parse::<f64>().ok())
+        .ok_or_else(|| format!("`{}` is not a number or a known constant", string.trim()))
+}
 
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct CliParser {
 
-    /// The input function to derive
-    #[clap(required = true)]
-    pub input_function: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// The input function to derive, or `-` to read one function per line from the standard input
+    #[clap(required_unless_present = "file")]
+    pub input_function: Option<String>,
+
+    /// Read one function per line from a file.
+    /// A line may end with a `; d=<var>` directive to set its derivation variable
+    #[clap(long, conflicts_with = "input_function")]
+    pub file: Option<PathBuf>,
 
     /// The derivation variable
     #[clap(short='d', default_value="x")]
     pub derivation_variable: String,
 
+    /// Print the function tree of every stage that is shown
+    #[clap(long)]
+    pub show_tree: bool,
+
+    /// Also show the derivative before simplification
+    #[clap(long)]
+    pub show_unsimplified: bool,
+
+    /// Only print the simplified derivative, overriding the other `--show-*` flags
+    #[clap(short, long)]
+    pub quiet: bool,
+
+    /// Explain the derivation step by step, listing the rule applied to every subexpression.
+    /// Only available for a single function, not with `--file` or `-`
+    #[clap(long, conflicts_with = "file")]
+    pub explain: bool,
+
+    /// List every simplification applied to the derivative, with the subexpression before and after it
+    #[clap(long, conflicts_with = "file")]
+    pub explain_simplification: bool,
+
+    /// Write the reciprocal trigonometric functions of the derivative as `csc(x)`, `sec(x)` and `cot(x)`, or as quotients like `1 / sin(x)`
+    #[clap(long, value_enum)]
+    pub trig_style: Option<TrigStyle>,
+
+    /// Write the derivatives of abstract functions, functions without a definition, as `f'(x)` or as `df(x) / dx`
+    #[clap(long, value_enum, default_value_t = DerivativeNotation::Prime)]
+    pub derivative_notation: DerivativeNotation,
+
+    /// Define a function usable in the input, written as `g(t) = t^2 + 1`. Can be repeated
+    #[clap(long)]
+    pub define: Vec<String>,
+
+    /// The output format
+    #[clap(long, value_enum, default_value_t=OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Evaluate the simplified derivative at the given point, written as `x=1,y=2`. Can be repeated
+    #[clap(long, value_parser = parse_point, conflicts_with = "file")]
+    pub at: Vec<Point>,
+
+    /// Instead of the derivative, print the partial derivatives of the function with respect to all its variables
+    /// at the points given with `--at`
+    #[clap(long, requires = "at")]
+    pub gradient: bool,
+
+    /// Check the simplified derivative against a numeric derivative, at the points given with `--at` or at random points
+    #[clap(long, conflicts_with_all = ["file", "gradient"])]
+    pub verify: bool,
+
+    /// Maximum relative difference between the symbolic and numeric derivatives accepted by `--verify`
+    #[clap(long, default_value_t = 1e-6, requires = "verify")]
+    pub tolerance: f64,
+
+    /// Number of random points checked by `--verify`
+    #[clap(long, default_value_t = 20, requires = "verify")]
+    pub samples: usize,
+
+    /// Random points checked by `--verify` have every variable in `[-range, range]`
+    #[clap(long, default_value_t = 10.0, requires = "verify")]
+    pub range: f64,
+
+    /// Seed of the random points checked by `--verify`
+    #[clap(long, default_value_t = 1, requires = "verify")]
+    pub seed: u64,
+
+    /// Tabulate the simplified derivative over evenly spaced values of a variable, written as `x=start:end:count`.
+    /// When repeated, the derivative is evaluated over every combination of values
+    #[clap(long, value_parser = parse_grid_axis, conflicts_with_all = ["file", "at"])]
+    pub grid: Vec<GridAxis>,
+
+    /// Print the simplified derivative as a function in the given programming language
+    #[clap(long, value_enum, conflicts_with = "file")]
+    pub emit: Option<Language>,
+
+    /// The name of the emitted function
+    #[clap(long, default_value="derivative", requires = "emit")]
+    pub emit_name: String,
+
+}
+
+
+#[derive(Subcommand)]
+pub enum Command {
+
+    /// Find an antiderivative of the function, or its definite integral with `--from` and `--to`
+    Integrate(IntegrateArgs),
+
+    /// Expand the function into its Taylor series
+    Series(SeriesArgs),
+
+    /// Compute the limit of the function at a point
+    Limit(LimitArgs),
+
+    /// Find the derivative of a variable defined implicitly by an equation, like `y` in `x^2 + y^2 = 25`
+    Implicit(ImplicitArgs),
+
+}
+
+
+#[derive(Args)]
+pub struct IntegrateArgs {
+
+    /// The input function to integrate
+    pub input_function: String,
+
+    /// The integration variable
+    #[clap(short='d', default_value="x")]
+    pub integration_variable: String,
+
+    /// Lower bound of a definite integral, computed numerically
+    #[clap(long, requires = "to", allow_hyphen_values = true)]
+    pub from: Option<f64>,
+
+    /// Upper bound of a definite integral, computed numerically
+    #[clap(long, requires = "from", allow_hyphen_values = true)]
+    pub to: Option<f64>,
+
+    /// The numerical integration method
+    #[clap(long, value_enum, default_value_t = Method::GaussKronrod, requires = "from")]
+    pub method: Method,
+
+    /// Absolute error accepted by the numerical integration
+    #[clap(long, default_value_t = 1e-10, requires = "from")]
+    pub tolerance: f64,
+
+    /// Define a function usable in the input, written as `g(t) = t^2 + 1`. Can be repeated
+    #[clap(long)]
+    pub define: Vec<String>,
+
+    /// The output format
+    #[clap(long, value_enum, default_value_t=OutputFormat::Text)]
+    pub format: OutputFormat,
+
+}
+
+
+#[derive(Args)]
+pub struct SeriesArgs {
+
+    /// The input function to expand
+    pub input_function: String,
+
+    /// The variable of the series
+    #[clap(short='d', default_value="x")]
+    pub variable: String,
+
+    /// The point the series is expanded around. The default gives the Maclaurin series
+    #[clap(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub around: f64,
+
+    /// The highest power of the polynomial
+    #[clap(long, default_value_t = 5)]
+    pub order: usize,
+
+    /// Define a function usable in the input, written as `g(t) = t^2 + 1`. Can be repeated
+    #[clap(long)]
+    pub define: Vec<String>,
+
+    /// The output format
+    #[clap(long, value_enum, default_value_t=OutputFormat::Text)]
+    pub format: OutputFormat,
+
+}
+
+
+#[derive(Args)]
+pub struct LimitArgs {
+
+    /// The input function
+    pub input_function: String,
+
+    /// The variable approaching the limit point
+    #[clap(short='d', default_value="x")]
+    pub variable: String,
+
+    /// The limit point, possibly `inf`, `-inf`, or a constant like `pi`
+    #[clap(long, value_parser = parse_limit_point, allow_hyphen_values = true)]
+    pub at: f64,
+
+    /// The side the limit point is approached from
+    #[clap(long, value_enum, default_value_t = Side::Both)]
+    pub side: Side,
+
+    /// Define a function usable in the input, written as `g(t) = t^2 + 1`. Can be repeated
+    #[clap(long)]
+    pub define: Vec<String>,
+
+    /// The output format
+    #[clap(long, value_enum, default_value_t=OutputFormat::Text)]
+    pub format: OutputFormat,
+
 }
 
+
+#[derive(Args)]
+pub struct ImplicitArgs {
+
+    /// The input equation, like `x^2 + y^2 = 25`
+    pub input_equation: String,
+
+    /// The derivation variable
+    #[clap(short='d', default_value="x")]
+    pub derivation_variable: String,
+
+    /// The variable that depends on the derivation variable, whose derivative is computed
+    #[clap(long, default_value="y")]
+    pub dependent: String,
+
+    /// Define a function usable in the input, written as `g(t) = t^2 + 1`. Can be repeated
+    #[clap(long)]
+    pub define: Vec<String>,
+
+    /// The output format
+    #[clap(long, value_enum, default_value_t=OutputFormat::Text)]
+    pub format: OutputFormat,
+
+}
@@ -0,0 +1,524 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use clap::ValueEnum;
+
+use crate::ast::{self, FunctionTree, OpNode, OpValue, Piece};
+use crate::bytecode;
+use crate::functions::{Functions, MultiFunctions};
+
+
+/// Target languages of the code emitters
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Language {
+    Rust,
+    C,
+    Python,
+    #[value(alias = "js")]
+    Javascript,
+}
+
+
+/// Emit a compilable function named `name` that evaluates `tree`.
+/// The arguments are the free variables of the tree, in alphabetical order.
+pub fn emit_function(tree: &FunctionTree, name: &str, language: Language) -> String {
+
+    let arguments = free_variables(&tree.root);
+
+    let mut body = String::new();
+    emit_unparenthesized(&tree.root, language, &mut body);
+
+    match language {
+
+        Language::Rust => {
+            let arguments = arguments.iter()
+                .map(|arg| format!("{}: f64", arg))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("pub fn {}({}) -> f64 {{\n    {}\n}}\n", name, arguments, body)
+        },
+
+        Language::C => {
+            let arguments = if arguments.is_empty() {
+                "void".to_string()
+            } else {
+                arguments.iter()
+                    .map(|arg| format!("double {}", arg))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            format!("#include <math.h>\n\ndouble {}({}) {{\n    return {};\n}}\n", name, arguments, body)
+        },
+
+        Language::Python => {
+            format!("import math\n\n\ndef {}({}):\n    return {}\n", name, arguments.join(", "), body)
+        },
+
+        Language::Javascript => {
+            format!("function {}({}) {{\n    return {};\n}}\n", name, arguments.join(", "), body)
+        },
+    }
+}
+
+
+/// Names of the variables in the tree, excluding the known constants
+fn free_variables<'a>(node: &OpNode<'a>) -> Vec<&'a str> {
+
+    fn collect<'a>(node: &OpNode<'a>, variables: &mut BTreeSet<&'a str>) {
+        match &node.value {
+            OpValue::Number(_) => (),
+            OpValue::Variable(name) => {
+                if ast::constant_value(name).is_none() {
+                    variables.insert(name);
+                }
+            },
+            OpValue::Add { left, right } |
+            OpValue::Sub { left, right } |
+            OpValue::Mul { left, right } |
+            OpValue::Div { left, right } |
+            OpValue::Pow { left, right } |
+            OpValue::Log { base: left, arg: right }
+             => {
+                collect(left, variables);
+                collect(right, variables);
+            },
+            OpValue::Function { func: _, arg } => collect(arg, variables),
+            OpValue::MultiFunction { func: _, args } |
+            OpValue::Call { name: _, args, derivative: _ }
+                => args.iter().for_each(|arg| collect(arg, variables)),
+            OpValue::Piecewise { pieces } => pieces.iter().flat_map(Piece::operands).for_each(|operand| collect(operand, variables)),
+        }
+    }
+
+    let mut variables = BTreeSet::new();
+    collect(node, &mut variables);
+    variables.into_iter().collect()
+}
+
+
+fn emit_number(n: f64, language: Language, output: &mut String) {
+
+    if !n.is_finite() {
+        let (nan, infinity) = match language {
+            Language::Rust => ("f64::NAN", "f64::INFINITY"),
+            Language::C => ("NAN", "INFINITY"),
+            Language::Python => ("math.nan", "math.inf"),
+            Language::Javascript => ("NaN", "Infinity"),
+        };
+        match (n.is_nan(), n.is_sign_negative()) {
+            (true, _) => output.push_str(nan),
+            (false, false) => output.push_str(infinity),
+            (false, true) => write!(output, "(-{})", infinity).unwrap(),
+        }
+        return;
+    }
+
+    // Debug formatting always includes the decimal point, so the literal is a float in every language.
+    // Rust needs the type suffix to call methods on a literal.
+    let suffix = if matches!(language, Language::Rust) { "_f64" } else { "" };
+
+    if n.is_sign_negative() {
+        write!(output, "({:?}{})", n, suffix).unwrap();
+    } else {
+        write!(output, "{:?}{}", n, suffix).unwrap();
+    }
+}
+
+
+fn emit_constant(name: &str, language: Language, output: &mut String) {
+
+    let value = ast::constant_value(name).expect("Only known constants are emitted as constants");
+
+    let constant = match (language, name) {
+        (Language::Rust, "e") => "std::f64::consts::E",
+        (Language::Rust, "pi") => "std::f64::consts::PI",
+        (Language::Python, "e") => "math.e",
+        (Language::Python, "pi") => "math.pi",
+        (Language::Javascript, "e") => "Math.E",
+        (Language::Javascript, "pi") => "Math.PI",
+        // `M_E` and `M_PI` are not part of standard C
+        _ => return emit_number(value, language, output)
+    };
+
+    output.push_str(constant);
+}
+
+
+/// Name of the function in the target language, for languages that use free functions
+fn function_name(func: Functions, language: Language) -> &'static str {
+    match func {
+        Functions::Sin => "sin",
+        Functions::Cos => "cos",
+        Functions::Tan => "tan",
+        Functions::Arcsin => "asin",
+        Functions::Arccos => "acos",
+        Functions::Arctan => "atan",
+        Functions::SquareRoot => "sqrt",
+        // `math.cbrt` requires Python 3.11
+        Functions::CubeRoot => "cbrt",
+        Functions::NaturalLog => match language {
+            Language::Rust => "ln",
+            _ => "log"
+        },
+        Functions::Sinh => "sinh",
+        Functions::Cosh => "cosh",
+        Functions::Tanh => "tanh",
+        Functions::Arsinh => "asinh",
+        Functions::Arcosh => "acosh",
+        Functions::Artanh => "atanh",
+        Functions::Exp => "exp",
+        Functions::Log10 => "log10",
+        Functions::Log2 => "log2",
+        Functions::Abs => match language {
+            Language::C | Language::Python => "fabs",
+            _ => "abs"
+        },
+        Functions::Floor => "floor",
+        Functions::Ceil => "ceil",
+        Functions::Secant |
+        Functions::Cosecant |
+        Functions::Cotangent |
+        Functions::Arcsecant |
+        Functions::Arccosecant |
+        Functions::Arccotangent |
+        Functions::Sign |
+        Functions::Heaviside => unreachable!("{} has no equivalent in the target languages", func),
+    }
+}
+
+
+fn multi_function_name(func: MultiFunctions, language: Language) -> &'static str {
+    match (func, language) {
+        (MultiFunctions::Min, Language::C) => "fmin",
+        (MultiFunctions::Max, Language::C) => "fmax",
+        (MultiFunctions::Atan2, _) => "atan2",
+        (MultiFunctions::Hypot, _) => "hypot",
+        (MultiFunctions::Min, _) => "min",
+        (MultiFunctions::Max, _) => "max",
+        (MultiFunctions::Root, _) => unreachable!("{} has no equivalent in the target languages", func),
+    }
+}
+
+
+/// Definition of the functions that have no equivalent in the target languages, in terms of the ones that do
+fn definition<'a>(node: &OpNode<'a>, func: Functions, arg: &Rc<OpNode<'a>>) -> Option<OpNode<'a>> {
+
+    let new = |value: OpValue<'a>| OpNode { source: Rc::clone(&node.source), value };
+    let one = || Rc::new(new(OpValue::Number(1_f64)));
+    let call = |func: Functions, arg: Rc<OpNode<'a>>| Rc::new(new(OpValue::Function { func, arg }));
+    let reciprocal = || Rc::new(new(OpValue::Div { left: one(), right: Rc::clone(arg) }));
+
+    let value = match func {
+        // sec(x) = 1/cos(x)
+        Functions::Secant => OpValue::Div { left: one(), right: call(Functions::Cos, Rc::clone(arg)) },
+        // csc(x) = 1/sin(x)
+        Functions::Cosecant => OpValue::Div { left: one(), right: call(Functions::Sin, Rc::clone(arg)) },
+        // cot(x) = cos(x)/sin(x)
+        Functions::Cotangent => OpValue::Div { left: call(Functions::Cos, Rc::clone(arg)), right: call(Functions::Sin, Rc::clone(arg)) },
+        // asec(x) = acos(1/x)
+        Functions::Arcsecant => OpValue::Function { func: Functions::Arccos, arg: reciprocal() },
+        // acsc(x) = asin(1/x)
+        Functions::Arccosecant => OpValue::Function { func: Functions::Arcsin, arg: reciprocal() },
+        // acot(x) = pi/2 - atan(x)
+        Functions::Arccotangent => OpValue::Sub {
+            left: Rc::new(new(OpValue::Div { left: Rc::new(new(OpValue::Variable("pi"))), right: Rc::new(new(OpValue::Number(2_f64))) })),
+            right: call(Functions::Arctan, Rc::clone(arg))
+        },
+        // heaviside(x) = (sign(x) + 1)/2
+        Functions::Heaviside => OpValue::Div {
+            left: Rc::new(new(OpValue::Add { left: call(Functions::Sign, Rc::clone(arg)), right: one() })),
+            right: Rc::new(new(OpValue::Number(2_f64)))
+        },
+        _ => return None
+    };
+
+    Some(new(value))
+}
+
+
+/// `log(b, x) = ln(x)/ln(b)`, as no target language has a logarithm in an arbitrary base
+fn change_of_base<'a>(node: &OpNode<'a>, base: &Rc<OpNode<'a>>, arg: &Rc<OpNode<'a>>) -> OpNode<'a> {
+    let ln = |arg: &Rc<OpNode<'a>>| Rc::new(OpNode {
+        source: Rc::clone(&node.source),
+        value: OpValue::Function { func: Functions::NaturalLog, arg: Rc::clone(arg) }
+    });
+    OpNode {
+        source: Rc::clone(&node.source),
+        value: OpValue::Div { left: ln(arg), right: ln(base) }
+    }
+}
+
+
+/// `root(n, x) = x^(1/n)`, as no target language has roots of any index.
+/// With a constant odd index, the power is emitted so that it is real for negative `x`, like `root`.
+/// With an index that depends on a variable, the power is not defined for negative `x` in the target languages
+fn root_as_power<'a>(node: &OpNode<'a>, index: &Rc<OpNode<'a>>, arg: &Rc<OpNode<'a>>) -> OpNode<'a> {
+    let new = |value: OpValue<'a>| Rc::new(OpNode { source: Rc::clone(&node.source), value });
+    OpNode {
+        source: Rc::clone(&node.source),
+        value: OpValue::Pow {
+            left: Rc::clone(arg),
+            right: new(OpValue::Div { left: new(OpValue::Number(1_f64)), right: Rc::clone(index) })
+        }
+    }
+}
+
+
+/// Value of the subtree if it has no variables, like the exponent `1/3`
+fn constant_value(node: &Rc<OpNode>) -> Option<f64> {
+    if !free_variables(node).is_empty() {
+        return None;
+    }
+    let program = bytecode::compile(&FunctionTree { root: Rc::clone(node) });
+    Some(program.run(&[], &mut program.new_registers()))
+}
+
+
+fn binary_operator<'n, 'a>(value: &'n OpValue<'a>) -> Option<(&'static str, &'n OpNode<'a>, &'n OpNode<'a>)> {
+    match value {
+        OpValue::Add { left, right } => Some(("+", left, right)),
+        OpValue::Sub { left, right } => Some(("-", left, right)),
+        OpValue::Mul { left, right } => Some(("*", left, right)),
+        OpValue::Div { left, right } => Some(("/", left, right)),
+        _ => None
+    }
+}
+
+
+/// Emit the expression without the outer parentheses, for positions that are already delimited (function arguments, return values)
+fn emit_unparenthesized(node: &OpNode, language: Language, output: &mut String) {
+    if let Some((op, left, right)) = binary_operator(&node.value) {
+        emit_node(left, language, output);
+        write!(output, " {} ", op).unwrap();
+        emit_node(right, language, output);
+    } else {
+        emit_node(node, language, output);
+    }
+}
+
+
+/// Emit the expression fully parenthesized, so that the operator precedence of the target language doesn't matter
+fn emit_node(node: &OpNode, language: Language, output: &mut String) {
+
+    if binary_operator(&node.value).is_some() {
+        output.push('(');
+        emit_unparenthesized(node, language, output);
+        output.push(')');
+        return;
+    }
+
+    match &node.value {
+
+        OpValue::Number(n) => emit_number(*n, language, output),
+
+        OpValue::Variable(name) => {
+            if ast::constant_value(name).is_some() {
+                emit_constant(name, language, output);
+            } else {
+                output.push_str(name);
+            }
+        },
+
+        OpValue::Pow { left, right } => match constant_value(right).filter(|exponent| ast::odd_root_numerator(*exponent).is_some()) {
+            Some(exponent) => emit_real_power(left, exponent, language, output),
+            None => match language {
+                Language::Rust => {
+                    emit_node(left, language, output);
+                    output.push_str(".powf(");
+                    emit_unparenthesized(right, language, output);
+                    output.push(')');
+                },
+                _ => {
+                    output.push_str(match language {
+                        Language::C => "pow(",
+                        Language::Python => "math.pow(",
+                        _ => "Math.pow(",
+                    });
+                    emit_unparenthesized(left, language, output);
+                    output.push_str(", ");
+                    emit_unparenthesized(right, language, output);
+                    output.push(')');
+                }
+            }
+        },
+
+        OpValue::Function { func: Functions::Sign, arg } => emit_sign(arg, language, output),
+
+        OpValue::Function { func, arg } => match definition(node, *func, arg) {
+            Some(definition) => emit_node(&definition, language, output),
+            None => emit_function_call(*func, arg, language, output)
+        },
+
+        OpValue::Log { base, arg } => emit_node(&change_of_base(node, base, arg), language, output),
+
+        OpValue::MultiFunction { func: MultiFunctions::Root, args } => emit_node(&root_as_power(node, &args[0], &args[1]), language, output),
+
+        OpValue::MultiFunction { func, args } => emit_multi_function_call(*func, args, language, output),
+
+        OpValue::Piecewise { pieces } => emit_piecewise(pieces, language, output),
+
+        OpValue::Call { .. } => unreachable!("Functions containing abstract functions are rejected before being evaluated"),
+
+        OpValue::Add { .. } |
+        OpValue::Sub { .. } |
+        OpValue::Mul { .. } |
+        OpValue::Div { .. }
+            => unreachable!("Binary operators are handled above"),
+    }
+}
+
+
+/// `x^(p/q)` with an odd `q` is real for negative `x`, like with `ast::exec_pow`, but the power functions of the target
+/// languages are NaN or raise an error there. The power of `abs(x)` is used instead, with the sign of `x` if `p` is odd
+fn emit_real_power(base: &OpNode, exponent: f64, language: Language, output: &mut String) {
+
+    if exponent == 1_f64 / 3_f64 {
+        return emit_function_call(Functions::CubeRoot, base, language, output);
+    }
+
+    let odd = ast::odd_root_numerator(exponent).is_some_and(|numerator| numerator % 2_f64 != 0_f64);
+
+    let mut power = String::new();
+    emit_number(exponent, language, &mut power);
+
+    let mut operand = String::new();
+    match language {
+        Language::Rust | Language::Javascript => emit_node(base, language, &mut operand),
+        Language::C | Language::Python => emit_unparenthesized(base, language, &mut operand),
+    }
+
+    match (language, odd) {
+        (Language::Rust, false) => write!(output, "{}.abs().powf({})", operand, power).unwrap(),
+        (Language::Rust, true) => write!(output, "{0}.abs().powf({1}).copysign({0})", operand, power).unwrap(),
+        (Language::C, false) => write!(output, "pow(fabs({}), {})", operand, power).unwrap(),
+        (Language::C, true) => write!(output, "copysign(pow(fabs({0}), {1}), {0})", operand, power).unwrap(),
+        (Language::Python, false) => write!(output, "math.pow(math.fabs({}), {})", operand, power).unwrap(),
+        (Language::Python, true) => write!(output, "math.copysign(math.pow(math.fabs({0}), {1}), {0})", operand, power).unwrap(),
+        (Language::Javascript, false) => write!(output, "Math.pow(Math.abs({}), {})", operand, power).unwrap(),
+        // Javascript has no `copysign`, and `Math.sign` is 0 at 0, where the power may be infinite
+        (Language::Javascript, true) => write!(output, "({0} < 0 ? -Math.pow(Math.abs({0}), {1}) : Math.pow(Math.abs({0}), {1}))", operand, power).unwrap(),
+    }
+}
+
+
+/// Only Javascript has a sign function that is 0 at 0, the other languages compare the argument with 0
+fn emit_sign(arg: &OpNode, language: Language, output: &mut String) {
+
+    let mut operand = String::new();
+    emit_node(arg, language, &mut operand);
+
+    match language {
+        Language::Javascript => write!(output, "Math.sign({})", operand).unwrap(),
+        Language::Rust => write!(output, "((({0} > 0.0) as i32 - ({0} < 0.0) as i32) as f64)", operand).unwrap(),
+        Language::C | Language::Python => write!(output, "(({0} > 0) - ({0} < 0))", operand).unwrap(),
+    }
+}
+
+
+/// A chain of conditional expressions, which is NaN if no condition holds and there is no `otherwise` piece
+fn emit_piecewise(pieces: &[Piece], language: Language, output: &mut String) {
+
+    let mut fallback = String::new();
+
+    output.push('(');
+
+    for piece in pieces {
+
+        let mut value = String::new();
+        emit_unparenthesized(&piece.value, language, &mut value);
+
+        let Some(condition) = &piece.condition else {
+            fallback = value;
+            break;
+        };
+
+        let mut test = String::new();
+        emit_node(&condition.left, language, &mut test);
+        write!(test, " {} ", condition.comparison).unwrap();
+        emit_node(&condition.right, language, &mut test);
+
+        match language {
+            Language::Rust => write!(output, "if {} {{ {} }} else ", test, value).unwrap(),
+            Language::Python => write!(output, "{} if {} else ", value, test).unwrap(),
+            Language::C | Language::Javascript => write!(output, "{} ? {} : ", test, value).unwrap(),
+        }
+    }
+
+    if fallback.is_empty() {
+        emit_number(f64::NAN, language, &mut fallback);
+    }
+
+    match language {
+        Language::Rust => write!(output, "{{ {} }})", fallback).unwrap(),
+        _ => write!(output, "{})", fallback).unwrap(),
+    }
+}
+
+
+fn emit_function_call(func: Functions, arg: &OpNode, language: Language, output: &mut String) {
+
+    let name = function_name(func, language);
+
+    match language {
+        Language::Rust => {
+            emit_node(arg, language, output);
+            write!(output, ".{}()", name).unwrap();
+        },
+        Language::C => {
+            write!(output, "{}(", name).unwrap();
+            emit_unparenthesized(arg, language, output);
+            output.push(')');
+        },
+        Language::Python => {
+            write!(output, "math.{}(", name).unwrap();
+            emit_unparenthesized(arg, language, output);
+            output.push(')');
+        },
+        Language::Javascript => {
+            write!(output, "Math.{}(", name).unwrap();
+            emit_unparenthesized(arg, language, output);
+            output.push(')');
+        },
+    }
+}
+
+
+fn emit_multi_function_call(func: MultiFunctions, args: &[Rc<OpNode>], language: Language, output: &mut String) {
+
+    let name = multi_function_name(func, language);
+
+    // Rust calls the function as a method of the first argument
+    let args = match language {
+        Language::Rust => {
+            emit_node(&args[0], language, output);
+            write!(output, ".{}(", name).unwrap();
+            &args[1..]
+        },
+        // `min` and `max` are builtins in Python
+        Language::Python if matches!(func, MultiFunctions::Min | MultiFunctions::Max) => {
+            write!(output, "{}(", name).unwrap();
+            args
+        },
+        Language::Python => {
+            write!(output, "math.{}(", name).unwrap();
+            args
+        },
+        Language::C => {
+            write!(output, "{}(", name).unwrap();
+            args
+        },
+        Language::Javascript => {
+            write!(output, "Math.{}(", name).unwrap();
+            args
+        },
+    };
+
+    for (i, arg) in args.iter().enumerate() {
+        if i != 0 {
+            output.push_str(", ");
+        }
+        emit_unparenthesized(arg, language, output);
+    }
+    output.push(')');
+}
@@ -0,0 +1,188 @@
+use std::rc::Rc;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::ast::{self, FunctionTree, OpNode, OpValue};
+use crate::errors::{self, CalcError};
+use crate::tokenizer;
+
+
+lazy_static! {
+
+    /// A definition starts with a call to the function being defined, followed by `=`
+    static ref DEFINITION_REGEX: Regex = Regex::new(
+        r#"^\s*[_a-zA-Z]\w*\s*\([^=]*\)\s*="#
+    ).expect("Regex failed to compile");
+
+}
+
+
+/// A function defined by the user, like `g(t) = t^2 + 1`
+pub struct Definition<'a> {
+    pub name: &'a str,
+    pub parameters: Vec<&'a str>,
+    /// The body may call other user-defined functions, which are only resolved when the function is expanded
+    pub body: FunctionTree<'a>,
+}
+
+
+/// The functions defined by the user, which calls in the input resolve to
+#[derive(Default)]
+pub struct Definitions<'a> {
+    definitions: Vec<Definition<'a>>,
+}
+
+impl<'a> Definitions<'a> {
+
+    /// Parse every definition, in order
+    pub fn parse_all(sources: &'a [String]) -> Result<Self, (&'a str, CalcError)> {
+        let mut definitions = Self::default();
+        for source in sources {
+            definitions.define(source).map_err(|error| (source.as_str(), error))?;
+        }
+        Ok(definitions)
+    }
+
+
+    /// Parse the definition and add it. A function can only be defined once
+    pub fn define(&mut self, source: &'a str) -> Result<(), CalcError> {
+
+        let equation = tokenizer::tokenize(source)?.parse_equation()?;
+
+        let OpValue::Call { name, args, derivative: _ } = &equation.left.root.value else {
+            if let OpValue::Function { .. } | OpValue::MultiFunction { .. } | OpValue::Log { .. } = equation.left.root.value {
+                let name = equation.left.root.source.string;
+                return Err(errors::parsing_error(&equation.left.root.source, format!("`{}` is a built-in function, it can't be redefined.", name).as_str()));
+            }
+            return Err(errors::parsing_error(
+                &equation.left.root.source,
+                "The left side of a definition must name the function and its parameters, like `g(t)`."
+            ));
+        };
+
+        let mut parameters: Vec<&'a str> = Vec::new();
+        for arg in args.iter() {
+            match arg.value {
+                OpValue::Variable(parameter) if ast::constant_value(parameter).is_some()
+                    => return Err(errors::parsing_error(&arg.source, format!("`{}` is a constant, it can't be a parameter.", parameter).as_str())),
+                OpValue::Variable(parameter) if parameters.contains(&parameter)
+                    => return Err(errors::parsing_error(&arg.source, format!("`{}` is already a parameter.", parameter).as_str())),
+                OpValue::Variable(parameter) => parameters.push(parameter),
+                _ => return Err(errors::parsing_error(&arg.source, "Parameters must be variable names.")),
+            }
+        }
+
+        if self.get(name).is_some() {
+            return Err(errors::parsing_error(&equation.left.root.source, format!("`{}` is already defined, it can't be redefined.", name).as_str()));
+        }
+
+        self.definitions.push(Definition { name, parameters, body: equation.right });
+
+        Ok(())
+    }
+
+
+    pub fn get(&self, name: &str) -> Option<&Definition<'a>> {
+        self.definitions.iter().find(|definition| definition.name == name)
+    }
+
+
+    /// Replace every call to a user-defined function with the body of the function, where the parameters are replaced by the arguments.
+    /// Calls in the bodies are expanded too, and a function that calls itself, directly or not, is an error.
+    /// Calls to functions without a definition are kept as abstract functions
+    pub fn expand(&self, function: &FunctionTree<'a>) -> Result<FunctionTree<'a>, CalcError> {
+        Ok(FunctionTree {
+            root: self.expand_node(&function.root, &mut Vec::new())?
+        })
+    }
+
+
+    /// `expanding` holds the functions whose bodies are being expanded, the innermost last
+    fn expand_node(&self, node: &Rc<OpNode<'a>>, expanding: &mut Vec<&'a str>) -> Result<Rc<OpNode<'a>>, CalcError> {
+
+        let value = match &node.value {
+
+            OpValue::Number(_) |
+            OpValue::Variable(_)
+                => return Ok(Rc::clone(node)),
+
+            OpValue::Add { left, right } => OpValue::Add { left: self.expand_node(left, expanding)?, right: self.expand_node(right, expanding)? },
+            OpValue::Sub { left, right } => OpValue::Sub { left: self.expand_node(left, expanding)?, right: self.expand_node(right, expanding)? },
+            OpValue::Mul { left, right } => OpValue::Mul { left: self.expand_node(left, expanding)?, right: self.expand_node(right, expanding)? },
+            OpValue::Div { left, right } => OpValue::Div { left: self.expand_node(left, expanding)?, right: self.expand_node(right, expanding)? },
+            OpValue::Pow { left, right } => OpValue::Pow { left: self.expand_node(left, expanding)?, right: self.expand_node(right, expanding)? },
+            OpValue::Function { func, arg } => OpValue::Function { func: *func, arg: self.expand_node(arg, expanding)? },
+            OpValue::Log { base, arg } => OpValue::Log { base: self.expand_node(base, expanding)?, arg: self.expand_node(arg, expanding)? },
+            OpValue::MultiFunction { func, args } => OpValue::MultiFunction {
+                func: *func,
+                args: args.iter().map(|arg| self.expand_node(arg, expanding)).collect::<Result<_, _>>()?
+            },
+            OpValue::Piecewise { pieces } => OpValue::Piecewise {
+                pieces: pieces.iter()
+                    .map(|piece| {
+                        let mut result = Ok(());
+                        let piece = piece.map(|operand| self.expand_node(operand, expanding).unwrap_or_else(|error| {
+                            result = Err(error);
+                            Rc::clone(operand)
+                        }));
+                        result.map(|_| piece)
+                    })
+                    .collect::<Result<_, _>>()?
+            },
+
+            OpValue::Call { name, args, derivative } => {
+
+                let args = args.iter().map(|arg| self.expand_node(arg, expanding)).collect::<Result<Vec<_>, _>>()?;
+
+                // A function without a definition is abstract, like `f` in `f(x) * g(x)`
+                let Some(definition) = self.get(name) else {
+                    return Ok(Rc::new(OpNode {
+                        source: Rc::clone(&node.source),
+                        value: OpValue::Call { name, args: args.into(), derivative: derivative.clone() }
+                    }));
+                };
+
+                if args.len() != definition.parameters.len() {
+                    return Err(self.error(node, expanding, errors::arity_message(name, definition.parameters.len(), args.len())));
+                }
+
+                if let Some(start) = expanding.iter().position(|other| other == name) {
+                    let cycle = expanding[start..].iter().chain([name]).copied().collect::<Vec<_>>();
+                    return Err(errors::invalid_input(format!("`{}` is defined in terms of itself: {}.", name, cycle.join(" -> ")).as_str()));
+                }
+
+                expanding.push(name);
+                let body = self.expand_node(&definition.body.root, expanding);
+                expanding.pop();
+
+                let body = FunctionTree { root: body? };
+                let replacements = definition.parameters.iter().copied().zip(args).collect::<Vec<_>>();
+
+                // The parameters are replaced all at once, so that an argument that is also a parameter name isn't replaced again
+                return Ok(body.substitute_all(&replacements).root);
+            },
+        };
+
+        Ok(Rc::new(OpNode {
+            source: Rc::clone(&node.source),
+            value
+        }))
+    }
+
+
+    /// Errors in the input point to the offending token, but the bodies of the definitions are not part of the input
+    fn error(&self, node: &OpNode, expanding: &[&str], message: String) -> CalcError {
+        match expanding.last() {
+            None => errors::parsing_error(&node.source, message.as_str()),
+            Some(definition) => errors::invalid_input(format!("In the definition of `{}`: {}", definition, message).as_str()),
+        }
+    }
+
+}
+
+
+/// Whether the line defines a function, like `g(t) = t^2 + 1`, rather than being a function to process
+pub fn is_definition(line: &str) -> bool {
+    DEFINITION_REGEX.is_match(line)
+}
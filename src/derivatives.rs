@@ -1,7 +1,13 @@
 use std::rc::Rc;
 
-use crate::functions::Functions;
-use crate::ast::{FunctionTree, OpNode, OpValue};
+use std::fmt;
+
+use crate::functions::{Functions, Kink, MultiFunctions};
+use crate::ast::{self, FunctionTree, OpNode, OpValue, Piece};
+use crate::bytecode;
+use crate::piecewise;
+use crate::errors::CalcError;
+use crate::notation::{self, Notation};
 
 
 macro_rules! op_node {
@@ -22,9 +28,315 @@ macro_rules! number {
 }
 
 
-fn derive_node<'a>(node: &OpNode<'a>, dvar: &'a str) -> Rc<OpNode<'a>> {
+/// A derivation rule
+#[derive(Clone, Copy)]
+pub enum Rule {
+    Constant,
+    Variable,
+    OtherVariable,
+    Sum,
+    Difference,
+    Product,
+    Quotient,
+    Power,
+    Exponential,
+    /// Logarithm in a constant base
+    Logarithm,
+    /// Logarithm in a base that depends on the derivation variable
+    VariableBaseLogarithm,
+    /// Derivative of a known function, combined with the chain rule
+    Function(Functions),
+    /// Partial derivatives of a function of several arguments, combined with the multivariate chain rule
+    MultiFunction(MultiFunctions),
+    /// Each piece of a piecewise function is derived on its own
+    Piecewise,
+    /// A function without a definition is derived into its symbolic derivative, combined with the multivariate chain rule
+    AbstractFunction,
+}
+
+impl Rule {
+
+    fn of(node: &OpNode, dvar: &str) -> Self {
+        match &node.value {
+            OpValue::Number(_) => Rule::Constant,
+            OpValue::Variable(name) if *name == dvar => Rule::Variable,
+            OpValue::Variable(_) => Rule::OtherVariable,
+            OpValue::Add { .. } => Rule::Sum,
+            OpValue::Sub { .. } => Rule::Difference,
+            OpValue::Mul { .. } => Rule::Product,
+            OpValue::Div { .. } => Rule::Quotient,
+            OpValue::Pow { left: _, right } if !right.contains_variable(dvar) => Rule::Power,
+            OpValue::Pow { .. } => Rule::Exponential,
+            OpValue::Log { base, arg: _ } if !base.contains_variable(dvar) => Rule::Logarithm,
+            OpValue::Log { .. } => Rule::VariableBaseLogarithm,
+            OpValue::Function { func, arg: _ } => Rule::Function(*func),
+            OpValue::MultiFunction { func, args: _ } => Rule::MultiFunction(*func),
+            OpValue::Piecewise { .. } => Rule::Piecewise,
+            OpValue::Call { .. } => Rule::AbstractFunction,
+        }
+    }
+
+
+    /// The rule written as a formula, where `a` and `b` are subexpressions and `c` and `n` are constants
+    pub fn formula(&self) -> &'static str {
+        match self {
+            Rule::Constant => "c' = 0",
+            Rule::Variable => "x' = 1",
+            Rule::OtherVariable => "c' = 0",
+            Rule::Sum => "(a + b)' = a' + b'",
+            Rule::Difference => "(a - b)' = a' - b'",
+            Rule::Product => "(a * b)' = a' * b + a * b'",
+            Rule::Quotient => "(a / b)' = (a' * b - a * b') / b^2",
+            Rule::Power => "(a^n)' = n * a^(n - 1) * a'",
+            Rule::Exponential => "(a^b)' = e^(b * ln(a)) * (b' * ln(a) + b * a' / a)",
+            Rule::Logarithm => "log(c, a)' = a' / (a * ln(c))",
+            Rule::VariableBaseLogarithm => "log(b, a)' = (a' / a * ln(b) - ln(a) * b' / b) / ln(b)^2",
+            Rule::Function(func) => match func {
+                Functions::Sin => "sin(a)' = cos(a) * a'",
+                Functions::Cos => "cos(a)' = -sin(a) * a'",
+                Functions::Tan => "tan(a)' = sec(a)^2 * a'",
+                Functions::Arcsin => "asin(a)' = a' / sqrt(1 - a^2)",
+                Functions::Arccos => "acos(a)' = -a' / sqrt(1 - a^2)",
+                Functions::Arctan => "atan(a)' = a' / (1 + a^2)",
+                Functions::SquareRoot => "sqrt(a)' = a' / (2 * sqrt(a))",
+                Functions::CubeRoot => "cbrt(a)' = a' / (3 * cbrt(a)^2)",
+                Functions::NaturalLog => "ln(a)' = a' / a",
+                Functions::Secant => "sec(a)' = sec(a) * tan(a) * a'",
+                Functions::Cosecant => "csc(a)' = -csc(a) * cot(a) * a'",
+                Functions::Cotangent => "cot(a)' = -csc(a)^2 * a'",
+                Functions::Arcsecant => "asec(a)' = a' / (a^2 * sqrt(1 - 1 / a^2))",
+                Functions::Arccosecant => "acsc(a)' = -a' / (a^2 * sqrt(1 - 1 / a^2))",
+                Functions::Arccotangent => "acot(a)' = -a' / (1 + a^2)",
+                Functions::Sinh => "sinh(a)' = cosh(a) * a'",
+                Functions::Cosh => "cosh(a)' = sinh(a) * a'",
+                Functions::Tanh => "tanh(a)' = a' / cosh(a)^2",
+                Functions::Arsinh => "asinh(a)' = a' / sqrt(a^2 + 1)",
+                Functions::Arcosh => "acosh(a)' = a' / sqrt(a^2 - 1)",
+                Functions::Artanh => "atanh(a)' = a' / (1 - a^2)",
+                Functions::Exp => "exp(a)' = exp(a) * a'",
+                Functions::Log10 => "log10(a)' = a' / (a * ln(10))",
+                Functions::Log2 => "log2(a)' = a' / (a * ln(2))",
+                Functions::Abs => "abs(a)' = sign(a) * a', where a ≠ 0",
+                Functions::Sign => "sign(a)' = 0, where a ≠ 0",
+                Functions::Floor => "floor(a)' = 0, where a is not an integer",
+                Functions::Ceil => "ceil(a)' = 0, where a is not an integer",
+                Functions::Heaviside => "heaviside(a)' = 0, where a ≠ 0",
+            },
+            Rule::MultiFunction(func) => match func {
+                MultiFunctions::Atan2 => "atan2(a, b)' = (b * a' - a * b') / (a^2 + b^2)",
+                MultiFunctions::Hypot => "hypot(a, b)' = (a * a' + b * b') / hypot(a, b)",
+                MultiFunctions::Min => "min(a, b)' = heaviside(b - a) * a' + heaviside(a - b) * b', where a ≠ b",
+                MultiFunctions::Max => "max(a, b)' = heaviside(a - b) * a' + heaviside(b - a) * b', where a ≠ b",
+                MultiFunctions::Root => "root(a, b)' = root(a, b) * (b' / (a * b) - ln(b) * a' / a^2)",
+            },
+            Rule::Piecewise => "{a if c; b otherwise}' = {a' if c; b' otherwise}, except where c changes",
+            Rule::AbstractFunction => "f(a, b)' = f_1(a, b) * a' + f_2(a, b) * b', or f(a)' = f'(a) * a'",
+        }
+    }
+
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Constant => write!(f, "constant rule"),
+            Rule::Variable => write!(f, "variable rule"),
+            Rule::OtherVariable => write!(f, "constant variable rule"),
+            Rule::Sum => write!(f, "sum rule"),
+            Rule::Difference => write!(f, "difference rule"),
+            Rule::Product => write!(f, "product rule"),
+            Rule::Quotient => write!(f, "quotient rule"),
+            Rule::Power => write!(f, "power rule"),
+            Rule::Exponential => write!(f, "exponential rule"),
+            Rule::Logarithm => write!(f, "logarithm rule"),
+            Rule::VariableBaseLogarithm => write!(f, "variable base logarithm rule"),
+            Rule::Function(func) => write!(f, "chain rule with {}", func),
+            Rule::MultiFunction(func) => write!(f, "multivariate chain rule with {}", func),
+            Rule::Piecewise => write!(f, "piecewise rule"),
+            Rule::AbstractFunction => write!(f, "chain rule with an abstract function"),
+        }
+    }
+}
+
+
+/// A rule applied to a subexpression during a derivation
+pub struct Step {
+    pub rule: Rule,
+    /// The derived subexpression, in infix notation
+    pub expression: String,
+    /// Nesting level of the subexpression, 0 being the whole function
+    pub depth: usize,
+}
+
+
+/// A subexpression that is not differentiable everywhere, like `abs(x - 1)` at `x = 1`.
+/// The derivative of the function does not exist where `argument` is at the kink.
+pub struct NonDifferentiable<'a> {
+    pub expression: Rc<OpNode<'a>>,
+    pub argument: Rc<OpNode<'a>>,
+    pub kink: Kink,
+    /// Why the derivative does not exist, when the kink was checked, like at the breakpoints of piecewise functions
+    pub reason: Option<String>,
+}
+
+impl NonDifferentiable<'_> {
+
+    /// Where the subexpression is not differentiable, like `x - 1 = 0`
+    pub fn condition(&self) -> String {
+        let argument = notation::format_node(&self.argument, Notation::Infix);
+        match self.kink {
+            Kink::Zero => format!("{} = 0", argument),
+            Kink::Integer => format!("{} is an integer", argument),
+        }
+    }
+
 
-    match &node.value {
+    /// Whether the subexpression is not differentiable at the point
+    pub fn contains(&self, point: &[(String, f64)]) -> Result<bool, CalcError> {
+        Ok(self.kink.contains(self.argument_at(point)?))
+    }
+
+
+    /// Whether the subexpression is not differentiable somewhere on the segment between the two points,
+    /// assuming its argument is continuous along it
+    pub fn crossed(&self, from: &[(String, f64)], to: &[(String, f64)]) -> Result<bool, CalcError> {
+        Ok(self.kink.crossed(self.argument_at(from)?, self.argument_at(to)?))
+    }
+
+
+    fn argument_at(&self, point: &[(String, f64)]) -> Result<f64, CalcError> {
+        let program = bytecode::compile(&FunctionTree { root: Rc::clone(&self.argument) });
+        let args = program.arguments(point)?.into_iter().copied().collect::<Vec<_>>();
+        Ok(program.run(&args, &mut program.new_registers()))
+    }
+
+}
+
+impl fmt::Display for NonDifferentiable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not differentiable where {}", notation::format_node(&self.expression, Notation::Infix), self.condition())?;
+        if let Some(reason) = &self.reason {
+            write!(f, ", {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Find the subexpressions of the function that are not differentiable with respect to `dvar` everywhere.
+/// Each one is listed once, even if it appears several times in the function
+pub fn non_differentiable<'a>(function: &FunctionTree<'a>, dvar: &'a str) -> Vec<NonDifferentiable<'a>> {
+
+    fn collect<'a>(node: &Rc<OpNode<'a>>, dvar: &'a str, found: &mut Vec<NonDifferentiable<'a>>) {
+
+        let kink = match &node.value {
+
+            OpValue::Number(_) |
+            OpValue::Variable(_)
+                => None,
+
+            OpValue::Add { left, right } |
+            OpValue::Sub { left, right } |
+            OpValue::Mul { left, right } |
+            OpValue::Div { left, right } |
+            OpValue::Pow { left, right } |
+            OpValue::Log { base: left, arg: right } => {
+                collect(left, dvar, found);
+                collect(right, dvar, found);
+                None
+            },
+
+            OpValue::Function { func, arg } => {
+                collect(arg, dvar, found);
+                func.kink().map(|kink| (Rc::clone(arg), kink))
+            },
+
+            OpValue::MultiFunction { func, args } => {
+                args.iter().for_each(|arg| collect(arg, dvar, found));
+                match (func, &args[..]) {
+                    // min(a, b) and max(a, b) switch arguments where a = b
+                    (MultiFunctions::Min | MultiFunctions::Max, [a, b]) => Some((
+                        Rc::new(OpNode { source: Rc::clone(&node.source), value: OpValue::Sub { left: Rc::clone(a), right: Rc::clone(b) } }),
+                        Kink::Zero
+                    )),
+                    _ => None
+                }
+            },
+
+            OpValue::Call { name: _, args, derivative: _ } => {
+                args.iter().for_each(|arg| collect(arg, dvar, found));
+                None
+            },
+
+            // The breakpoints are checked right away, since only some of them may not be differentiable
+            OpValue::Piecewise { pieces } => {
+                pieces.iter().flat_map(Piece::operands).for_each(|operand| collect(operand, dvar, found));
+                for breakpoint in piecewise::breakpoints(node, pieces, dvar) {
+                    if !found.iter().any(|other| ast::same_expression(&other.expression, node) && ast::same_expression(&other.argument, &breakpoint.argument)) {
+                        found.push(breakpoint);
+                    }
+                }
+                None
+            },
+        };
+
+        if let Some((argument, kink)) = kink {
+            if argument.contains_variable(dvar) && !found.iter().any(|other| ast::same_expression(&other.expression, node)) {
+                found.push(NonDifferentiable { expression: Rc::clone(node), argument, kink, reason: None });
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    collect(&function.root, dvar, &mut found);
+    found
+}
+
+
+/// Records the rules applied during a derivation, if enabled
+struct Trace {
+    enabled: bool,
+    depth: usize,
+    steps: Vec<Step>,
+}
+
+impl Trace {
+
+    fn disabled() -> Self {
+        Self { enabled: false, depth: 0, steps: Vec::new() }
+    }
+
+
+    fn enabled() -> Self {
+        Self { enabled: true, depth: 0, steps: Vec::new() }
+    }
+
+
+    /// Record the derivation of the node, before deriving its children
+    fn enter(&mut self, node: &OpNode, dvar: &str) {
+        if self.enabled {
+            self.steps.push(Step {
+                rule: Rule::of(node, dvar),
+                expression: notation::format_node(node, Notation::Infix),
+                depth: self.depth,
+            });
+        }
+        self.depth += 1;
+    }
+
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+}
+
+
+fn derive_node<'a>(node: &OpNode<'a>, dvar: &'a str, trace: &mut Trace) -> Rc<OpNode<'a>> {
+
+    trace.enter(node, dvar);
+
+    let derivative = match &node.value {
 
         OpValue::Number(_)
         // f(x) = n
@@ -36,8 +348,8 @@ fn derive_node<'a>(node: &OpNode<'a>, dvar: &'a str) -> Rc<OpNode<'a>> {
         // f'(x) = a'(x) + b'(x)
          => op_node!(node,
                 OpValue::Add { 
-                    left: derive_node(left, dvar), // a'(x)
-                    right: derive_node(right, dvar) // b'(x)
+                    left: derive_node(left, dvar, trace), // a'(x)
+                    right: derive_node(right, dvar, trace) // b'(x)
                 }
             ),
 
@@ -46,8 +358,8 @@ fn derive_node<'a>(node: &OpNode<'a>, dvar: &'a str) -> Rc<OpNode<'a>> {
         // f'(x) = a'(x) - b'(x)
          => op_node!(node,
                 OpValue::Sub { 
-                    left: derive_node(left, dvar), // a'(x)
-                    right: derive_node(right, dvar) // b'(x)
+                    left: derive_node(left, dvar, trace), // a'(x)
+                    right: derive_node(right, dvar, trace) // b'(x)
                 }
             ),
 
@@ -58,13 +370,13 @@ fn derive_node<'a>(node: &OpNode<'a>, dvar: &'a str) -> Rc<OpNode<'a>> {
                 OpValue::Add {
                     left: op_node!(node, // a'(x) * b(x)
                         OpValue::Mul {
-                            left: derive_node(left, dvar), // a'(x)
+                            left: derive_node(left, dvar, trace), // a'(x)
                             right: Rc::clone(right) // b(x)
                     }),
                     right: op_node!(node, // b'(x) * a(x)
                         OpValue::Mul {
                             left: Rc::clone(left), // a(x)
-                            right: derive_node(right, dvar) // b'(x)
+                            right: derive_node(right, dvar, trace) // b'(x)
                     })
                 }
             ),
@@ -78,13 +390,13 @@ fn derive_node<'a>(node: &OpNode<'a>, dvar: &'a str) -> Rc<OpNode<'a>> {
                         OpValue::Sub {
                             left: op_node!(node, // a'(x) * b(x)
                                 OpValue::Mul {
-                                    left: derive_node(left, dvar), // a'(x)
+                                    left: derive_node(left, dvar, trace), // a'(x)
                                     right: Rc::clone(right) // b(x)
                             }),
                             right: op_node!(node, // a(x) * b'(x)
                                 OpValue::Mul {
                                     left: Rc::clone(left), // a(x)
-                                    right: derive_node(right, dvar) // b'(x)
+                                    right: derive_node(right, dvar, trace) // b'(x)
                             }),
                     }),
                     right: op_node!(node, // g(x)^2
@@ -109,7 +421,126 @@ fn derive_node<'a>(node: &OpNode<'a>, dvar: &'a str) -> Rc<OpNode<'a>> {
         },
         
         OpValue::Function { func, arg }
-            => derive_function(*func, Rc::clone(arg), dvar),
+            => derive_function(*func, Rc::clone(arg), dvar, trace),
+
+        OpValue::MultiFunction { func, args } => {
+        // f(x) = g(a(x), b(x))
+        // f'(x) = ∂g/∂a * a'(x) + ∂g/∂b * b'(x)
+
+            // Constant arguments are skipped, the partial derivative with respect to them may not be defined, like ln(b) for root(a, b) with b < 0
+            partial_derivatives(node, *func, args).into_iter()
+                .zip(args.iter())
+                .filter(|(_, arg)| arg.contains_variable(dvar))
+                .map(|(partial, arg)| op_node!(node,
+                    OpValue::Mul {
+                        left: partial, // ∂g/∂a
+                        right: derive_node(arg, dvar, trace) // a'(x)
+                    }
+                ))
+                .reduce(|sum, term| op_node!(node, OpValue::Add { left: sum, right: term }))
+                .unwrap_or_else(|| number!(node, 0))
+        },
+
+        OpValue::Call { name, args, derivative } => {
+        // f(x) = g(a(x), b(x))
+        // f'(x) = g_1(a(x), b(x)) * a'(x) + g_2(a(x), b(x)) * b'(x)
+        // where g has no definition, so its partial derivatives g_1 and g_2 stay symbolic
+
+            args.iter().enumerate()
+                .filter(|(_, arg)| arg.contains_variable(dvar))
+                .map(|(index, arg)| op_node!(node,
+                    OpValue::Mul {
+                        left: op_node!(node, OpValue::Call { name, args: args.clone(), derivative: derivative.with_respect_to(index) }), // g_i(a(x), b(x))
+                        right: derive_node(arg, dvar, trace) // a'(x)
+                    }
+                ))
+                .reduce(|sum, term| op_node!(node, OpValue::Add { left: sum, right: term }))
+                .unwrap_or_else(|| number!(node, 0))
+        },
+
+        OpValue::Piecewise { pieces } => {
+        // f(x) = {a(x) if c(x); b(x) otherwise}
+        // f'(x) = {a'(x) if c(x); b'(x) otherwise}
+        // The breakpoints, where c(x) changes, are checked by `non_differentiable`
+
+            op_node!(node,
+                OpValue::Piecewise {
+                    pieces: pieces.iter()
+                        .map(|piece| Piece {
+                            value: derive_node(&piece.value, dvar, trace),
+                            condition: piece.condition.clone()
+                        })
+                        .collect()
+                }
+            )
+        },
+
+        OpValue::Log { base, arg } => {
+        // f(x) = log(b, a(x))
+        // f'(x) = a'(x) / (a(x) * ln(b))
+        // or
+        // f(x) = log(b(x), a(x)) = ln(a(x)) / ln(b(x))
+        // f'(x) = (a'(x)/a(x) * ln(b(x)) - ln(a(x)) * b'(x)/b(x)) / ln(b(x))^2
+
+            let ln = |arg: &Rc<OpNode<'a>>| op_node!(node,
+                OpValue::Function {
+                    func: Functions::NaturalLog,
+                    arg: Rc::clone(arg)
+                }
+            );
+
+            if !base.contains_variable(dvar) {
+                // The base is constant, so the logarithm is a natural logarithm times a constant
+                op_node!(node,
+                    OpValue::Div {
+                        left: derive_node(arg, dvar, trace), // a'(x)
+                        right: op_node!(node, // a(x) * ln(b)
+                            OpValue::Mul {
+                                left: Rc::clone(arg), // a(x)
+                                right: ln(base) // ln(b)
+                            }
+                        )
+                    }
+                )
+            } else {
+                op_node!(node,
+                    OpValue::Div {
+                        left: op_node!(node, // a'(x)/a(x) * ln(b(x)) - ln(a(x)) * b'(x)/b(x)
+                            OpValue::Sub {
+                                left: op_node!(node, // a'(x)/a(x) * ln(b(x))
+                                    OpValue::Mul {
+                                        left: op_node!(node, // a'(x) / a(x)
+                                            OpValue::Div {
+                                                left: derive_node(arg, dvar, trace), // a'(x)
+                                                right: Rc::clone(arg) // a(x)
+                                            }
+                                        ),
+                                        right: ln(base) // ln(b(x))
+                                    }
+                                ),
+                                right: op_node!(node, // ln(a(x)) * b'(x)/b(x)
+                                    OpValue::Mul {
+                                        left: ln(arg), // ln(a(x))
+                                        right: op_node!(node, // b'(x) / b(x)
+                                            OpValue::Div {
+                                                left: derive_node(base, dvar, trace), // b'(x)
+                                                right: Rc::clone(base) // b(x)
+                                            }
+                                        )
+                                    }
+                                )
+                            }
+                        ),
+                        right: op_node!(node, // ln(b(x))^2
+                            OpValue::Pow {
+                                left: ln(base), // ln(b(x))
+                                right: number!(node, 2) // ^2
+                            }
+                        )
+                    }
+                )
+            }
+        },
         
         OpValue::Pow { left, right } => {
         // f(x) = a(x) ^ b
@@ -118,29 +549,26 @@ fn derive_node<'a>(node: &OpNode<'a>, dvar: &'a str) -> Rc<OpNode<'a>> {
         // f(x) = a(x) ^ b(x)
         // f'(x) = e ^ ( b(x) * ln(a(x)) ) * ( b'(x) * ln(a(x)) + b(x) * a'(x)/a(x) )
 
-            let bx = derive_node(&right, dvar);
-
-            // If the derivative of a function is zero, it's not a function of the derivation variable
-            if matches!(bx.value, OpValue::Number(0.0)) {
+            if !right.contains_variable(dvar) {
                 // The exponent is constant, so treat this operation as a regular power
                 op_node!(node,
                     OpValue::Mul {
-                        left: op_node!(node, // (b * a(x)) ^ (b - 1)
-                            OpValue::Pow {
-                                left: op_node!(node, // b(x) * a(x)
-                                    OpValue::Mul {
-                                        left: Rc::clone(right), // b
-                                        right: Rc::clone(left) // a(x)
-                                    }
-                                ),
-                                right: op_node!(node, // b - 1
-                                    OpValue::Sub {
-                                        left: Rc::clone(right), // b
-                                        right: number!(node, 1) // 1
+                        left: op_node!(node, // b * a(x) ^ (b - 1)
+                            OpValue::Mul {
+                                left: Rc::clone(right), // b
+                                right: op_node!(node, // a(x) ^ (b - 1)
+                                    OpValue::Pow {
+                                        left: Rc::clone(left), // a(x)
+                                        right: op_node!(node, // b - 1
+                                            OpValue::Sub {
+                                                left: Rc::clone(right), // b
+                                                right: number!(node, 1) // 1
+                                            }
+                                        )
                                     }
                                 )
                         }), 
-                        right: derive_node(left, dvar) // a'(x)
+                        right: derive_node(left, dvar, trace) // a'(x)
                     }
                 )
             } else {
@@ -167,7 +595,7 @@ fn derive_node<'a>(node: &OpNode<'a>, dvar: &'a str) -> Rc<OpNode<'a>> {
                             OpValue::Add {
                                 left: op_node!(node, // b'(x) * ln(a(x))
                                     OpValue::Mul {
-                                        left: derive_node(right, dvar), // b'(x)
+                                        left: derive_node(right, dvar, trace), // b'(x)
                                         right: op_node!(node, // ln( a(x) )
                                             OpValue::Function {
                                                 func: Functions::NaturalLog,
@@ -181,7 +609,7 @@ fn derive_node<'a>(node: &OpNode<'a>, dvar: &'a str) -> Rc<OpNode<'a>> {
                                         left: Rc::clone(right), // b(x)
                                         right: op_node!(node, // a'(x) / a(x)
                                             OpValue::Div {
-                                                left: derive_node(left, dvar), // a'(x)
+                                                left: derive_node(left, dvar, trace), // a'(x)
                                                 right: Rc::clone(left)
                                             }
                                         )
@@ -195,12 +623,15 @@ fn derive_node<'a>(node: &OpNode<'a>, dvar: &'a str) -> Rc<OpNode<'a>> {
 
         },
 
-    }
+    };
+
+    trace.leave();
 
+    derivative
 }
 
 
-fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str) -> Rc<OpNode<'a>> {
+fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str, trace: &mut Trace) -> Rc<OpNode<'a>> {
     match func {
 
         Functions::Sin
@@ -214,7 +645,7 @@ fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str) -> R
                         arg: Rc::clone(&arg)
                     }
                 ),
-                right: derive_node(&arg, dvar) // a'(x)
+                right: derive_node(&arg, dvar, trace) // a'(x)
             }
         ),
 
@@ -232,7 +663,7 @@ fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str) -> R
                                 arg: Rc::clone(&arg)
                             })
                     }),
-                right: derive_node(&arg, dvar) // a'(x)
+                right: derive_node(&arg, dvar, trace) // a'(x)
             }
         ),
 
@@ -252,7 +683,7 @@ fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str) -> R
                         right: number!(arg, 2) // ^2
                     }
                 ),
-                right: derive_node(&arg, dvar) // a'(x)
+                right: derive_node(&arg, dvar, trace) // a'(x)
 
             }
         ),
@@ -262,7 +693,7 @@ fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str) -> R
         // f'(x) = a'(x) / sqrt(1 - a(x)^2)
          => op_node!(arg,
             OpValue::Div {
-                left: derive_node(&arg, dvar), // a'(x)
+                left: derive_node(&arg, dvar, trace), // a'(x)
                 right: op_node!(arg, // sqrt(1 - a(x)^2)
                     OpValue::Function {
                         func: Functions::SquareRoot,
@@ -290,7 +721,7 @@ fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str) -> R
                 left: number!(arg, -1), // -1
                 right: op_node!(arg, // a'(x) / sqrt(1 - a(x)^2)
                     OpValue::Div {
-                        left: derive_node(&arg, dvar), // a'(x)
+                        left: derive_node(&arg, dvar, trace), // a'(x)
                         right: op_node!(arg, // sqrt(1 - a(x)^2)
                             OpValue::Function {
                                 func: Functions::SquareRoot,
@@ -317,7 +748,7 @@ fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str) -> R
         // f'(x) = a'(x) / (1 + a(x)^2)
          => op_node!(arg,
             OpValue::Div {
-                left: derive_node(&arg, dvar), // a'(x)
+                left: derive_node(&arg, dvar, trace), // a'(x)
                 right: op_node!(arg, // 1 + a(x)^2
                     OpValue::Add {
                         left: number!(arg, 1), // 1
@@ -337,7 +768,7 @@ fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str) -> R
         // f'(x) = a'(x)/a(x)
          => op_node!(arg,
             OpValue::Div {
-                left: derive_node(&arg, dvar), // a'(x)
+                left: derive_node(&arg, dvar, trace), // a'(x)
                 right: arg // a(x)
             }
         ),
@@ -347,7 +778,7 @@ fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str) -> R
         // f'(x) = a'(x) / (2 * sqrt(a(x))
          => op_node!(arg,
             OpValue::Div {
-                left: derive_node(&arg, dvar), // a'(x)
+                left: derive_node(&arg, dvar, trace), // a'(x)
                 right: op_node!(arg, // 2 * sqrt(a(x))
                     OpValue::Mul {
                         left: number!(arg, 2), // 2
@@ -362,6 +793,31 @@ fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str) -> R
             }
         ),
 
+        Functions::CubeRoot
+        // f(x) = cbrt(a(x))
+        // f'(x) = a'(x) / (3 * cbrt(a(x))^2)
+         => op_node!(arg,
+            OpValue::Div {
+                left: derive_node(&arg, dvar, trace), // a'(x)
+                right: op_node!(arg, // 3 * cbrt(a(x))^2
+                    OpValue::Mul {
+                        left: number!(arg, 3), // 3
+                        right: op_node!(arg, // cbrt(a(x))^2
+                            OpValue::Pow {
+                                left: op_node!(arg, // cbrt(a(x))
+                                    OpValue::Function {
+                                        func: Functions::CubeRoot,
+                                        arg: Rc::clone(&arg) // a(x)
+                                    }
+                                ),
+                                right: number!(arg, 2) // ^2
+                            }
+                        )
+                    }
+                )
+            }
+        ),
+
         Functions::Secant
         // f(x) = sec(a(x))
         // f'(x) = sec(a(x)) * tan(a(x)) * a'(x)
@@ -381,12 +837,423 @@ fn derive_function<'a>(func: Functions, arg: Rc<OpNode<'a>>, dvar: &'a str) -> R
                                 arg: Rc::clone(&arg)
                             }    
                         ),
-                        right: derive_node(&arg, dvar) // a'(x)
+                        right: derive_node(&arg, dvar, trace) // a'(x)
                     }
                 )
             }
         ),
-        
+
+        Functions::Cosecant
+        // f(x) = csc(a(x))
+        // f'(x) = -csc(a(x)) * cot(a(x)) * a'(x)
+         => op_node!(arg,
+            OpValue::Mul {
+                left: op_node!(arg, // -csc(a(x))
+                    OpValue::Mul {
+                        left: number!(arg, -1), // -1
+                        right: op_node!(arg,
+                            OpValue::Function {
+                                func: Functions::Cosecant,
+                                arg: Rc::clone(&arg)
+                            })
+                    }),
+                right: op_node!(arg, // cot(a(x)) * a'(x)
+                    OpValue::Mul {
+                        left: op_node!(arg, // cot(a(x))
+                            OpValue::Function {
+                                func: Functions::Cotangent,
+                                arg: Rc::clone(&arg)
+                            }
+                        ),
+                        right: derive_node(&arg, dvar, trace) // a'(x)
+                    }
+                )
+            }
+        ),
+
+        Functions::Cotangent
+        // f(x) = cot(a(x))
+        // f'(x) = -csc(a(x))^2 * a'(x)
+         => op_node!(arg,
+            OpValue::Mul {
+                left: op_node!(arg, // -csc(a(x)) ^ 2
+                    OpValue::Mul {
+                        left: number!(arg, -1), // -1
+                        right: op_node!(arg, // csc(a(x)) ^ 2
+                            OpValue::Pow {
+                                left: op_node!(arg, // csc(a(x))
+                                    OpValue::Function {
+                                        func: Functions::Cosecant,
+                                        arg: Rc::clone(&arg)
+                                    }
+                                ),
+                                right: number!(arg, 2) // ^2
+                            }
+                        )
+                    }
+                ),
+                right: derive_node(&arg, dvar, trace) // a'(x)
+            }
+        ),
+
+        Functions::Arcsecant
+        // f(x) = asec(a(x))
+        // f'(x) = a'(x) / (a(x)^2 * sqrt(1 - 1 / a(x)^2)), which is a'(x) / (|a(x)| * sqrt(a(x)^2 - 1))
+         => op_node!(arg,
+            OpValue::Div {
+                left: derive_node(&arg, dvar, trace), // a'(x)
+                right: inverse_secant_denominator(&arg) // a(x)^2 * sqrt(1 - 1 / a(x)^2)
+            }
+        ),
+
+        Functions::Arccosecant
+        // f(x) = acsc(a(x))
+        // f'(x) = -a'(x) / (a(x)^2 * sqrt(1 - 1 / a(x)^2))
+         => op_node!(arg,
+            OpValue::Mul {
+                left: number!(arg, -1), // -1
+                right: op_node!(arg, // a'(x) / (a(x)^2 * sqrt(1 - 1 / a(x)^2))
+                    OpValue::Div {
+                        left: derive_node(&arg, dvar, trace), // a'(x)
+                        right: inverse_secant_denominator(&arg) // a(x)^2 * sqrt(1 - 1 / a(x)^2)
+                    }
+                )
+            }
+        ),
+
+        Functions::Arccotangent
+        // f(x) = acot(a(x))
+        // f'(x) = -a'(x) / (1 + a(x)^2)
+         => op_node!(arg,
+            OpValue::Mul {
+                left: number!(arg, -1), // -1
+                right: op_node!(arg, // a'(x) / (1 + a(x)^2)
+                    OpValue::Div {
+                        left: derive_node(&arg, dvar, trace), // a'(x)
+                        right: op_node!(arg, // 1 + a(x)^2
+                            OpValue::Add {
+                                left: number!(arg, 1), // 1
+                                right: op_node!(arg, // a(x) ^ 2
+                                    OpValue::Pow {
+                                        left: Rc::clone(&arg), // a(x)
+                                        right: number!(arg, 2) // ^2
+                                    }
+                                )
+                            }
+                        )
+                    }
+                )
+            }
+        ),
+
+        Functions::Exp
+        // f(x) = exp(a(x))
+        // f'(x) = exp(a(x)) * a'(x)
+         => op_node!(arg,
+            OpValue::Mul {
+                left: op_node!(arg, // exp(a(x))
+                    OpValue::Function {
+                        func: Functions::Exp,
+                        arg: Rc::clone(&arg)
+                    }
+                ),
+                right: derive_node(&arg, dvar, trace) // a'(x)
+            }
+        ),
+
+        Functions::Log10
+        // f(x) = log10(a(x))
+        // f'(x) = a'(x) / (a(x) * ln(10))
+         => op_node!(arg,
+            OpValue::Div {
+                left: derive_node(&arg, dvar, trace), // a'(x)
+                right: op_node!(arg, // a(x) * ln(10)
+                    OpValue::Mul {
+                        left: Rc::clone(&arg), // a(x)
+                        right: op_node!(arg, // ln(10)
+                            OpValue::Function {
+                                func: Functions::NaturalLog,
+                                arg: number!(arg, 10)
+                            }
+                        )
+                    }
+                )
+            }
+        ),
+
+        Functions::Log2
+        // f(x) = log2(a(x))
+        // f'(x) = a'(x) / (a(x) * ln(2))
+         => op_node!(arg,
+            OpValue::Div {
+                left: derive_node(&arg, dvar, trace), // a'(x)
+                right: op_node!(arg, // a(x) * ln(2)
+                    OpValue::Mul {
+                        left: Rc::clone(&arg), // a(x)
+                        right: op_node!(arg, // ln(2)
+                            OpValue::Function {
+                                func: Functions::NaturalLog,
+                                arg: number!(arg, 2)
+                            }
+                        )
+                    }
+                )
+            }
+        ),
+
+        Functions::Abs
+        // f(x) = abs(a(x))
+        // f'(x) = sign(a(x)) * a'(x), where a(x) ≠ 0
+         => op_node!(arg,
+            OpValue::Mul {
+                left: op_node!(arg, // sign(a(x))
+                    OpValue::Function {
+                        func: Functions::Sign,
+                        arg: Rc::clone(&arg)
+                    }
+                ),
+                right: derive_node(&arg, dvar, trace) // a'(x)
+            }
+        ),
+
+        Functions::Sign |
+        Functions::Floor |
+        Functions::Ceil |
+        Functions::Heaviside
+        // f(x) = sign(a(x))
+        // f'(x) = 0, except at the jumps where it does not exist
+         => number!(arg, 0),
+
+        Functions::Sinh
+        // f(x) = sinh(a(x))
+        // f'(x) = cosh(a(x)) * a'(x)
+         => op_node!(arg,
+            OpValue::Mul {
+                left: op_node!(arg, // cosh(a(x))
+                    OpValue::Function {
+                        func: Functions::Cosh,
+                        arg: Rc::clone(&arg)
+                    }
+                ),
+                right: derive_node(&arg, dvar, trace) // a'(x)
+            }
+        ),
+
+        Functions::Cosh
+        // f(x) = cosh(a(x))
+        // f'(x) = sinh(a(x)) * a'(x)
+         => op_node!(arg,
+            OpValue::Mul {
+                left: op_node!(arg, // sinh(a(x))
+                    OpValue::Function {
+                        func: Functions::Sinh,
+                        arg: Rc::clone(&arg)
+                    }
+                ),
+                right: derive_node(&arg, dvar, trace) // a'(x)
+            }
+        ),
+
+        Functions::Tanh
+        // f(x) = tanh(a(x))
+        // f'(x) = a'(x) / cosh(a(x))^2
+         => op_node!(arg,
+            OpValue::Div {
+                left: derive_node(&arg, dvar, trace), // a'(x)
+                right: op_node!(arg, // cosh(a(x)) ^ 2
+                    OpValue::Pow {
+                        left: op_node!(arg, // cosh(a(x))
+                            OpValue::Function {
+                                func: Functions::Cosh,
+                                arg: Rc::clone(&arg)
+                            }
+                        ),
+                        right: number!(arg, 2) // ^2
+                    }
+                )
+            }
+        ),
+
+        Functions::Arsinh
+        // f(x) = asinh(a(x))
+        // f'(x) = a'(x) / sqrt(a(x)^2 + 1)
+         => op_node!(arg,
+            OpValue::Div {
+                left: derive_node(&arg, dvar, trace), // a'(x)
+                right: op_node!(arg, // sqrt(a(x)^2 + 1)
+                    OpValue::Function {
+                        func: Functions::SquareRoot,
+                        arg: op_node!(arg, // a(x)^2 + 1
+                            OpValue::Add {
+                                left: op_node!(arg, // a(x)^2
+                                    OpValue::Pow {
+                                        left: Rc::clone(&arg), // a(x)
+                                        right: number!(arg, 2) // ^2
+                                    }
+                                ),
+                                right: number!(arg, 1) // 1
+                            }
+                        )
+                    }
+                )
+            }
+        ),
+
+        Functions::Arcosh
+        // f(x) = acosh(a(x))
+        // f'(x) = a'(x) / sqrt(a(x)^2 - 1)
+         => op_node!(arg,
+            OpValue::Div {
+                left: derive_node(&arg, dvar, trace), // a'(x)
+                right: op_node!(arg, // sqrt(a(x)^2 - 1)
+                    OpValue::Function {
+                        func: Functions::SquareRoot,
+                        arg: op_node!(arg, // a(x)^2 - 1
+                            OpValue::Sub {
+                                left: op_node!(arg, // a(x)^2
+                                    OpValue::Pow {
+                                        left: Rc::clone(&arg), // a(x)
+                                        right: number!(arg, 2) // ^2
+                                    }
+                                ),
+                                right: number!(arg, 1) // 1
+                            }
+                        )
+                    }
+                )
+            }
+        ),
+
+        Functions::Artanh
+        // f(x) = atanh(a(x))
+        // f'(x) = a'(x) / (1 - a(x)^2)
+         => op_node!(arg,
+            OpValue::Div {
+                left: derive_node(&arg, dvar, trace), // a'(x)
+                right: op_node!(arg, // 1 - a(x)^2
+                    OpValue::Sub {
+                        left: number!(arg, 1), // 1
+                        right: op_node!(arg, // a(x)^2
+                            OpValue::Pow {
+                                left: Rc::clone(&arg), // a(x)
+                                right: number!(arg, 2) // ^2
+                            }
+                        )
+                    }
+                )
+            }
+        ),
+
+    }
+}
+
+
+/// `a^2 * sqrt(1 - 1 / a^2)`, the denominator of the derivatives of `asec(a)` and `acsc(a)`.
+/// It equals `|a| * sqrt(a^2 - 1)` without needing the absolute value
+fn inverse_secant_denominator<'a>(arg: &Rc<OpNode<'a>>) -> Rc<OpNode<'a>> {
+    let square = || op_node!(arg, // a^2
+        OpValue::Pow {
+            left: Rc::clone(arg),
+            right: number!(arg, 2)
+        }
+    );
+    op_node!(arg,
+        OpValue::Mul {
+            left: square(), // a^2
+            right: op_node!(arg, // sqrt(1 - 1 / a^2)
+                OpValue::Function {
+                    func: Functions::SquareRoot,
+                    arg: op_node!(arg, // 1 - 1 / a^2
+                        OpValue::Sub {
+                            left: number!(arg, 1),
+                            right: op_node!(arg, // 1 / a^2
+                                OpValue::Div {
+                                    left: number!(arg, 1),
+                                    right: square()
+                                }
+                            )
+                        }
+                    )
+                }
+            )
+        }
+    )
+}
+
+
+/// Partial derivatives of the function of several arguments with respect to each of its arguments
+fn partial_derivatives<'a>(node: &OpNode<'a>, func: MultiFunctions, args: &[Rc<OpNode<'a>>]) -> Vec<Rc<OpNode<'a>>> {
+
+    let [a, b] = args else {
+        unreachable!("The arity of `{}` is checked when parsing", func)
+    };
+
+    let square = |arg: &Rc<OpNode<'a>>| op_node!(node, OpValue::Pow { left: Rc::clone(arg), right: number!(node, 2) });
+    let div = |left: Rc<OpNode<'a>>, right: Rc<OpNode<'a>>| op_node!(node, OpValue::Div { left, right });
+    let sub = |left: &Rc<OpNode<'a>>, right: &Rc<OpNode<'a>>| op_node!(node, OpValue::Sub { left: Rc::clone(left), right: Rc::clone(right) });
+    let step = |arg: Rc<OpNode<'a>>| op_node!(node, OpValue::Function { func: Functions::Heaviside, arg });
+    let itself = || op_node!(node, node.value.clone());
+
+    match func {
+
+        MultiFunctions::Atan2 => {
+        // f(a, b) = atan2(a, b)
+        // ∂f/∂a = b / (a^2 + b^2)
+        // ∂f/∂b = -a / (a^2 + b^2)
+            let norm = || op_node!(node, OpValue::Add { left: square(a), right: square(b) });
+            vec![
+                div(Rc::clone(b), norm()),
+                div(op_node!(node, OpValue::Mul { left: number!(node, -1), right: Rc::clone(a) }), norm()),
+            ]
+        },
+
+        MultiFunctions::Hypot => {
+        // f(a, b) = hypot(a, b)
+        // ∂f/∂a = a / hypot(a, b)
+        // ∂f/∂b = b / hypot(a, b)
+            vec![
+                div(Rc::clone(a), itself()),
+                div(Rc::clone(b), itself()),
+            ]
+        },
+
+        MultiFunctions::Min => {
+        // f(a, b) = min(a, b)
+        // ∂f/∂a = heaviside(b - a), which is 1 where a < b and 0 where a > b
+        // ∂f/∂b = heaviside(a - b)
+        // Both are undefined where a = b
+            vec![
+                step(sub(b, a)),
+                step(sub(a, b)),
+            ]
+        },
+
+        MultiFunctions::Max => {
+        // f(a, b) = max(a, b)
+        // ∂f/∂a = heaviside(a - b), which is 1 where a > b and 0 where a < b
+        // ∂f/∂b = heaviside(b - a)
+        // Both are undefined where a = b
+            vec![
+                step(sub(a, b)),
+                step(sub(b, a)),
+            ]
+        },
+
+        MultiFunctions::Root => {
+        // f(a, b) = root(a, b) = b^(1/a)
+        // ∂f/∂a = -root(a, b) * ln(b) / a^2
+        // ∂f/∂b = root(a, b) / (a * b)
+            vec![
+                div(
+                    op_node!(node, OpValue::Mul {
+                        left: op_node!(node, OpValue::Mul { left: number!(node, -1), right: itself() }),
+                        right: op_node!(node, OpValue::Function { func: Functions::NaturalLog, arg: Rc::clone(b) })
+                    }),
+                    square(a)
+                ),
+                div(itself(), op_node!(node, OpValue::Mul { left: Rc::clone(a), right: Rc::clone(b) })),
+            ]
+        },
     }
 }
 
@@ -403,8 +1270,21 @@ pub fn derive<'a>(func: &FunctionTree<'a>, dvar: &'a str) -> FunctionTree<'a> {
     */
 
     FunctionTree {
-        root: derive_node(&func.root, dvar)
+        root: derive_node(&func.root, dvar, &mut Trace::disabled())
     }
 
 }
 
+
+/// Derive `func` with respect to `dvar`, recording the rule applied to every subexpression
+pub fn derive_explained<'a>(func: &FunctionTree<'a>, dvar: &'a str) -> (FunctionTree<'a>, Vec<Step>) {
+
+    let mut trace = Trace::enabled();
+
+    let derivative = FunctionTree {
+        root: derive_node(&func.root, dvar, &mut trace)
+    };
+
+    (derivative, trace.steps)
+}
+
@@ -0,0 +1,224 @@
+use std::ops::{Add, Div, Mul, Sub};
+use std::f64::consts;
+
+use crate::ast::{self, FunctionTree, OpNode, OpValue};
+use crate::errors::{self, CalcError};
+use crate::functions::{Functions, MultiFunctions};
+
+
+/// A dual number `value + derivative * ε`, where `ε^2 = 0`.
+/// Evaluating a function on dual numbers yields both the function value and its exact derivative.
+#[derive(Clone, Copy)]
+pub struct Dual {
+    pub value: f64,
+    pub derivative: f64,
+}
+
+impl Dual {
+
+    pub fn constant(value: f64) -> Self {
+        Self { value, derivative: 0_f64 }
+    }
+
+
+    /// Apply a function `f` whose derivative at `self.value` is `df` (chain rule)
+    fn chain(self, f: f64, df: f64) -> Self {
+        Self {
+            value: f,
+            derivative: df * self.derivative
+        }
+    }
+
+
+    pub fn powd(self, exponent: Dual) -> Self {
+        let value = ast::exec_pow(self.value, exponent.value);
+        if exponent.derivative == 0_f64 {
+            // Constant exponent, also valid for negative bases
+            Self {
+                value,
+                derivative: exponent.value * ast::exec_pow(self.value, exponent.value - 1_f64) * self.derivative
+            }
+        } else {
+            Self {
+                value,
+                derivative: value * (exponent.derivative * self.value.ln() + exponent.value * self.derivative / self.value)
+            }
+        }
+    }
+
+}
+
+impl Add for Dual {
+    type Output = Dual;
+
+    fn add(self, rhs: Dual) -> Dual {
+        Dual { value: self.value + rhs.value, derivative: self.derivative + rhs.derivative }
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+
+    fn sub(self, rhs: Dual) -> Dual {
+        Dual { value: self.value - rhs.value, derivative: self.derivative - rhs.derivative }
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+
+    fn mul(self, rhs: Dual) -> Dual {
+        Dual {
+            value: self.value * rhs.value,
+            derivative: self.derivative * rhs.value + self.value * rhs.derivative
+        }
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+
+    fn div(self, rhs: Dual) -> Dual {
+        Dual {
+            value: self.value / rhs.value,
+            derivative: (self.derivative * rhs.value - self.value * rhs.derivative) / (rhs.value * rhs.value)
+        }
+    }
+}
+
+
+/// Derivative of the function at `x`
+pub fn function_derivative(func: Functions, x: f64) -> f64 {
+    match func {
+        Functions::Sin => x.cos(),
+        Functions::Cos => -x.sin(),
+        Functions::Tan => 1_f64 / (x.cos() * x.cos()),
+        Functions::Arcsin => 1_f64 / (1_f64 - x * x).sqrt(),
+        Functions::Arccos => -1_f64 / (1_f64 - x * x).sqrt(),
+        Functions::Arctan => 1_f64 / (1_f64 + x * x),
+        Functions::SquareRoot => 1_f64 / (2_f64 * x.sqrt()),
+        Functions::CubeRoot => 1_f64 / (3_f64 * x.cbrt() * x.cbrt()),
+        Functions::NaturalLog => 1_f64 / x,
+        Functions::Secant => x.tan() / x.cos(),
+        Functions::Cosecant => -x.cos() / (x.sin() * x.sin()),
+        Functions::Cotangent => -1_f64 / (x.sin() * x.sin()),
+        Functions::Arcsecant => 1_f64 / (x * x * (1_f64 - 1_f64 / (x * x)).sqrt()),
+        Functions::Arccosecant => -1_f64 / (x * x * (1_f64 - 1_f64 / (x * x)).sqrt()),
+        Functions::Arccotangent => -1_f64 / (1_f64 + x * x),
+        Functions::Sinh => x.cosh(),
+        Functions::Cosh => x.sinh(),
+        Functions::Tanh => 1_f64 / (x.cosh() * x.cosh()),
+        Functions::Arsinh => 1_f64 / (x * x + 1_f64).sqrt(),
+        Functions::Arcosh => 1_f64 / (x * x - 1_f64).sqrt(),
+        Functions::Artanh => 1_f64 / (1_f64 - x * x),
+        Functions::Exp => x.exp(),
+        Functions::Log10 => 1_f64 / (x * consts::LN_10),
+        Functions::Log2 => 1_f64 / (x * consts::LN_2),
+        // Not differentiable at the kinks, where this is the average of the one-sided derivatives
+        Functions::Abs => ast::exec_function(Functions::Sign, x),
+        Functions::Sign |
+        Functions::Floor |
+        Functions::Ceil |
+        Functions::Heaviside => 0_f64,
+    }
+}
+
+
+/// Partial derivatives of the function with respect to each of its arguments, at the given point
+pub fn multi_function_partials(func: MultiFunctions, args: &[f64]) -> Vec<f64> {
+    match (func, args) {
+        (MultiFunctions::Atan2, [y, x]) => vec![x / (x * x + y * y), -y / (x * x + y * y)],
+        (MultiFunctions::Hypot, [a, b]) => vec![a / a.hypot(*b), b / a.hypot(*b)],
+        // Only one of the arguments is selected, the derivative is not defined where they are equal
+        (MultiFunctions::Min, [a, b]) => selector_partials(a < b, a > b),
+        (MultiFunctions::Max, [a, b]) => selector_partials(a > b, a < b),
+        (MultiFunctions::Root, [n, x]) => {
+            let root = ast::exec_root(*n, *x);
+            vec![-root * x.ln() / (n * n), root / (n * x)]
+        },
+        _ => unreachable!("The arity of `{}` is checked when parsing", func)
+    }
+}
+
+
+/// Partial derivatives of `min` and `max`, which select the first or second argument
+fn selector_partials(first: bool, second: bool) -> Vec<f64> {
+    match (first, second) {
+        (true, _) => vec![1_f64, 0_f64],
+        (_, true) => vec![0_f64, 1_f64],
+        _ => vec![f64::NAN, f64::NAN],
+    }
+}
+
+
+pub fn exec_function(func: Functions, arg: Dual) -> Dual {
+    arg.chain(ast::exec_function(func, arg.value), function_derivative(func, arg.value))
+}
+
+
+/// Evaluate the function and its derivative with respect to `dvar` at the given point (forward-mode automatic differentiation)
+pub fn evaluate(tree: &FunctionTree, dvar: &str, point: &[(String, f64)]) -> Result<Dual, CalcError> {
+    evaluate_node(&tree.root, dvar, point)
+}
+
+
+fn evaluate_node(node: &OpNode, dvar: &str, point: &[(String, f64)]) -> Result<Dual, CalcError> {
+    Ok(match &node.value {
+        OpValue::Number(n) => Dual::constant(*n),
+        OpValue::Add { left, right } => evaluate_node(left, dvar, point)? + evaluate_node(right, dvar, point)?,
+        OpValue::Sub { left, right } => evaluate_node(left, dvar, point)? - evaluate_node(right, dvar, point)?,
+        OpValue::Mul { left, right } => evaluate_node(left, dvar, point)? * evaluate_node(right, dvar, point)?,
+        OpValue::Div { left, right } => evaluate_node(left, dvar, point)? / evaluate_node(right, dvar, point)?,
+        OpValue::Pow { left, right } => evaluate_node(left, dvar, point)?.powd(evaluate_node(right, dvar, point)?),
+        OpValue::Function { func, arg } => exec_function(*func, evaluate_node(arg, dvar, point)?),
+        OpValue::MultiFunction { func, args } => {
+            let args = args.iter().map(|arg| evaluate_node(arg, dvar, point)).collect::<Result<Vec<_>, _>>()?;
+            let values = args.iter().map(|arg| arg.value).collect::<Vec<_>>();
+            Dual {
+                value: ast::exec_multi_function(*func, &values),
+                // Constant arguments are skipped, the partial derivative with respect to them may be NaN
+                derivative: multi_function_partials(*func, &values).iter().zip(&args)
+                    .filter(|(_, arg)| arg.derivative != 0_f64)
+                    .map(|(partial, arg)| partial * arg.derivative)
+                    .sum()
+            }
+        },
+
+        OpValue::Call { .. } => unreachable!("Functions containing abstract functions are rejected before being evaluated"),
+
+        // Only the piece whose condition holds is differentiated
+        OpValue::Piecewise { pieces } => {
+            let active = ast::active_piece(pieces, |condition| Ok::<_, CalcError>(condition.comparison.holds(
+                evaluate_node(&condition.left, dvar, point)?.value,
+                evaluate_node(&condition.right, dvar, point)?.value
+            )))?;
+            match active {
+                Some(piece) => evaluate_node(&piece.value, dvar, point)?,
+                None => Dual { value: f64::NAN, derivative: f64::NAN }
+            }
+        },
+
+        // log(b, a) = ln(a) / ln(b)
+        OpValue::Log { base, arg }
+            => exec_function(Functions::NaturalLog, evaluate_node(arg, dvar, point)?) / exec_function(Functions::NaturalLog, evaluate_node(base, dvar, point)?),
+        OpValue::Variable(name) => {
+            let value = point.iter()
+                .find(|(var, _)| var == name)
+                .map(|(_, value)| *value)
+                .or_else(|| ast::constant_value(name))
+                .ok_or_else(|| errors::invalid_input(format!("No value was given for variable `{}`", name).as_str()))?;
+            Dual {
+                value,
+                derivative: if *name == dvar { 1_f64 } else { 0_f64 }
+            }
+        },
+    })
+}
+
+
+/// Whether two derivative values agree, up to floating point errors
+pub fn agrees(symbolic: f64, automatic: f64) -> bool {
+    (symbolic.is_nan() && automatic.is_nan())
+        || symbolic == automatic
+        || (symbolic - automatic).abs() <= 1e-9 * (1_f64 + automatic.abs())
+}
@@ -1,8 +1,55 @@
+use std::fmt;
+
 use colored::Colorize;
 
 use crate::tokenizer::SourceToken;
 
 
+#[derive(Clone, Copy, Debug)]
+pub enum ErrorKind {
+    InvalidToken,
+    InvalidInput,
+    Parsing,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::InvalidToken => write!(f, "invalid_token"),
+            ErrorKind::InvalidInput => write!(f, "invalid_input"),
+            ErrorKind::Parsing => write!(f, "parsing"),
+        }
+    }
+}
+
+
+/// The source token an error refers to
+#[derive(Debug)]
+pub struct ErrorToken {
+    pub string: String,
+    /// 1-based column of the first character of the token
+    pub column: usize,
+}
+
+impl ErrorToken {
+
+    /// 1-based column right after the last character of the token
+    pub fn end_column(&self) -> usize {
+        self.column + self.string.chars().count()
+    }
+
+}
+
+
+/// An error in the user input, optionally pointing to the offending token
+#[derive(Debug)]
+pub struct CalcError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub token: Option<ErrorToken>,
+}
+
+
 pub fn print_source_context(source: &str, char_pointer: usize) {
 
     println!("{}", source);
@@ -11,34 +58,64 @@ pub fn print_source_context(source: &str, char_pointer: usize) {
 }
 
 
-pub fn invalid_token(token: &SourceToken, source: &str, message: &str) -> ! {
+/// Print a human-readable description of the error.
+/// `source` is the input the error originated from.
+pub fn report(error: &CalcError, source: &str) {
 
-    println!("Invalid token `{}` at column {}:\n", token.string, token.column);
+    match (error.kind, &error.token) {
 
-    print_source_context(source, token.column);
+        (ErrorKind::InvalidToken, Some(token)) => {
+            println!("Invalid token `{}` at column {}:\n", token.string, token.column);
+            print_source_context(source, token.column);
+            println!("\n{}\n", error.message);
+        },
 
-    println!("\n{}\n", message);
+        (ErrorKind::Parsing, Some(token)) => {
+            println!("Parsing error on token `{}` at column {}:\n", token.string, token.column);
+            print_source_context(source, token.column);
+            println!("\n{}\n", error.message);
+        },
 
-    std::process::exit(1);
+        _ => println!("Invalid input:\n{}\n", error.message),
+    }
 }
 
 
-pub fn invalid_input(message: &str) -> ! {
+fn token_error(kind: ErrorKind, token: &SourceToken, message: &str) -> CalcError {
+    CalcError {
+        kind,
+        message: message.to_string(),
+        token: Some(ErrorToken {
+            string: token.string.to_string(),
+            column: token.column
+        })
+    }
+}
 
-    println!("Invalid input:\n{}\n", message);
 
-    std::process::exit(1);
+pub fn invalid_token(token: &SourceToken, message: &str) -> CalcError {
+    token_error(ErrorKind::InvalidToken, token, message)
 }
 
 
-pub fn parsing_error(token: &SourceToken, source: &str, message: &str) -> ! {
+pub fn invalid_input(message: &str) -> CalcError {
+    CalcError {
+        kind: ErrorKind::InvalidInput,
+        message: message.to_string(),
+        token: None
+    }
+}
 
-    println!("Parsing error on token `{}` at column {}:\n", token.string, token.column);
 
-    print_source_context(source, token.column);
+pub fn parsing_error(token: &SourceToken, message: &str) -> CalcError {
+    token_error(ErrorKind::Parsing, token, message)
+}
 
-    println!("\n{}\n", message);
 
-    std::process::exit(1);
+/// The message for a function called with the wrong number of arguments, like "`g` takes 1 argument, but 2 were given."
+pub fn arity_message(name: &str, arity: usize, given: usize) -> String {
+    format!(
+        "`{}` takes {} argument{}, but {} {} given.",
+        name, arity, if arity == 1 { "" } else { "s" }, given, if given == 1 { "was" } else { "were" }
+    )
 }
-
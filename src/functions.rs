@@ -2,17 +2,17 @@ use std::fmt;
 
 
 macro_rules! declare_functions {
-    ($($name:ident $repr:ident),+) => {
+    ($(#[$doc:meta])* $enum:ident { $($name:ident $repr:ident),+ }) => {
         
-/// Known mathematical functions
-#[derive(Clone, Copy)]
-pub enum Functions {
+$(#[$doc])*
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum $enum {
 
     $($name),+
 
 }
 
-impl Functions {
+impl $enum {
 
     pub fn from_name(name: &str) -> Option<Self> {
         match name {
@@ -23,7 +23,7 @@ impl Functions {
 
 }
 
-impl fmt::Display for Functions {
+impl fmt::Display for $enum {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             $(Self::$name => write!(f, stringify!($repr)),)+
@@ -36,15 +36,183 @@ impl fmt::Display for Functions {
 
 declare_functions! {
 
-    Sin sin,
-    Cos cos,
-    Tan tan,
-    Arcsin asin,
-    Arccos acos,
-    Arctan atan,
-    SquareRoot sqrt,
-    NaturalLog ln,
-    Secant sec
+    /// Known mathematical functions
+    Functions {
+
+        Sin sin,
+        Cos cos,
+        Tan tan,
+        Arcsin asin,
+        Arccos acos,
+        Arctan atan,
+        SquareRoot sqrt,
+        CubeRoot cbrt,
+        NaturalLog ln,
+        Secant sec,
+        Cosecant csc,
+        Cotangent cot,
+        Arcsecant asec,
+        Arccosecant acsc,
+        Arccotangent acot,
+        Sinh sinh,
+        Cosh cosh,
+        Tanh tanh,
+        Arsinh asinh,
+        Arcosh acosh,
+        Artanh atanh,
+        Exp exp,
+        Log10 log10,
+        Log2 log2,
+        Abs abs,
+        Sign sign,
+        Floor floor,
+        Ceil ceil,
+        Heaviside heaviside
+
+    }
+}
+
+
+declare_functions! {
+
+    /// Known mathematical functions of several arguments
+    MultiFunctions {
 
+        Atan2 atan2,
+        Hypot hypot,
+        Min min,
+        Max max,
+        Root root
+
+    }
 }
 
+
+
+impl Functions {
+
+    /// The LaTeX command for the function, if LaTeX has one
+    pub fn latex_name(&self) -> Option<&'static str> {
+        match self {
+            Functions::Sin => Some("\\sin"),
+            Functions::Cos => Some("\\cos"),
+            Functions::Tan => Some("\\tan"),
+            Functions::Arcsin => Some("\\arcsin"),
+            Functions::Arccos => Some("\\arccos"),
+            Functions::Arctan => Some("\\arctan"),
+            Functions::NaturalLog => Some("\\ln"),
+            Functions::Secant => Some("\\sec"),
+            Functions::Cosecant => Some("\\csc"),
+            Functions::Cotangent => Some("\\cot"),
+            Functions::Arcsecant |
+            Functions::Arccosecant |
+            Functions::Arccotangent => None,
+            Functions::Sinh => Some("\\sinh"),
+            Functions::Cosh => Some("\\cosh"),
+            Functions::Tanh => Some("\\tanh"),
+            Functions::Exp => Some("\\exp"),
+            Functions::Log10 => Some("\\log_{10}"),
+            Functions::Log2 => Some("\\log_{2}"),
+            Functions::Arsinh |
+            Functions::Arcosh |
+            Functions::Artanh => None,
+            Functions::SquareRoot |
+            Functions::CubeRoot => None,
+            Functions::Sign => Some("\\operatorname{sgn}"),
+            Functions::Abs |
+            Functions::Floor |
+            Functions::Ceil |
+            Functions::Heaviside => None,
+        }
+    }
+
+
+    /// Where the function is not differentiable, if anywhere in its domain
+    pub fn kink(&self) -> Option<Kink> {
+        match self {
+            Functions::Abs |
+            Functions::Sign |
+            Functions::Heaviside => Some(Kink::Zero),
+            Functions::Floor |
+            Functions::Ceil => Some(Kink::Integer),
+            _ => None
+        }
+    }
+
+
+    /// Whether the function is piecewise constant, jumping at its kinks
+    pub fn is_step(&self) -> bool {
+        matches!(self, Functions::Sign | Functions::Floor | Functions::Ceil | Functions::Heaviside)
+    }
+
+
+    /// Whether the values of the function are bounded, even where its argument goes to infinity
+    pub fn is_bounded(&self) -> bool {
+        matches!(self,
+            Functions::Sin | Functions::Cos | Functions::Tanh |
+            Functions::Arcsin | Functions::Arccos | Functions::Arctan |
+            Functions::Arcsecant | Functions::Arccosecant | Functions::Arccotangent |
+            Functions::Sign | Functions::Heaviside
+        )
+    }
+
+}
+
+
+/// Where a function is not differentiable
+#[derive(Clone, Copy)]
+pub enum Kink {
+    /// Where the argument is zero, like `abs(x)` at 0
+    Zero,
+    /// Where the argument is an integer, like `floor(x)`
+    Integer,
+}
+
+impl Kink {
+
+    /// Whether the function is not differentiable at the value of its argument
+    pub fn contains(&self, arg: f64) -> bool {
+        match self {
+            Kink::Zero => arg == 0_f64,
+            Kink::Integer => arg.fract() == 0_f64,
+        }
+    }
+
+
+    /// Whether the function is not differentiable somewhere between two values of its argument, both included
+    pub fn crossed(&self, from: f64, to: f64) -> bool {
+        self.contains(from) || self.contains(to) || match self {
+            Kink::Zero => from.signum() != to.signum(),
+            Kink::Integer => from.floor() != to.floor(),
+        }
+    }
+
+}
+
+
+impl MultiFunctions {
+
+    /// Number of arguments the function takes
+    pub fn arity(&self) -> usize {
+        match self {
+            MultiFunctions::Atan2 |
+            MultiFunctions::Hypot |
+            MultiFunctions::Min |
+            MultiFunctions::Max |
+            MultiFunctions::Root => 2,
+        }
+    }
+
+
+    /// The LaTeX command for the function, if LaTeX has one
+    pub fn latex_name(&self) -> Option<&'static str> {
+        match self {
+            MultiFunctions::Min => Some("\\min"),
+            MultiFunctions::Max => Some("\\max"),
+            MultiFunctions::Atan2 |
+            MultiFunctions::Hypot |
+            MultiFunctions::Root => None,
+        }
+    }
+
+}
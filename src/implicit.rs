@@ -0,0 +1,113 @@
+use std::rc::Rc;
+
+use crate::abstract_functions::Derivative;
+use crate::ast::{Equation, FunctionTree, OpNode, OpValue};
+use crate::derivatives;
+use crate::errors::{self, CalcError};
+
+
+/// Stands for the derivative of the dependent variable while its terms are collected.
+/// It is not a valid variable name, so it can't clash with the variables of the equation
+const DERIVATIVE: &str = "dy/dx";
+
+
+/// The derivative of `dependent` with respect to `var`, where `dependent` is defined implicitly by the equation, like `y` in `x^2 + y^2 = 25`.
+///
+/// Both sides are derived with respect to `var`, with `dependent` replaced by the abstract function `dependent(var)`.
+/// By the chain rule, the derivative of each side is linear in the derivative of `dependent`, so the derived equation can be written as
+/// `a + b * dy/dx = c + d * dy/dx`, and the result is `(c - a) / (b - d)`
+pub fn differentiate<'a>(equation: &Equation<'a>, var: &'a str, dependent: &'a str) -> Result<FunctionTree<'a>, CalcError> {
+
+    if var == dependent {
+        return Err(errors::invalid_input(format!("`{}` can't depend on itself", var).as_str()));
+    }
+
+    let (a, b) = derive_side(&equation.left, var, dependent);
+    let (c, d) = derive_side(&equation.right, var, dependent);
+
+    let coefficient = FunctionTree { root: difference(&b, &d) }.simplify();
+
+    if matches!(coefficient.root.value, OpValue::Number(n) if n == 0_f64) {
+        return Err(errors::invalid_input(
+            format!("The derivative of the equation doesn't depend on the derivative of `{}`, so it can't be solved for it", dependent).as_str()
+        ));
+    }
+
+    let solution = FunctionTree {
+        root: Rc::new(OpNode {
+            source: Rc::clone(&equation.left.root.source),
+            value: OpValue::Div { left: difference(&c, &a), right: coefficient.root }
+        })
+    };
+
+    Ok(solution.simplify())
+}
+
+
+/// Derive one side of the equation, written as `a + b * dy/dx`, and return `a` and `b` simplified
+fn derive_side<'a>(side: &FunctionTree<'a>, var: &'a str, dependent: &'a str) -> (Rc<OpNode<'a>>, Rc<OpNode<'a>>) {
+
+    let new = |value: OpValue<'a>| Rc::new(OpNode { source: Rc::clone(&side.root.source), value });
+
+    // y -> y(x)
+    let call = new(OpValue::Call {
+        name: dependent,
+        args: Box::new([new(OpValue::Variable(var))]),
+        derivative: Derivative::default()
+    });
+
+    let derivative = derivatives::derive(&side.substitute(dependent, &call), var);
+    let derivative = FunctionTree { root: collect_node(&derivative.root, dependent) };
+
+    let coefficient = derivatives::derive(&derivative, DERIVATIVE).simplify();
+    let rest = derivative.substitute(DERIVATIVE, &new(OpValue::Number(0_f64))).simplify();
+
+    (rest.root, coefficient.root)
+}
+
+
+/// `left - right`, without the subtraction when one side is 0, like the derivative of the constant side of most equations
+fn difference<'a>(left: &Rc<OpNode<'a>>, right: &Rc<OpNode<'a>>) -> Rc<OpNode<'a>> {
+
+    let new = |value: OpValue<'a>| Rc::new(OpNode { source: Rc::clone(&left.source), value });
+
+    match (&left.value, &right.value) {
+        (_, OpValue::Number(n)) if *n == 0_f64 => Rc::clone(left),
+        (OpValue::Number(n), _) if *n == 0_f64 => new(OpValue::Mul { left: new(OpValue::Number(-1_f64)), right: Rc::clone(right) }),
+        _ => new(OpValue::Sub { left: Rc::clone(left), right: Rc::clone(right) }),
+    }
+}
+
+
+/// Replace `y(x)` with `y` again, and its derivative `y'(x)` with the `DERIVATIVE` placeholder
+fn collect_node<'a>(node: &Rc<OpNode<'a>>, dependent: &'a str) -> Rc<OpNode<'a>> {
+
+    let value = match &node.value {
+
+        OpValue::Number(_) |
+        OpValue::Variable(_)
+            => return Rc::clone(node),
+
+        OpValue::Call { name, args: _, derivative } if *name == dependent => match derivative.order() {
+            0 => OpValue::Variable(dependent),
+            1 => OpValue::Variable(DERIVATIVE),
+            _ => unreachable!("The equation is only derived once"),
+        },
+
+        OpValue::Add { left, right } => OpValue::Add { left: collect_node(left, dependent), right: collect_node(right, dependent) },
+        OpValue::Sub { left, right } => OpValue::Sub { left: collect_node(left, dependent), right: collect_node(right, dependent) },
+        OpValue::Mul { left, right } => OpValue::Mul { left: collect_node(left, dependent), right: collect_node(right, dependent) },
+        OpValue::Div { left, right } => OpValue::Div { left: collect_node(left, dependent), right: collect_node(right, dependent) },
+        OpValue::Pow { left, right } => OpValue::Pow { left: collect_node(left, dependent), right: collect_node(right, dependent) },
+        OpValue::Function { func, arg } => OpValue::Function { func: *func, arg: collect_node(arg, dependent) },
+        OpValue::Log { base, arg } => OpValue::Log { base: collect_node(base, dependent), arg: collect_node(arg, dependent) },
+        OpValue::MultiFunction { func, args } => OpValue::MultiFunction { func: *func, args: args.iter().map(|arg| collect_node(arg, dependent)).collect() },
+        OpValue::Piecewise { pieces } => OpValue::Piecewise { pieces: pieces.iter().map(|piece| piece.map(|operand| collect_node(operand, dependent))).collect() },
+        OpValue::Call { name, args, derivative } => OpValue::Call { name, args: args.iter().map(|arg| collect_node(arg, dependent)).collect(), derivative: derivative.clone() },
+    };
+
+    Rc::new(OpNode {
+        source: Rc::clone(&node.source),
+        value
+    })
+}
@@ -0,0 +1,598 @@
+use std::rc::Rc;
+
+use crate::ast::{FunctionTree, OpNode, OpValue};
+use crate::bytecode;
+use crate::derivatives;
+use crate::errors::{self, CalcError};
+use crate::functions::{Functions, MultiFunctions};
+use crate::notation::{self, Notation};
+use crate::verification;
+
+
+/// Maximum number of nested integrations by parts. Without a limit, integration by parts may never terminate
+const MAX_PARTS_DEPTH: usize = 3;
+
+/// Values of the integration variable at which subexpressions are evaluated to check whether they are constant
+const SAMPLES: [f64; 5] = [0.37, 1.21, -0.83, 2.53, -1.69];
+
+
+fn op_node<'a>(source: &OpNode<'a>, value: OpValue<'a>) -> Rc<OpNode<'a>> {
+    Rc::new(OpNode {
+        source: Rc::clone(&source.source),
+        value
+    })
+}
+
+
+fn number<'a>(source: &OpNode<'a>, n: f64) -> Rc<OpNode<'a>> {
+    op_node(source, OpValue::Number(n))
+}
+
+
+fn function<'a>(func: Functions, arg: &Rc<OpNode<'a>>) -> Rc<OpNode<'a>> {
+    op_node(arg, OpValue::Function { func, arg: Rc::clone(arg) })
+}
+
+
+/// `ln(abs(u))`, the antiderivative of `1 / u` on both sides of 0
+fn ln_abs<'a>(arg: &Rc<OpNode<'a>>) -> Rc<OpNode<'a>> {
+    function(Functions::NaturalLog, &function(Functions::Abs, arg))
+}
+
+
+fn simplified<'a>(node: &Rc<OpNode<'a>>) -> Rc<OpNode<'a>> {
+    FunctionTree { root: Rc::clone(node) }.simplify().root
+}
+
+
+/// Find an antiderivative of the function with respect to `var`, without the integration constant.
+/// The antiderivative is checked by differentiating it back and comparing it with the function at random points.
+pub fn integrate<'a>(function: &FunctionTree<'a>, var: &'a str) -> Result<FunctionTree<'a>, CalcError> {
+
+    let antiderivative = integrate_node(&function.simplify().root, var, 0)
+        .map(|root| FunctionTree { root }.simplify())
+        .ok_or_else(|| errors::invalid_input(
+            format!("Cannot find an antiderivative of `{}`", notation::format_tree(function, Notation::Infix)).as_str()
+        ))?;
+
+    let derivative = derivatives::derive(&antiderivative, var).simplify();
+    let points = verification::random_points(function, var, 20, 10_f64, 1);
+
+    if !verification::same_values(function, &derivative, &points, 1e-6)? {
+        return Err(errors::invalid_input(
+            format!("The antiderivative `{}` does not differentiate back to the function", notation::format_tree(&antiderivative, Notation::Infix)).as_str()
+        ));
+    }
+
+    Ok(antiderivative)
+}
+
+
+fn integrate_node<'a>(node: &Rc<OpNode<'a>>, var: &'a str, depth: usize) -> Option<Rc<OpNode<'a>>> {
+
+    // ∫ c dx = c * x
+    if !node.contains_variable(var) {
+        return Some(op_node(node, OpValue::Mul { left: Rc::clone(node), right: op_node(node, OpValue::Variable(var)) }));
+    }
+
+    match &node.value {
+
+        // ∫ a + b dx = ∫ a dx + ∫ b dx
+        OpValue::Add { left, right } => return Some(op_node(node,
+            OpValue::Add {
+                left: integrate_node(left, var, depth)?,
+                right: integrate_node(right, var, depth)?
+            }
+        )),
+
+        // ∫ a - b dx = ∫ a dx - ∫ b dx
+        OpValue::Sub { left, right } => return Some(op_node(node,
+            OpValue::Sub {
+                left: integrate_node(left, var, depth)?,
+                right: integrate_node(right, var, depth)?
+            }
+        )),
+
+        // ∫ c * a dx = c * ∫ a dx
+        OpValue::Mul { left, right } if !left.contains_variable(var) => return Some(op_node(node,
+            OpValue::Mul {
+                left: Rc::clone(left),
+                right: integrate_node(right, var, depth)?
+            }
+        )),
+
+        // ∫ a * c dx = (∫ a dx) * c
+        OpValue::Mul { left, right } if !right.contains_variable(var) => return Some(op_node(node,
+            OpValue::Mul {
+                left: integrate_node(left, var, depth)?,
+                right: Rc::clone(right)
+            }
+        )),
+
+        // ∫ a / c dx = (∫ a dx) / c
+        OpValue::Div { left, right } if !right.contains_variable(var) => return Some(op_node(node,
+            OpValue::Div {
+                left: integrate_node(left, var, depth)?,
+                right: Rc::clone(right)
+            }
+        )),
+
+        // ∫ log(c, a) dx = (∫ ln(a) dx) / ln(c)
+        OpValue::Log { base, arg } if !base.contains_variable(var) => return Some(op_node(node,
+            OpValue::Div {
+                left: integrate_node(&function(Functions::NaturalLog, arg), var, depth)?,
+                right: function(Functions::NaturalLog, base)
+            }
+        )),
+
+        _ => ()
+    }
+
+    substitution(node, var)
+        .or_else(|| constant_in(node, var)
+            .map(|c| op_node(node, OpValue::Mul { left: c, right: op_node(node, OpValue::Variable(var)) }))
+        )
+        .or_else(|| monomial(node, var))
+        .or_else(|| by_parts(node, var, depth))
+}
+
+
+/// u-substitution: ∫ f(u) * k * u' dx = k * F(u), where F is a known antiderivative of f and k is constant.
+/// Without any other factor, this also integrates functions of linear arguments, where u' is constant.
+fn substitution<'a>(node: &Rc<OpNode<'a>>, var: &'a str) -> Option<Rc<OpNode<'a>>> {
+
+    // Pairs of f(u) and the rest of the integrand
+    let mut candidates = vec![(Rc::clone(node), number(node, 1_f64))];
+
+    match &node.value {
+        OpValue::Mul { left, right } => {
+            candidates.push((Rc::clone(left), Rc::clone(right)));
+            candidates.push((Rc::clone(right), Rc::clone(left)));
+        },
+        OpValue::Div { left, right } => {
+            let reciprocal = op_node(node, OpValue::Div { left: number(node, 1_f64), right: Rc::clone(right) });
+            candidates.push((Rc::clone(&reciprocal), Rc::clone(left)));
+            candidates.push((Rc::clone(left), reciprocal));
+        },
+        _ => ()
+    }
+
+    candidates.into_iter().find_map(|(outer, rest)| {
+
+        antiderivative_table(&outer, var).into_iter().find_map(|(u, antiderivative)| {
+
+            let du = derivatives::derive(&FunctionTree { root: Rc::clone(&u) }, var).simplify().root;
+            let k = constant_in(&op_node(node, OpValue::Div { left: Rc::clone(&rest), right: du }), var)?;
+
+            match k.value {
+                OpValue::Number(k) if k == 0_f64 || !k.is_finite() => None,
+                OpValue::Number(1_f64) => Some(antiderivative),
+                _ => Some(op_node(node, OpValue::Mul { left: k, right: antiderivative }))
+            }
+        })
+    })
+}
+
+
+/// Integration by parts: ∫ u * v' dx = u * v - ∫ u' * v dx.
+/// The factors are tried as u in LIATE order (logarithmic, inverse trigonometric, algebraic, trigonometric, exponential).
+fn by_parts<'a>(node: &Rc<OpNode<'a>>, var: &'a str, depth: usize) -> Option<Rc<OpNode<'a>>> {
+
+    if depth >= MAX_PARTS_DEPTH {
+        return None;
+    }
+
+    let OpValue::Mul { left, right } = &node.value else {
+        return None;
+    };
+
+    let mut factors = [left, right];
+    factors.sort_by_key(|factor| liate_rank(factor, var));
+
+    [(factors[0], factors[1]), (factors[1], factors[0])].into_iter().find_map(|(u, dv)| {
+
+        let v = simplified(&integrate_node(dv, var, depth + 1)?);
+        let du = derivatives::derive(&FunctionTree { root: Rc::clone(u) }, var).simplify().root;
+
+        let rest = integrate_node(&op_node(node, OpValue::Mul { left: du, right: Rc::clone(&v) }), var, depth + 1)?;
+
+        Some(op_node(node,
+            OpValue::Sub {
+                left: op_node(node, OpValue::Mul { left: Rc::clone(u), right: v }),
+                right: rest
+            }
+        ))
+    })
+}
+
+
+/// Preference of the factor as the u of an integration by parts, lower is preferred
+fn liate_rank(node: &OpNode, var: &str) -> u8 {
+    match &node.value {
+        OpValue::Function { func: Functions::NaturalLog | Functions::Log10 | Functions::Log2, .. } => 0,
+        OpValue::Log { .. } => 0,
+        OpValue::Function { func: Functions::Arcsin | Functions::Arccos | Functions::Arctan, .. } => 1,
+        OpValue::Function { func: Functions::Arcsecant | Functions::Arccosecant | Functions::Arccotangent, .. } => 1,
+        OpValue::Function { func: Functions::Arsinh | Functions::Arcosh | Functions::Artanh, .. } => 1,
+        OpValue::Function { func: Functions::Exp, .. } => 4,
+        OpValue::Function { .. } => 3,
+        OpValue::Pow { left, right: _ } if !left.contains_variable(var) => 4,
+        OpValue::Mul { left, right } if !left.contains_variable(var) => liate_rank(right, var),
+        _ => 2
+    }
+}
+
+
+/// If the integrand has the form f(u), return u and an antiderivative F(u) of f with respect to u.
+/// Some integrands can be seen as functions of different subexpressions, each one is a candidate.
+fn antiderivative_table<'a>(node: &Rc<OpNode<'a>>, var: &'a str) -> Vec<(Rc<OpNode<'a>>, Rc<OpNode<'a>>)> {
+
+    let n = |value: f64| number(node, value);
+    let new = |value: OpValue<'a>| op_node(node, value);
+
+    match &node.value {
+
+        // ∫ u du = u^2 / 2
+        OpValue::Variable(name) if *name == var => vec![(Rc::clone(node),
+            new(OpValue::Div { left: new(OpValue::Pow { left: Rc::clone(node), right: n(2_f64) }), right: n(2_f64) })
+        )],
+
+        OpValue::Function { func, arg: u } => {
+            let antiderivative = match func {
+
+                // ∫ sin(u) du = -cos(u)
+                Functions::Sin => new(OpValue::Mul { left: n(-1_f64), right: function(Functions::Cos, u) }),
+
+                // ∫ cos(u) du = sin(u)
+                Functions::Cos => function(Functions::Sin, u),
+
+                // ∫ tan(u) du = -ln(abs(cos(u)))
+                Functions::Tan => new(OpValue::Mul {
+                    left: n(-1_f64),
+                    right: ln_abs(&function(Functions::Cos, u))
+                }),
+
+                // ∫ sec(u) du = ln(abs(sec(u) + tan(u)))
+                Functions::Secant => ln_abs(&new(OpValue::Add {
+                    left: function(Functions::Secant, u),
+                    right: function(Functions::Tan, u)
+                })),
+
+                // ∫ csc(u) du = -ln(abs(csc(u) + cot(u)))
+                Functions::Cosecant => new(OpValue::Mul {
+                    left: n(-1_f64),
+                    right: ln_abs(&new(OpValue::Add {
+                        left: function(Functions::Cosecant, u),
+                        right: function(Functions::Cotangent, u)
+                    }))
+                }),
+
+                // ∫ cot(u) du = ln(abs(sin(u)))
+                Functions::Cotangent => ln_abs(&function(Functions::Sin, u)),
+
+                // ∫ asin(u) du = u * asin(u) + sqrt(1 - u^2)
+                Functions::Arcsin => new(OpValue::Add {
+                    left: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+                    right: function(Functions::SquareRoot, &new(OpValue::Sub {
+                        left: n(1_f64),
+                        right: new(OpValue::Pow { left: Rc::clone(u), right: n(2_f64) })
+                    }))
+                }),
+
+                // ∫ acos(u) du = u * acos(u) - sqrt(1 - u^2)
+                Functions::Arccos => new(OpValue::Sub {
+                    left: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+                    right: function(Functions::SquareRoot, &new(OpValue::Sub {
+                        left: n(1_f64),
+                        right: new(OpValue::Pow { left: Rc::clone(u), right: n(2_f64) })
+                    }))
+                }),
+
+                // ∫ atan(u) du = u * atan(u) - ln(1 + u^2) / 2
+                Functions::Arctan => new(OpValue::Sub {
+                    left: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+                    right: new(OpValue::Div {
+                        left: function(Functions::NaturalLog, &new(OpValue::Add {
+                            left: n(1_f64),
+                            right: new(OpValue::Pow { left: Rc::clone(u), right: n(2_f64) })
+                        })),
+                        right: n(2_f64)
+                    })
+                }),
+
+                // ∫ sqrt(u) du = 2 * u^(3/2) / 3
+                Functions::SquareRoot => new(OpValue::Div {
+                    left: new(OpValue::Mul {
+                        left: n(2_f64),
+                        right: new(OpValue::Pow { left: Rc::clone(u), right: n(1.5_f64) })
+                    }),
+                    right: n(3_f64)
+                }),
+
+                // ∫ cbrt(u) du = 3 * u * cbrt(u) / 4
+                Functions::CubeRoot => new(OpValue::Div {
+                    left: new(OpValue::Mul {
+                        left: n(3_f64),
+                        right: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) })
+                    }),
+                    right: n(4_f64)
+                }),
+
+                // ∫ acot(u) du = u * acot(u) + ln(1 + u^2) / 2
+                Functions::Arccotangent => new(OpValue::Add {
+                    left: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+                    right: new(OpValue::Div {
+                        left: function(Functions::NaturalLog, &new(OpValue::Add {
+                            left: n(1_f64),
+                            right: new(OpValue::Pow { left: Rc::clone(u), right: n(2_f64) })
+                        })),
+                        right: n(2_f64)
+                    })
+                }),
+
+                // ∫ asec(u) du = u * asec(u) - ln(abs(u) + sqrt(u^2 - 1))
+                // ∫ acsc(u) du = u * acsc(u) + ln(abs(u) + sqrt(u^2 - 1))
+                Functions::Arcsecant |
+                Functions::Arccosecant => {
+                    let product = new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) });
+                    let ln = function(Functions::NaturalLog, &new(OpValue::Add {
+                        left: function(Functions::Abs, u),
+                        right: function(Functions::SquareRoot, &new(OpValue::Sub {
+                            left: new(OpValue::Pow { left: Rc::clone(u), right: n(2_f64) }),
+                            right: n(1_f64)
+                        }))
+                    }));
+                    if *func == Functions::Arcsecant {
+                        new(OpValue::Sub { left: product, right: ln })
+                    } else {
+                        new(OpValue::Add { left: product, right: ln })
+                    }
+                },
+
+                // ∫ abs(u) du = u * abs(u) / 2
+                Functions::Abs => new(OpValue::Div {
+                    left: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+                    right: n(2_f64)
+                }),
+
+                // ∫ sign(u) du = abs(u)
+                Functions::Sign => function(Functions::Abs, u),
+
+                // ∫ heaviside(u) du = u * heaviside(u)
+                Functions::Heaviside => new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+
+                // The antiderivatives of floor(u) and ceil(u) are not elementary
+                Functions::Floor |
+                Functions::Ceil => return Vec::new(),
+
+                // ∫ ln(u) du = u * ln(u) - u
+                Functions::NaturalLog => new(OpValue::Sub {
+                    left: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+                    right: Rc::clone(u)
+                }),
+
+                // ∫ exp(u) du = exp(u)
+                Functions::Exp => Rc::clone(node),
+
+                // ∫ log10(u) du = u * log10(u) - u / ln(10)
+                Functions::Log10 => new(OpValue::Sub {
+                    left: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+                    right: new(OpValue::Div { left: Rc::clone(u), right: function(Functions::NaturalLog, &n(10_f64)) })
+                }),
+
+                // ∫ log2(u) du = u * log2(u) - u / ln(2)
+                Functions::Log2 => new(OpValue::Sub {
+                    left: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+                    right: new(OpValue::Div { left: Rc::clone(u), right: function(Functions::NaturalLog, &n(2_f64)) })
+                }),
+
+                // ∫ sinh(u) du = cosh(u)
+                Functions::Sinh => function(Functions::Cosh, u),
+
+                // ∫ cosh(u) du = sinh(u)
+                Functions::Cosh => function(Functions::Sinh, u),
+
+                // ∫ tanh(u) du = ln(cosh(u))
+                Functions::Tanh => function(Functions::NaturalLog, &function(Functions::Cosh, u)),
+
+                // ∫ asinh(u) du = u * asinh(u) - sqrt(u^2 + 1)
+                Functions::Arsinh => new(OpValue::Sub {
+                    left: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+                    right: function(Functions::SquareRoot, &new(OpValue::Add {
+                        left: new(OpValue::Pow { left: Rc::clone(u), right: n(2_f64) }),
+                        right: n(1_f64)
+                    }))
+                }),
+
+                // ∫ acosh(u) du = u * acosh(u) - sqrt(u^2 - 1)
+                Functions::Arcosh => new(OpValue::Sub {
+                    left: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+                    right: function(Functions::SquareRoot, &new(OpValue::Sub {
+                        left: new(OpValue::Pow { left: Rc::clone(u), right: n(2_f64) }),
+                        right: n(1_f64)
+                    }))
+                }),
+
+                // ∫ atanh(u) du = u * atanh(u) + ln(1 - u^2) / 2
+                Functions::Artanh => new(OpValue::Add {
+                    left: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) }),
+                    right: new(OpValue::Div {
+                        left: function(Functions::NaturalLog, &new(OpValue::Sub {
+                            left: n(1_f64),
+                            right: new(OpValue::Pow { left: Rc::clone(u), right: n(2_f64) })
+                        })),
+                        right: n(2_f64)
+                    })
+                }),
+            };
+            vec![(Rc::clone(u), antiderivative)]
+        },
+
+        // ∫ root(n, u) du = n * u * root(n, u) / (n + 1)
+        OpValue::MultiFunction { func: MultiFunctions::Root, args } if !args[0].contains_variable(var) => {
+            let (index, u) = (&args[0], &args[1]);
+            vec![(Rc::clone(u), new(OpValue::Div {
+                left: new(OpValue::Mul {
+                    left: Rc::clone(index),
+                    right: new(OpValue::Mul { left: Rc::clone(u), right: Rc::clone(node) })
+                }),
+                right: new(OpValue::Add { left: Rc::clone(index), right: n(1_f64) })
+            }))]
+        },
+
+        OpValue::Pow { left, right } if !right.contains_variable(var) => match (&left.value, &right.value) {
+
+            // ∫ sec(u)^2 du = tan(u)
+            (OpValue::Function { func: Functions::Secant, arg: u }, OpValue::Number(2_f64))
+                => vec![(Rc::clone(u), function(Functions::Tan, u))],
+
+            // ∫ csc(u)^2 du = -cot(u)
+            (OpValue::Function { func: Functions::Cosecant, arg: u }, OpValue::Number(2_f64))
+                => vec![(Rc::clone(u), new(OpValue::Mul { left: n(-1_f64), right: function(Functions::Cotangent, u) }))],
+
+            // ∫ u^-1 du = ln(abs(u))
+            (_, OpValue::Number(-1_f64))
+                => vec![(Rc::clone(left), ln_abs(left))],
+
+            // ∫ u^n du = u^(n + 1) / (n + 1)
+            _ => {
+                let exponent = new(OpValue::Add { left: Rc::clone(right), right: n(1_f64) });
+                vec![(Rc::clone(left), new(OpValue::Div {
+                    left: new(OpValue::Pow { left: Rc::clone(left), right: Rc::clone(&exponent) }),
+                    right: exponent
+                }))]
+            }
+        },
+
+        OpValue::Pow { left, right } if !left.contains_variable(var) => match left.value {
+
+            // ∫ e^u du = e^u
+            OpValue::Variable("e") => vec![(Rc::clone(right), Rc::clone(node))],
+
+            // ∫ c^u du = c^u / ln(c)
+            _ => vec![(Rc::clone(right), new(OpValue::Div {
+                left: Rc::clone(node),
+                right: function(Functions::NaturalLog, left)
+            }))],
+        },
+
+        OpValue::Div { left: c, right: denominator } if !c.contains_variable(var) => {
+
+            // ∫ c / u du = c * ln(abs(u))
+            let mut candidates = vec![(Rc::clone(denominator), ln_abs(denominator))];
+
+            match &denominator.value {
+
+                // ∫ c / (1 + u^2) du = c * atan(u)
+                OpValue::Add { left, right } => match (&left.value, &right.value) {
+                    (OpValue::Number(1_f64), OpValue::Pow { left: u, right: exponent }) |
+                    (OpValue::Pow { left: u, right: exponent }, OpValue::Number(1_f64))
+                        if matches!(exponent.value, OpValue::Number(2_f64))
+                        => candidates.push((Rc::clone(u), function(Functions::Arctan, u))),
+                    _ => ()
+                },
+
+                // ∫ c / sqrt(1 - u^2) du = c * asin(u)
+                OpValue::Function { func: Functions::SquareRoot, arg } => {
+                    if let OpValue::Sub { left, right } = &arg.value {
+                        if let (OpValue::Number(1_f64), OpValue::Pow { left: u, right: exponent }) = (&left.value, &right.value) {
+                            if matches!(exponent.value, OpValue::Number(2_f64)) {
+                                candidates.push((Rc::clone(u), function(Functions::Arcsin, u)));
+                            }
+                        }
+                    }
+                },
+
+                // ∫ c / u^n du = c * u^(1 - n) / (1 - n)
+                OpValue::Pow { left: u, right: exponent }
+                    if !exponent.contains_variable(var) && !matches!(exponent.value, OpValue::Number(1_f64))
+                 => {
+                    let exponent = new(OpValue::Sub { left: n(1_f64), right: Rc::clone(exponent) });
+                    candidates.push((Rc::clone(u), new(OpValue::Div {
+                        left: new(OpValue::Pow { left: Rc::clone(u), right: Rc::clone(&exponent) }),
+                        right: exponent
+                    })));
+                },
+
+                _ => ()
+            }
+
+            candidates.into_iter()
+                .map(|(u, antiderivative)| (u, new(OpValue::Mul { left: Rc::clone(c), right: antiderivative })))
+                .collect()
+        },
+
+        _ => Vec::new()
+    }
+}
+
+
+/// ∫ k * x^n dx = k * x^(n + 1) / (n + 1), for integrands that are monomials once expanded, like `x^2 / x`.
+/// The exponent is estimated numerically, so only integer and half-integer exponents are recognized.
+fn monomial<'a>(node: &Rc<OpNode<'a>>, var: &'a str) -> Option<Rc<OpNode<'a>>> {
+
+    let program = bytecode::compile(&FunctionTree { root: Rc::clone(node) });
+    let mut registers = program.new_registers();
+
+    let mut evaluate_at = |x: f64| {
+        let args = program.variables().iter()
+            .map(|name| if *name == var { x } else { 1_f64 })
+            .collect::<Vec<_>>();
+        program.run(&args, &mut registers)
+    };
+
+    // f(2x) / f(x) = 2^n
+    let exponent = (evaluate_at(2_f64 * SAMPLES[1]) / evaluate_at(SAMPLES[1])).log2();
+    let rounded = (exponent * 2_f64).round() / 2_f64;
+
+    if !exponent.is_finite() || (exponent - rounded).abs() > 1e-9 {
+        return None;
+    }
+
+    let x = op_node(node, OpValue::Variable(var));
+    let power = op_node(node, OpValue::Pow { left: Rc::clone(&x), right: number(node, rounded) });
+
+    let k = constant_in(&op_node(node, OpValue::Div { left: Rc::clone(node), right: Rc::clone(&power) }), var)?;
+
+    let (_, antiderivative) = antiderivative_table(&power, var).into_iter().next()?;
+
+    Some(op_node(node, OpValue::Mul { left: k, right: antiderivative }))
+}
+
+
+/// If the value of the expression does not depend on `var`, return it as a number,
+/// or as the simplified expression if it depends on other variables.
+/// The check is numeric, so that expressions the simplifier can't reduce, like `x / x`, are recognized.
+fn constant_in<'a>(node: &Rc<OpNode<'a>>, var: &str) -> Option<Rc<OpNode<'a>>> {
+
+    let program = bytecode::compile(&FunctionTree { root: Rc::clone(node) });
+    let mut registers = program.new_registers();
+
+    let others_count = program.variables().iter().filter(|name| **name != var).count();
+
+    // Every sample of the other variables must give the same value for every sample of `var`
+    for other in [1.37_f64, 2.19_f64] {
+
+        let values = SAMPLES.iter()
+            .map(|x| {
+                let args = program.variables().iter()
+                    .map(|name| if *name == var { *x } else { other })
+                    .collect::<Vec<_>>();
+                program.run(&args, &mut registers)
+            })
+            .filter(|value| value.is_finite())
+            .collect::<Vec<_>>();
+
+        let first = *values.first()?;
+
+        if values.len() < 3 || values.iter().any(|value| (value - first).abs() > 1e-9 * 1_f64.max(first.abs())) {
+            return None;
+        }
+
+        if others_count == 0 {
+            return Some(number(node, first));
+        }
+    }
+
+    Some(simplified(node))
+}
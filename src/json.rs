@@ -0,0 +1,84 @@
+use serde_json::{json, Map, Value};
+
+use crate::ast::{FunctionTree, OpNode, OpValue};
+use crate::errors::CalcError;
+use crate::notation::{self, Notation};
+
+
+/// Convert the node and its children into nested JSON objects mirroring `OpValue`
+pub fn node_to_json(node: &OpNode) -> Value {
+    match &node.value {
+        OpValue::Number(n) => json!({ "type": "Number", "value": n }),
+        OpValue::Add { left, right } => json!({ "type": "Add", "left": node_to_json(left), "right": node_to_json(right) }),
+        OpValue::Sub { left, right } => json!({ "type": "Sub", "left": node_to_json(left), "right": node_to_json(right) }),
+        OpValue::Mul { left, right } => json!({ "type": "Mul", "left": node_to_json(left), "right": node_to_json(right) }),
+        OpValue::Div { left, right } => json!({ "type": "Div", "left": node_to_json(left), "right": node_to_json(right) }),
+        OpValue::Pow { left, right } => json!({ "type": "Pow", "left": node_to_json(left), "right": node_to_json(right) }),
+        OpValue::Variable(name) => json!({ "type": "Variable", "name": name }),
+        OpValue::Function { func, arg } => json!({ "type": "Function", "func": func.to_string(), "arg": node_to_json(arg) }),
+        OpValue::Log { base, arg } => json!({ "type": "Log", "base": node_to_json(base), "arg": node_to_json(arg) }),
+        OpValue::MultiFunction { func, args } => json!({
+            "type": "MultiFunction",
+            "func": func.to_string(),
+            "args": args.iter().map(|arg| node_to_json(arg)).collect::<Vec<_>>()
+        }),
+        OpValue::Call { name, args, derivative } => json!({
+            "type": "Call",
+            "name": name,
+            "args": args.iter().map(|arg| node_to_json(arg)).collect::<Vec<_>>(),
+            "partials": derivative.partials
+        }),
+        OpValue::Piecewise { pieces } => json!({
+            "type": "Piecewise",
+            "pieces": pieces.iter().map(|piece| json!({
+                "value": node_to_json(&piece.value),
+                "condition": piece.condition.as_ref().map(|condition| json!({
+                    "left": node_to_json(&condition.left),
+                    "comparison": condition.comparison.to_string(),
+                    "right": node_to_json(&condition.right)
+                }))
+            })).collect::<Vec<_>>()
+        }),
+    }
+}
+
+
+/// The function written in every supported notation, plus its tree
+pub fn function_to_json(tree: &FunctionTree) -> Value {
+
+    let mut object = Map::new();
+
+    for notation in Notation::ALL {
+        object.insert(notation.name().to_string(), Value::String(notation::format_tree(tree, notation)));
+    }
+
+    object.insert("tree".to_string(), node_to_json(&tree.root));
+
+    Value::Object(object)
+}
+
+
+/// A number on the extended real line. JSON numbers can't be infinite, so infinities are the strings `inf` and `-inf`
+pub fn extended_number(value: f64) -> Value {
+    if value.is_infinite() {
+        Value::String(value.to_string())
+    } else {
+        json!(value)
+    }
+}
+
+
+pub fn error_to_json(error: &CalcError) -> Value {
+
+    let span = error.token.as_ref().map(|token| json!({
+        "token": token.string,
+        "start": token.column,
+        "end": token.end_column(),
+    }));
+
+    json!({
+        "kind": error.kind.to_string(),
+        "message": error.message,
+        "span": span,
+    })
+}
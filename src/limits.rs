@@ -0,0 +1,596 @@
+use std::fmt;
+use std::rc::Rc;
+
+use clap::ValueEnum;
+
+use crate::ast::{self, FunctionTree, OpNode, OpValue};
+use crate::bytecode;
+use crate::derivatives;
+use crate::errors::{self, CalcError};
+use crate::functions::Functions;
+use crate::notation::{self, Notation};
+use crate::series;
+
+
+/// The side from which the variable approaches the limit point
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Side {
+    Both,
+    /// From values smaller than the limit point
+    Left,
+    /// From values greater than the limit point
+    Right,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Both => write!(f, "both"),
+            Side::Left => write!(f, "left"),
+            Side::Right => write!(f, "right"),
+        }
+    }
+}
+
+
+/// Ways of resolving an indeterminate form
+#[derive(Clone, Copy)]
+pub enum Method {
+    /// Comparing the lowest-order terms of the Taylor series of the numerator and denominator
+    Series,
+    /// lim a / b = lim a' / b'
+    LHopital,
+    /// a * b = a / (1 / b) and a - b = (1 / b - 1 / a) / (1 / (a * b)), turning the form into a quotient
+    Quotient,
+    /// a ^ b = e ^ (b * ln(a)), turning the form into a product
+    Exponential,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Method::Series => write!(f, "series expansion"),
+            Method::LHopital => write!(f, "L'Hôpital's rule"),
+            Method::Quotient => write!(f, "rewriting as a quotient"),
+            Method::Exponential => write!(f, "rewriting as an exponential"),
+        }
+    }
+}
+
+
+/// An indeterminate form found while computing a limit, and how it was resolved
+pub struct Resolution {
+    /// The indeterminate form, like `0/0`
+    pub form: &'static str,
+    /// The subexpression in indeterminate form, in infix notation
+    pub expression: String,
+    pub method: Method,
+}
+
+
+pub struct Limit {
+    /// The value of the limit, possibly infinite
+    pub value: f64,
+    /// The side the limit point was approached from. A two-sided limit is one-sided when the function is only defined on one side
+    pub side: Side,
+    /// The indeterminate forms resolved, in the order they were found
+    pub resolutions: Vec<Resolution>,
+}
+
+
+/// Maximum number of nested rewritings of indeterminate forms, as L'Hôpital's rule doesn't always terminate
+const MAX_DEPTH: usize = 5;
+
+/// Highest order of the Taylor series compared when resolving `0/0`.
+/// Repeated derivatives grow quickly, so the order is kept low and L'Hôpital's rule handles the rest
+const SERIES_ORDER: usize = 4;
+
+/// Values smaller than this are considered zero, to absorb the rounding errors of evaluations like `sin(pi)`
+const EPSILON: f64 = 1e-12;
+
+/// Values larger than this, when the function is much smaller next to the limit point, are considered infinite,
+/// to absorb the rounding errors of evaluations at poles like `tan(pi / 2)`
+const POLE: f64 = 1e12;
+
+
+/// Why a limit couldn't be computed
+enum Failure {
+    /// A subexpression has no limit, like `sin(x)` at infinity, and it couldn't be squeezed
+    Undetermined(String),
+    Unresolved(&'static str),
+}
+
+
+/// Compute the limit of the function as `var` approaches `point`, which may be infinite
+pub fn limit<'a>(function: &FunctionTree<'a>, var: &'a str, point: f64, side: Side) -> Result<Limit, CalcError> {
+
+    if point.is_nan() {
+        return Err(errors::invalid_input("The limit point is not a number"));
+    }
+
+    // Infinity can only be approached from one side
+    let side = match (side, point) {
+        (_, f64::INFINITY) => Side::Left,
+        (_, f64::NEG_INFINITY) => Side::Right,
+        (side, _) => side
+    };
+
+    if let Some(other) = bytecode::compile(function).variables().iter().find(|name| **name != var) {
+        return Err(errors::invalid_input(
+            format!("Limits can only be computed for functions of a single variable, but `{}` is also a variable", other).as_str()
+        ));
+    }
+
+    let function = FunctionTree { root: simplified(&function.root) };
+
+    let limit = match defined_side(&function, point, side)? {
+
+        // The two-sided limit exists if both one-sided limits exist and are equal
+        Side::Both => {
+            let left = one_sided_limit(&function, var, point, Side::Left)?;
+            let right = one_sided_limit(&function, var, point, Side::Right)?;
+
+            if !same_limit(left.value, right.value) {
+                return Err(errors::invalid_input(
+                    format!("The limit does not exist: the limit from the left is {}, but the limit from the right is {}", left.value, right.value).as_str()
+                ));
+            }
+
+            Limit { side: Side::Both, ..right }
+        },
+
+        side => one_sided_limit(&function, var, point, side)?
+    };
+
+    Ok(limit)
+}
+
+
+fn one_sided_limit<'a>(function: &FunctionTree<'a>, var: &'a str, point: f64, side: Side) -> Result<Limit, CalcError> {
+
+    let mut solver = Solver {
+        var,
+        point,
+        side,
+        resolutions: Vec::new(),
+    };
+
+    match solver.limit_node(&function.root, 0) {
+
+        Ok(value) => Ok(Limit {
+            value: if value.abs() < EPSILON { 0_f64 } else { value },
+            side,
+            resolutions: solver.resolutions
+        }),
+
+        Err(Failure::Undetermined(reason)) => Err(errors::invalid_input(format!("The limit cannot be determined: {}", reason).as_str())),
+
+        Err(Failure::Unresolved(form)) => Err(errors::invalid_input(
+            format!("Cannot resolve the indeterminate form `{}` in the limit", form).as_str()
+        )),
+    }
+}
+
+
+/// A point close to the limit point, on the side it is approached from
+fn nearby(point: f64, side: Side) -> f64 {
+
+    if point.is_infinite() {
+        return point.signum() * 1e8;
+    }
+
+    let h = 1e-7 * 1_f64.max(point.abs());
+
+    match side {
+        Side::Left => point - h,
+        Side::Right => point + h,
+        Side::Both => unreachable!("Two-sided limits are computed as two one-sided limits"),
+    }
+}
+
+
+/// Evaluate the subtree at the given value of `var`, its only variable
+fn evaluate(node: &Rc<OpNode>, x: f64) -> f64 {
+    let program = bytecode::compile(&FunctionTree { root: Rc::clone(node) });
+    let args = vec![x; program.variables().len()];
+    program.run(&args, &mut program.new_registers())
+}
+
+
+/// If the function is only defined on one side of the limit point, like `sqrt(x)` at 0, the limit is one-sided
+fn defined_side(function: &FunctionTree, point: f64, side: Side) -> Result<Side, CalcError> {
+
+    if side != Side::Both {
+        return if evaluate(&function.root, nearby(point, side)).is_nan() {
+            Err(errors::invalid_input("The function is not defined near the limit point"))
+        } else {
+            Ok(side)
+        };
+    }
+
+    let [left, right] = [Side::Left, Side::Right].map(|side| !evaluate(&function.root, nearby(point, side)).is_nan());
+
+    match (left, right) {
+        (true, true) => Ok(Side::Both),
+        (true, false) => Ok(Side::Left),
+        (false, true) => Ok(Side::Right),
+        (false, false) => Err(errors::invalid_input("The function is not defined near the limit point")),
+    }
+}
+
+
+struct Solver<'a> {
+    var: &'a str,
+    point: f64,
+    side: Side,
+    resolutions: Vec<Resolution>,
+}
+
+impl<'a> Solver<'a> {
+
+    /// Limit of the subtree, computed from the limits of its operands.
+    /// Indeterminate forms are rewritten and their limit is computed recursively, up to `MAX_DEPTH` times.
+    fn limit_node(&mut self, node: &Rc<OpNode<'a>>, depth: usize) -> Result<f64, Failure> {
+
+        let value = match &node.value {
+
+            OpValue::Number(n) => *n,
+
+            OpValue::Variable(name) if *name == self.var => self.point,
+
+            OpValue::Variable(name) => ast::constant_value(name).expect("The function has no other variable"),
+
+            OpValue::Add { left, right } |
+            OpValue::Sub { left, right } => {
+                let (a, b) = (self.limit_node(left, depth)?, self.limit_node(right, depth)?);
+                let b = if matches!(node.value, OpValue::Sub { .. }) { -b } else { b };
+
+                if a.is_infinite() && b.is_infinite() && a.signum() != b.signum() {
+                    let (a, b) = match &node.value {
+                        OpValue::Sub { .. } => (Rc::clone(left), Rc::clone(right)),
+                        _ => (Rc::clone(left), new(node, OpValue::Mul { left: new(node, OpValue::Number(-1_f64)), right: Rc::clone(right) }))
+                    };
+                    let quotient = match (&a.value, &b.value) {
+                        // p / q - r / s = (p * s - r * q) / (q * s)
+                        (OpValue::Div { left: p, right: q }, OpValue::Div { left: r, right: s }) => new(node, OpValue::Div {
+                            left: new(node, OpValue::Sub {
+                                left: new(node, OpValue::Mul { left: Rc::clone(p), right: Rc::clone(s) }),
+                                right: new(node, OpValue::Mul { left: Rc::clone(r), right: Rc::clone(q) })
+                            }),
+                            right: new(node, OpValue::Mul { left: Rc::clone(q), right: Rc::clone(s) })
+                        }),
+                        // a - b = (1 / b - 1 / a) / (1 / (a * b))
+                        _ => new(node, OpValue::Div {
+                            left: new(node, OpValue::Sub { left: reciprocal(node, &b), right: reciprocal(node, &a) }),
+                            right: reciprocal(node, &new(node, OpValue::Mul { left: a, right: b }))
+                        }),
+                    };
+                    return self.resolve(node, "∞ - ∞", Method::Quotient, &quotient, depth);
+                }
+
+                a + b
+            },
+
+            OpValue::Mul { left, right } => {
+                let (a, b) = (self.bounded_limit(left, depth)?, self.bounded_limit(right, depth)?);
+
+                // A bounded factor without a limit times a factor going to zero goes to zero, like `x * sin(1 / x)` at 0
+                if a.is_nan() || b.is_nan() {
+                    return if is_zero(a) || is_zero(b) { Ok(0_f64) } else { Err(self.undetermined(node)) };
+                }
+
+                // A constant zero factor is exactly zero, so it doesn't give an indeterminate form
+                let (a_zero, b_zero) = (is_zero(a) && left.contains_variable(self.var), is_zero(b) && right.contains_variable(self.var));
+
+                match (a_zero, b_zero) {
+                    // a * b = b / (1 / a), with the factor going to infinity as numerator, like ln(x) / (1 / x)
+                    (true, false) if b.is_infinite() => {
+                        let quotient = new(node, OpValue::Div { left: Rc::clone(right), right: reciprocal(node, left) });
+                        return self.resolve(node, "0 * ∞", Method::Quotient, &quotient, depth);
+                    },
+                    (false, true) if a.is_infinite() => {
+                        let quotient = new(node, OpValue::Div { left: Rc::clone(left), right: reciprocal(node, right) });
+                        return self.resolve(node, "0 * ∞", Method::Quotient, &quotient, depth);
+                    },
+                    _ if is_zero(a) || is_zero(b) => 0_f64,
+                    _ => a * b
+                }
+            },
+
+            OpValue::Div { left, right } => {
+                let (a, b) = (self.bounded_limit(left, depth)?, self.limit_node(right, depth)?);
+
+                // A bounded numerator without a limit over a denominator going to infinity goes to zero, like `sin(x) / x` at infinity
+                if a.is_nan() {
+                    return if b.is_infinite() { Ok(0_f64) } else { Err(self.undetermined(node)) };
+                }
+
+                match (is_zero(a), is_zero(b)) {
+                    (true, true) if left.contains_variable(self.var) => return self.quotient(node, left, right, "0/0", depth),
+                    _ if a.is_infinite() && b.is_infinite() => return self.quotient(node, left, right, "∞/∞", depth),
+                    (true, _) => 0_f64,
+                    // c / 0 is infinite, with the sign of the quotient near the limit point
+                    (false, true) => a.signum() * self.sign_nearby(right) * f64::INFINITY,
+                    (false, false) => a / b,
+                }
+            },
+
+            OpValue::Pow { left, right } => {
+                let (a, b) = (self.limit_node(left, depth)?, self.limit_node(right, depth)?);
+
+                // A constant base or exponent is exact, so it doesn't give an indeterminate form
+                let form = if !left.contains_variable(self.var) || !right.contains_variable(self.var) {
+                    None
+                } else if is_zero(a) && is_zero(b) {
+                    Some("0^0")
+                } else if a == 1_f64 && b.is_infinite() {
+                    Some("1^∞")
+                } else if a.is_infinite() && is_zero(b) {
+                    Some("∞^0")
+                } else {
+                    None
+                };
+
+                if let Some(form) = form {
+                    // a ^ b = e ^ (b * ln(a))
+                    let exponent = new(node, OpValue::Mul {
+                        left: Rc::clone(right),
+                        right: new(node, OpValue::Function { func: Functions::NaturalLog, arg: Rc::clone(left) })
+                    });
+                    return self.resolve(node, form, Method::Exponential, &exponent, depth).map(f64::exp);
+                }
+
+                // 0 ^ -n is infinite, with the sign of the power near the limit point
+                if is_zero(a) && b < 0_f64 {
+                    self.sign_nearby(node) * f64::INFINITY
+                } else {
+                    ast::exec_pow(a, b)
+                }
+            },
+
+            OpValue::Function { func, arg } => {
+                let a = self.limit_node(arg, depth)?;
+                // Step functions jump where their argument reaches a kink, so their limit is their value on the side the argument comes from
+                if func.is_step() && func.kink().is_some_and(|kink| kink.contains(a)) {
+                    ast::exec_function(*func, evaluate(arg, nearby(self.point, self.side)))
+                } else {
+                    ast::exec_function(*func, a)
+                }
+            },
+
+            OpValue::MultiFunction { func, args } => {
+                let args = args.iter().map(|arg| self.limit_node(arg, depth)).collect::<Result<Vec<_>, _>>()?;
+                ast::exec_multi_function(*func, &args)
+            },
+
+            OpValue::Call { .. } => unreachable!("Functions containing abstract functions are rejected before being evaluated"),
+
+            // The limit of the piece that is active on the side the variable comes from
+            OpValue::Piecewise { pieces } => {
+                let x = nearby(self.point, self.side);
+                let active = ast::active_piece(pieces, |condition| Ok::<_, Failure>(
+                    condition.comparison.holds(evaluate(&condition.left, x), evaluate(&condition.right, x))
+                ))?;
+                match active {
+                    Some(piece) => self.limit_node(&piece.value, depth)?,
+                    None => f64::NAN
+                }
+            },
+
+            // log(b, a) = ln(a) / ln(b), which can be an indeterminate quotient when the base depends on the variable
+            OpValue::Log { base, arg } => {
+                let ln = |arg: &Rc<OpNode<'a>>| new(node, OpValue::Function { func: Functions::NaturalLog, arg: Rc::clone(arg) });
+                self.limit_node(&new(node, OpValue::Div { left: ln(arg), right: ln(base) }), depth)?
+            },
+        };
+
+        if value.is_nan() {
+            return Err(self.undetermined(node));
+        }
+
+        // The limit point is rounded, so a pole evaluates to a large finite value rather than to an infinity
+        if value.is_finite() && value.abs() > POLE && node.contains_variable(self.var) {
+            let nearby = evaluate(node, nearby(self.point, self.side));
+            if nearby.abs() < value.abs() * 1e-3 {
+                return Ok(nearby.signum() * f64::INFINITY);
+            }
+        }
+
+        Ok(value)
+    }
+
+
+    /// Limit of an operand, or NaN if it has no limit but is bounded, like `sin(x)` at infinity, so that it can be squeezed
+    fn bounded_limit(&mut self, node: &Rc<OpNode<'a>>, depth: usize) -> Result<f64, Failure> {
+        match self.limit_node(node, depth) {
+            Err(Failure::Undetermined(_)) if is_bounded(node) => Ok(f64::NAN),
+            result => result
+        }
+    }
+
+
+    fn undetermined(&self, node: &Rc<OpNode<'a>>) -> Failure {
+        Failure::Undetermined(format!("`{}` has no limit", notation::format_node(node, Notation::Infix)))
+    }
+
+
+    /// Resolve the indeterminate form of `node` by computing the limit of the equivalent `rewritten` expression instead.
+    /// The rewritten expression is not simplified, as the simplifier could turn it back into the indeterminate form.
+    fn resolve(&mut self, node: &Rc<OpNode<'a>>, form: &'static str, method: Method, rewritten: &Rc<OpNode<'a>>, depth: usize) -> Result<f64, Failure> {
+
+        if depth >= MAX_DEPTH {
+            return Err(Failure::Unresolved(form));
+        }
+
+        self.resolutions.push(Resolution {
+            form,
+            expression: notation::format_node(node, Notation::Infix),
+            method
+        });
+
+        self.limit_node(rewritten, depth + 1)
+    }
+
+
+    /// Resolve `0/0` and `∞/∞`, by series expansion if possible and by L'Hôpital's rule otherwise
+    fn quotient(&mut self, node: &Rc<OpNode<'a>>, numerator: &Rc<OpNode<'a>>, denominator: &Rc<OpNode<'a>>, form: &'static str, depth: usize) -> Result<f64, Failure> {
+
+        // ∞/∞ is also 0/0 as (1 / b) / (1 / a), which may have a series expansion
+        let series = if form == "0/0" {
+            self.compare_series(numerator, denominator)
+        } else {
+            self.compare_series(&simplified(&reciprocal(node, denominator)), &simplified(&reciprocal(node, numerator)))
+        };
+
+        if let Some(value) = series {
+            self.resolutions.push(Resolution {
+                form,
+                expression: notation::format_node(node, Notation::Infix),
+                method: Method::Series
+            });
+            return Ok(value);
+        }
+
+        let derivative = |node: &Rc<OpNode<'a>>| derivatives::derive(&FunctionTree { root: Rc::clone(node) }, self.var).simplify().root;
+
+        let rewritten = new(node, OpValue::Div { left: derivative(numerator), right: derivative(denominator) });
+
+        self.resolve(node, form, Method::LHopital, &rewritten, depth)
+    }
+
+
+    /// Limit of `numerator / denominator`, from the lowest-order nonzero terms of their Taylor series.
+    /// Limits at infinity are expanded in `t = 1 / x` around 0.
+    /// Return `None` if the series don't exist or have symbolic coefficients.
+    fn compare_series(&self, numerator: &Rc<OpNode<'a>>, denominator: &Rc<OpNode<'a>>) -> Option<f64> {
+
+        let (point, side) = match self.point {
+            f64::INFINITY => (0_f64, Side::Right),
+            f64::NEG_INFINITY => (0_f64, Side::Left),
+            point => (point, self.side)
+        };
+
+        let expanded = |node: &Rc<OpNode<'a>>| {
+            let function = FunctionTree { root: Rc::clone(node) };
+            if self.point.is_infinite() {
+                let x = new(node, OpValue::Variable(self.var));
+                FunctionTree { root: simplified(&function.substitute(self.var, &reciprocal(node, &x)).root) }
+            } else {
+                function
+            }
+        };
+
+        let leading_term = |node: &Rc<OpNode<'a>>| -> Option<(usize, f64)> {
+            let series = series::taylor(&expanded(node), self.var, point, SERIES_ORDER).ok()?;
+            series.coefficients.iter()
+                .enumerate()
+                .map(|(k, coefficient)| (k, evaluate(&coefficient.root, 0_f64)))
+                .find(|(_, coefficient)| !is_zero(*coefficient))
+                .filter(|_| series.coefficients.iter().all(|coefficient| !coefficient.root.contains_variable(self.var)))
+        };
+
+        let (n, a) = leading_term(numerator)?;
+        let (d, b) = leading_term(denominator)?;
+
+        // Near the point, the quotient behaves like a / b * (x - p)^(n - d)
+        let value = match n.cmp(&d) {
+            std::cmp::Ordering::Greater => 0_f64,
+            std::cmp::Ordering::Equal => a / b,
+            // Odd powers of (x - p) are negative on the left
+            std::cmp::Ordering::Less => match ((d - n) % 2 == 1, side) {
+                (true, Side::Left) => -(a / b).signum() * f64::INFINITY,
+                _ => (a / b).signum() * f64::INFINITY,
+            },
+        };
+
+        Some(value)
+    }
+
+
+    /// Sign of the subtree near the limit point, on the side it is approached from
+    fn sign_nearby(&self, node: &Rc<OpNode>) -> f64 {
+        evaluate(node, nearby(self.point, self.side)).signum()
+    }
+
+}
+
+
+fn is_zero(value: f64) -> bool {
+    value.abs() < EPSILON
+}
+
+
+/// Whether the values of the subtree are bounded wherever it is defined
+fn is_bounded(node: &OpNode) -> bool {
+    match &node.value {
+        OpValue::Number(_) => true,
+        OpValue::Function { func, .. } => func.is_bounded(),
+        OpValue::Add { left, right } |
+        OpValue::Sub { left, right } |
+        OpValue::Mul { left, right } => is_bounded(left) && is_bounded(right),
+        _ => false
+    }
+}
+
+
+/// Whether the one-sided limits agree, up to rounding errors
+fn same_limit(left: f64, right: f64) -> bool {
+    left == right || (left - right).abs() < EPSILON * 1_f64.max(left.abs())
+}
+
+
+fn new<'a>(source: &OpNode<'a>, value: OpValue<'a>) -> Rc<OpNode<'a>> {
+    Rc::new(OpNode {
+        source: Rc::clone(&source.source),
+        value
+    })
+}
+
+
+fn reciprocal<'a>(source: &OpNode<'a>, node: &Rc<OpNode<'a>>) -> Rc<OpNode<'a>> {
+    new(source, OpValue::Div { left: new(source, OpValue::Number(1_f64)), right: Rc::clone(node) })
+}
+
+
+/// Simplify the subtree and flatten its nested fractions, which come from the reciprocals taken while computing limits,
+/// like `1 / (1 / x)` when substituting `x = 1 / t`
+fn simplified<'a>(node: &Rc<OpNode<'a>>) -> Rc<OpNode<'a>> {
+    let simplified = FunctionTree { root: Rc::clone(node) }.simplify();
+    FunctionTree { root: flatten_fractions(&simplified.root) }.simplify().root
+}
+
+
+/// `a / (b / c) -> a * c / b`, everywhere in the subtree
+fn flatten_fractions<'a>(node: &Rc<OpNode<'a>>) -> Rc<OpNode<'a>> {
+
+    let value = match &node.value {
+
+        OpValue::Number(_) |
+        OpValue::Variable(_)
+            => return Rc::clone(node),
+
+        OpValue::Div { left, right } => {
+            let (left, right) = (flatten_fractions(left), flatten_fractions(right));
+            match &right.value {
+                // The new quotient may have a fraction as denominator too, like `a / ((p / q) / c)`
+                OpValue::Div { left: numerator, right: denominator } => return flatten_fractions(&new(node, OpValue::Div {
+                    left: new(node, OpValue::Mul { left, right: Rc::clone(denominator) }),
+                    right: Rc::clone(numerator)
+                })),
+                _ => OpValue::Div { left, right }
+            }
+        },
+
+        OpValue::Add { left, right } => OpValue::Add { left: flatten_fractions(left), right: flatten_fractions(right) },
+        OpValue::Sub { left, right } => OpValue::Sub { left: flatten_fractions(left), right: flatten_fractions(right) },
+        OpValue::Mul { left, right } => OpValue::Mul { left: flatten_fractions(left), right: flatten_fractions(right) },
+        OpValue::Pow { left, right } => OpValue::Pow { left: flatten_fractions(left), right: flatten_fractions(right) },
+        OpValue::Function { func, arg } => OpValue::Function { func: *func, arg: flatten_fractions(arg) },
+        OpValue::Log { base, arg } => OpValue::Log { base: flatten_fractions(base), arg: flatten_fractions(arg) },
+        OpValue::MultiFunction { func, args } => OpValue::MultiFunction { func: *func, args: args.iter().map(flatten_fractions).collect() },
+        OpValue::Piecewise { pieces } => OpValue::Piecewise { pieces: pieces.iter().map(|piece| piece.map(flatten_fractions)).collect() },
+        OpValue::Call { name, args, derivative } => OpValue::Call { name, args: args.iter().map(flatten_fractions).collect(), derivative: derivative.clone() },
+    };
+
+    new(node, value)
+}
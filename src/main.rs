@@ -2,43 +2,960 @@ mod cli_parser;
 mod tokenizer;
 mod ast;
 mod errors;
+#[cfg(test)]
 mod tests;
 mod functions;
 mod derivatives;
 mod parsing_tree;
+mod notation;
+mod json;
+mod batch;
+mod codegen;
+mod bytecode;
+mod dual;
+mod reverse;
+mod verification;
+mod integrals;
+mod quadrature;
+mod series;
+mod limits;
+mod trigonometry;
+mod piecewise;
+mod definitions;
+mod abstract_functions;
+mod implicit;
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 
-use cli_parser::CliParser;
+use cli_parser::{CliParser, Command, IntegrateArgs, SeriesArgs, LimitArgs, ImplicitArgs, OutputFormat, Point, GridAxis};
+use ast::FunctionTree;
+use errors::CalcError;
+use batch::BatchLine;
+use trigonometry::TrigStyle;
+use notation::Notation;
+use definitions::Definitions;
+use abstract_functions::DerivativeNotation;
+
+
+/// The results of every stage of the derivation of a function
+struct Derivation<'a> {
+    function: FunctionTree<'a>,
+    derivative: FunctionTree<'a>,
+    simplified: FunctionTree<'a>,
+    /// The derivation rules applied, if an explanation was requested
+    explanation: Vec<derivatives::Step>,
+    /// The simplifications applied, if a simplification trace was requested
+    simplification: Vec<ast::SimplificationStep>,
+    /// Subexpressions where the derivative does not exist, like `abs(x)` at 0
+    non_differentiable: Vec<derivatives::NonDifferentiable<'a>>,
+}
 
 
 fn main() {
     
     let args = CliParser::parse();
 
-    if !tokenizer::is_variable(&args.derivation_variable) {
-        errors::invalid_input(format!("Derivation variable `{}` is not a valid variable name", args.derivation_variable).as_str());
+    match &args.command {
+        Some(Command::Integrate(integrate_args)) => return integrate(integrate_args),
+        Some(Command::Series(series_args)) => return expand_series(series_args),
+        Some(Command::Limit(limit_args)) => return compute_limit(limit_args),
+        Some(Command::Implicit(implicit_args)) => return differentiate_implicitly(implicit_args),
+        None => ()
+    }
+
+    let batch = match (&args.input_function, &args.file) {
+        (_, Some(path)) => batch::read_file(path),
+        (Some(input), None) if input == "-" => {
+            reject_single_function_flags(&args);
+            batch::read_stdin()
+        },
+        (Some(input), None) => {
+            derive_single(&args, input);
+            return;
+        },
+        (None, None) => unreachable!("Clap requires either an input function or a file")
+    };
+
+    match batch {
+        Ok(lines) => derive_batch(&args, &lines),
+        Err(error) => exit_with_error(args.format, &args.derivation_variable, "", &error)
+    }
+
+}
+
+
+/// The flags that only apply to a single function conflict with `--file` in clap, but reading the functions from the
+/// standard input can't be told apart from a single function until the input is known, so they are checked here
+fn reject_single_function_flags(args: &CliParser) {
+
+    let flags = [
+        ("--explain", args.explain),
+        ("--explain-simplification", args.explain_simplification),
+        ("--at", !args.at.is_empty()),
+        ("--verify", args.verify),
+        ("--grid", !args.grid.is_empty()),
+        ("--emit", args.emit.is_some()),
+    ];
+
+    if let Some((flag, _)) = flags.iter().find(|(_, present)| *present) {
+        CliParser::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("the argument '{}' cannot be used when reading functions from the standard input with '-'", flag)
+            )
+            .exit();
+    }
+}
+
+
+/// Parse the input, replacing the calls to user-defined functions with their bodies
+fn parse_input<'a>(input: &'a str, definitions: &Definitions<'a>) -> Result<FunctionTree<'a>, CalcError> {
+    definitions.expand(&tokenizer::tokenize(input)?.parse()?)
+}
+
+
+/// Parse the `--define` options, or report the first invalid definition and terminate the program
+fn define_all<'a>(format: OutputFormat, var: &str, sources: &'a [String]) -> Definitions<'a> {
+    Definitions::parse_all(sources)
+        .unwrap_or_else(|(source, error)| exit_with_error(format, var, source, &error))
+}
+
+
+fn derive_input<'a>(input: &'a str, dvar: &'a str, definitions: &Definitions<'a>, explain: bool, explain_simplification: bool, trig_style: Option<TrigStyle>, derivative_notation: DerivativeNotation) -> Result<Derivation<'a>, CalcError> {
+
+    if !tokenizer::is_variable(dvar) {
+        return Err(errors::invalid_input(format!("Derivation variable `{}` is not a valid variable name", dvar).as_str()));
+    }
+
+    let function = parse_input(input, definitions)?;
+
+    let (derivative, explanation) = if explain {
+        derivatives::derive_explained(&function, dvar)
+    } else {
+        (derivatives::derive(&function, dvar), Vec::new())
+    };
+
+    let (simplified, simplification) = if explain_simplification {
+        derivative.simplify_explained()
+    } else {
+        (derivative.simplify(), Vec::new())
+    };
+
+    let simplified = match trig_style {
+        Some(style) => trigonometry::rewrite(&simplified, style),
+        None => simplified
+    };
+
+    let derivative = abstract_functions::rewrite(&derivative, derivative_notation);
+    let simplified = abstract_functions::rewrite(&simplified, derivative_notation);
+
+    let non_differentiable = derivatives::non_differentiable(&function, dvar);
+
+    Ok(Derivation {
+        function,
+        derivative,
+        simplified,
+        explanation,
+        simplification,
+        non_differentiable
+    })
+}
+
+
+fn derive_single(args: &CliParser, input: &str) {
+
+    let definitions = define_all(args.format, &args.derivation_variable, &args.define);
+
+    let derivation = match derive_input(input, &args.derivation_variable, &definitions, args.explain, args.explain_simplification, args.trig_style, args.derivative_notation) {
+        Ok(derivation) => derivation,
+        Err(error) => exit_with_error(args.format, &args.derivation_variable, input, &error)
+    };
+
+    // These outputs evaluate the function or its derivative, which have no value if they contain abstract functions
+    if args.emit.is_some() || !args.grid.is_empty() || args.verify || args.gradient || !args.at.is_empty() {
+        abstract_functions::require_defined(&derivation.function)
+            .unwrap_or_else(|error| exit_with_error(args.format, &args.derivation_variable, input, &error));
+    }
+
+    if let Some(language) = args.emit {
+        print!("{}", codegen::emit_function(&derivation.simplified, &args.emit_name, language));
+        return;
+    }
+
+    if !args.grid.is_empty() {
+        // The grid may leave out variables the derivative doesn't depend on, and then the domain of the function can't be checked
+        let table = tabulate(&derivation.simplified, &args.grid).map(|(columns, values)| match tabulate(&derivation.function, &args.grid) {
+            Ok((_, function_values)) => (columns, in_domain(values, &function_values)),
+            Err(_) => (columns, values),
+        });
+        match table {
+            Ok((columns, values)) => print_table(args, &columns, &values),
+            Err(error) => exit_with_error(args.format, &args.derivation_variable, input, &error)
+        }
+        return;
+    }
+
+    if args.verify {
+        verify(args, input, &derivation);
+        return;
+    }
+
+    if args.gradient {
+        print_gradients(args, input, &derivation.function);
+        return;
+    }
+
+    let values = match evaluate_at_points(&derivation.simplified, &args.at) {
+        Ok(values) => values,
+        Err(error) => exit_with_error(args.format, &args.derivation_variable, input, &error)
+    };
+
+    let values = match evaluate_at_points(&derivation.function, &args.at) {
+        Ok(function_values) => in_domain(values, &function_values),
+        Err(error) => exit_with_error(args.format, &args.derivation_variable, input, &error)
+    };
+
+    // The subexpression that is not differentiable at each point, if any
+    let undefined = args.at.iter()
+        .map(|point| non_differentiable_at(&derivation.non_differentiable, point))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|error| exit_with_error(args.format, &args.derivation_variable, input, &error));
+
+    // Cross-check the symbolic derivative with automatic differentiation, where the derivative exists
+    for ((point, value), _) in args.at.iter().zip(&values).zip(&undefined).filter(|((_, value), undefined)| undefined.is_none() && !value.is_nan()) {
+        match dual::evaluate(&derivation.function, &args.derivation_variable, &point.0) {
+            Ok(automatic) if !dual::agrees(*value, automatic.derivative) => eprintln!(
+                "warning: at {} the symbolic derivative is {}, but automatic differentiation gives {}",
+                point, value, automatic.derivative
+            ),
+            Ok(_) => (),
+            Err(error) => exit_with_error(args.format, &args.derivation_variable, input, &error)
+        }
+    }
+
+    match args.format {
+
+        OutputFormat::Text => {
+            print_text(args, &derivation);
+            for kink in &derivation.non_differentiable {
+                println!("note: {}", kink);
+            }
+            for ((point, value), undefined) in args.at.iter().zip(values).zip(undefined) {
+                match undefined {
+                    Some(kink) => println!("at {}: the derivative does not exist, {}", point, kink),
+                    None => println!("at {}: {}", point, value),
+                }
+            }
+        },
+
+        OutputFormat::Porcelain => {
+            print_porcelain(input, &args.derivation_variable, &derivation);
+            for ((point, value), undefined) in args.at.iter().zip(values).zip(undefined) {
+                match undefined {
+                    Some(kink) => println!("undefined\t{}\t{}", point, kink),
+                    None => println!("value\t{}\t{}", point, value),
+                }
+            }
+        },
+
+        OutputFormat::Json => {
+            let mut object = derivation_to_json(input, &args.derivation_variable, &derivation);
+            if !args.at.is_empty() {
+                object["values"] = args.at.iter().zip(values).zip(undefined)
+                    .map(|((point, value), undefined)| {
+                        let mut object = serde_json::json!({
+                            "point": point.0.iter().map(|(name, value)| (name.clone(), serde_json::json!(value))).collect::<serde_json::Map<_, _>>(),
+                            "value": value
+                        });
+                        if let Some(kink) = undefined {
+                            object["value"] = serde_json::Value::Null;
+                            object["undefined"] = serde_json::json!(kink.to_string());
+                        }
+                        object
+                    })
+                    .collect();
+            }
+            println!("{}", object);
+        },
+    }
+}
+
+
+/// Values of named variables at a list of points
+type Columns = Vec<(String, Vec<f64>)>;
+
+
+/// Evaluate the function over every combination of the grid values.
+/// Return the values of each grid variable at every point, along with the function values.
+fn tabulate(function: &FunctionTree, grid: &[GridAxis]) -> Result<(Columns, Vec<f64>), CalcError> {
+
+    let points = grid.iter().map(|axis| axis.count).product::<usize>();
+
+    // The last axis varies the fastest
+    let mut columns = Vec::with_capacity(grid.len());
+    let mut repeat = points;
+    for axis in grid {
+        repeat /= axis.count.max(1);
+        let axis_values = axis.values().collect::<Vec<_>>();
+        let column = (0..points)
+            .map(|i| axis_values[(i / repeat) % axis_values.len()])
+            .collect::<Vec<_>>();
+        columns.push((axis.variable.clone(), column));
+    }
+
+    let program = bytecode::compile(function);
+
+    let args = program.arguments(&columns)?.into_iter()
+        .map(Vec::as_slice)
+        .collect::<Vec<_>>();
+
+    let mut values = vec![0_f64; points];
+    program.run_batch(&args, &mut values);
+
+    Ok((columns, values))
+}
+
+
+fn print_table(args: &CliParser, columns: &[(String, Vec<f64>)], values: &[f64]) {
+
+    match args.format {
+
+        OutputFormat::Text |
+        OutputFormat::Porcelain
+         => {
+            if matches!(args.format, OutputFormat::Text) {
+                let header = columns.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join("\t");
+                println!("# {}\tderivative", header);
+            }
+            for (i, value) in values.iter().enumerate() {
+                for (_, column) in columns {
+                    print!("{}\t", column[i]);
+                }
+                println!("{}", value);
+            }
+        },
+
+        OutputFormat::Json => {
+            let mut object = columns.iter()
+                .map(|(name, column)| (name.clone(), serde_json::json!(column)))
+                .collect::<serde_json::Map<_, _>>();
+            object.insert("derivative".to_string(), serde_json::json!(values));
+            println!("{}", serde_json::Value::Object(object));
+        },
+    }
+}
+
+
+/// Check the derivative numerically and print the report.
+/// If any point doesn't match, the program exits with a non-zero status.
+fn verify(args: &CliParser, input: &str, derivation: &Derivation) {
+
+    let dvar = args.derivation_variable.as_str();
+
+    let points = if args.at.is_empty() {
+        verification::random_points(&derivation.function, dvar, args.samples, args.range, args.seed)
+    } else {
+        args.at.iter().map(|point| point.0.clone()).collect()
+    };
+
+    let report = verification::verify(&derivation.function, &derivation.simplified, &derivation.non_differentiable, dvar, &points, args.tolerance)
+        .unwrap_or_else(|error| exit_with_error(args.format, &args.derivation_variable, input, &error));
+
+    let format_point = |point: &[(String, f64)]| Point(point.to_vec()).to_string();
+
+    match args.format {
+
+        OutputFormat::Text => {
+            for mismatch in &report.mismatches {
+                println!("mismatch at {}: symbolic derivative is {}, numeric derivative is {}", format_point(&mismatch.point), mismatch.symbolic, mismatch.numeric);
+            }
+            println!("{} of {} points checked match", report.checked - report.mismatches.len(), report.checked);
+            if report.inconclusive != 0 {
+                println!("{} points are inconclusive, the numeric derivative did not converge there", report.inconclusive);
+            }
+        },
+
+        OutputFormat::Porcelain => {
+            println!("checked\t{}", report.checked);
+            println!("inconclusive\t{}", report.inconclusive);
+            for mismatch in &report.mismatches {
+                println!("mismatch\t{}\t{}\t{}", format_point(&mismatch.point), mismatch.symbolic, mismatch.numeric);
+            }
+        },
+
+        OutputFormat::Json => {
+            let mismatches = report.mismatches.iter()
+                .map(|mismatch| serde_json::json!({
+                    "point": mismatch.point.iter().map(|(name, value)| (name.clone(), serde_json::json!(value))).collect::<serde_json::Map<_, _>>(),
+                    "symbolic": mismatch.symbolic,
+                    "numeric": mismatch.numeric,
+                }))
+                .collect::<Vec<_>>();
+            println!("{}", serde_json::json!({
+                "input": input,
+                "variable": dvar,
+                "checked": report.checked,
+                "inconclusive": report.inconclusive,
+                "mismatches": mismatches,
+            }));
+        },
+    }
+
+    // A check without any point in the domain proves nothing, so it can't pass
+    if report.checked == 0 {
+        eprintln!("warning: none of the points are in the domain of the function, so the derivative could not be verified");
+        std::process::exit(1);
+    }
+
+    if !report.mismatches.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+
+fn print_gradients(args: &CliParser, input: &str, function: &FunctionTree) {
+
+    let gradients = args.at.iter()
+        .map(|point| reverse::gradient(function, &point.0))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|error| exit_with_error(args.format, &args.derivation_variable, input, &error));
+
+    match args.format {
+
+        OutputFormat::Text => {
+            for (point, gradient) in args.at.iter().zip(gradients) {
+                let partials = gradient.partials.iter()
+                    .map(|(var, partial)| format!("d/d{} = {}", var, partial))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                println!("at {}: {}", point, partials);
+            }
+        },
+
+        OutputFormat::Porcelain => {
+            for (point, gradient) in args.at.iter().zip(gradients) {
+                for (var, partial) in gradient.partials {
+                    println!("partial\t{}\t{}\t{}", point, var, partial);
+                }
+            }
+        },
+
+        OutputFormat::Json => {
+            let gradients = args.at.iter().zip(gradients)
+                .map(|(point, gradient)| serde_json::json!({
+                    "point": point.0.iter().map(|(name, value)| (name.clone(), serde_json::json!(value))).collect::<serde_json::Map<_, _>>(),
+                    "value": gradient.value,
+                    "partials": gradient.partials.iter().map(|(var, partial)| (var.to_string(), serde_json::json!(partial))).collect::<serde_json::Map<_, _>>(),
+                }))
+                .collect::<Vec<_>>();
+            println!("{}", serde_json::json!({
+                "input": input,
+                "gradients": gradients,
+            }));
+        },
+    }
+}
+
+
+/// Evaluate the function at every point, compiling it only once
+fn evaluate_at_points(function: &FunctionTree, points: &[Point]) -> Result<Vec<f64>, CalcError> {
+
+    if points.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let program = bytecode::compile(function);
+    let mut registers = program.new_registers();
+
+    points.iter()
+        .map(|point| {
+            let args = program.arguments(&point.0)?.into_iter().copied().collect::<Vec<_>>();
+            Ok(program.run(&args, &mut registers))
+        })
+        .collect()
+}
+
+
+/// The derivative may have a value where the function isn't defined, like `1 / (1 - x^2)` for `atanh(x)` at x=2.
+/// There the derivative doesn't exist, so its value is NaN, like the derivative of `sqrt(x)` at negative points
+fn in_domain(derivative_values: Vec<f64>, function_values: &[f64]) -> Vec<f64> {
+    derivative_values.into_iter()
+        .zip(function_values)
+        .map(|(value, function_value)| if function_value.is_nan() { f64::NAN } else { value })
+        .collect()
+}
+
+
+/// The first subexpression that is not differentiable at the point, if any
+fn non_differentiable_at<'d, 'a>(non_differentiable: &'d [derivatives::NonDifferentiable<'a>], point: &Point) -> Result<Option<&'d derivatives::NonDifferentiable<'a>>, CalcError> {
+    for kink in non_differentiable {
+        if kink.contains(&point.0)? {
+            return Ok(Some(kink));
+        }
+    }
+    Ok(None)
+}
+
+
+/// Derive every line and print one result per line.
+/// Lines defining a function, like `g(t) = t^2 + 1`, make it usable in the following lines and print nothing unless they are invalid.
+/// If any line fails, a summary is printed to stderr and the program exits with a non-zero status.
+fn derive_batch(args: &CliParser, lines: &[BatchLine]) {
+
+    let mut definitions = define_all(args.format, &args.derivation_variable, &args.define);
+
+    let (mut failed_functions, mut failed_definitions) = (0, 0);
+
+    for line in lines {
+
+        let dvar = line.variable.as_deref().unwrap_or(&args.derivation_variable);
+
+        let is_definition = definitions::is_definition(&line.function);
+
+        let result = if is_definition {
+            match definitions.define(&line.function) {
+                Ok(()) => continue,
+                Err(error) => Err(error)
+            }
+        } else {
+            derive_input(&line.function, dvar, &definitions, false, false, args.trig_style, args.derivative_notation)
+        };
+
+        match (&result, is_definition) {
+            (Ok(_), _) => (),
+            (Err(_), true) => failed_definitions += 1,
+            (Err(_), false) => failed_functions += 1,
+        }
+
+        match (args.format, result) {
+
+            (OutputFormat::Text, Ok(derivation))
+                => println!("{}", derivation.simplified),
+
+            (OutputFormat::Text, Err(error))
+                => match &error.token {
+                    Some(token) => println!("error: line {}, column {}: {}", line.number, token.column, error.message),
+                    None => println!("error: line {}: {}", line.number, error.message)
+                },
+
+            (OutputFormat::Porcelain, Ok(derivation))
+                => println!("{}\tok\t{}", line.number, derivation.simplified),
+
+            (OutputFormat::Porcelain, Err(error))
+                => println!("{}\terror\t{}", line.number, error.message),
+
+            (OutputFormat::Json, result) => {
+                let mut object = match result {
+                    Ok(derivation) => derivation_to_json(&line.function, dvar, &derivation),
+                    Err(error) => error_to_json(&line.function, dvar, &error)
+                };
+                object["line"] = serde_json::json!(line.number);
+                println!("{}", object);
+            },
+        }
+    }
+
+    let definition_lines = lines.iter().filter(|line| definitions::is_definition(&line.function)).count();
+
+    if failed_functions != 0 {
+        eprintln!("{} of {} functions failed", failed_functions, lines.len() - definition_lines);
+    }
+    if failed_definitions != 0 {
+        eprintln!("{} of {} definitions failed", failed_definitions, definition_lines);
+    }
+    if failed_functions + failed_definitions != 0 {
+        std::process::exit(1);
+    }
+}
+
+
+fn integrate_input<'a>(input: &'a str, var: &'a str, definitions: &Definitions<'a>) -> Result<FunctionTree<'a>, CalcError> {
+
+    if !tokenizer::is_variable(var) {
+        return Err(errors::invalid_input(format!("Integration variable `{}` is not a valid variable name", var).as_str()));
+    }
+
+    let function = parse_input(input, definitions)?;
+    abstract_functions::require_defined(&function)?;
+
+    integrals::integrate(&function, var)
+}
+
+
+/// Print an antiderivative of the function, checked by differentiating it back.
+/// With bounds, print the definite integral instead.
+fn integrate(args: &IntegrateArgs) {
+
+    let input = args.input_function.as_str();
+    let var = args.integration_variable.as_str();
+
+    let definitions = define_all(args.format, var, &args.define);
+
+    if let (Some(from), Some(to)) = (args.from, args.to) {
+        integrate_numerically(args, &definitions, from, to);
+        return;
+    }
+
+    let antiderivative = integrate_input(input, var, &definitions)
+        .unwrap_or_else(|error| exit_with_error(args.format, var, input, &error));
+
+    match args.format {
+
+        OutputFormat::Text => println!("{} + C", antiderivative),
+
+        OutputFormat::Porcelain => {
+            println!("input\t{}", input.trim());
+            println!("variable\t{}", var);
+            println!("antiderivative\t{}", antiderivative);
+        },
+
+        OutputFormat::Json => println!("{}", serde_json::json!({
+            "input": input,
+            "variable": var,
+            "antiderivative": json::function_to_json(&antiderivative),
+        })),
+    }
+}
+
+
+fn integrate_numerically(args: &IntegrateArgs, definitions: &Definitions, from: f64, to: f64) {
+
+    let input = args.input_function.as_str();
+    let var = args.integration_variable.as_str();
+
+    let estimate = parse_input(input, definitions)
+        .and_then(|function| abstract_functions::require_defined(&function).map(|_| function))
+        .and_then(|function| quadrature::integrate(&function, var, from, to, args.method, args.tolerance))
+        .unwrap_or_else(|error| exit_with_error(args.format, var, input, &error));
+
+    match args.format {
+
+        OutputFormat::Text => println!("{} ± {}", estimate.value, estimate.error),
+
+        OutputFormat::Porcelain => {
+            println!("input\t{}", input.trim());
+            println!("variable\t{}", var);
+            println!("from\t{}", from);
+            println!("to\t{}", to);
+            println!("value\t{}", estimate.value);
+            println!("error\t{}", estimate.error);
+            println!("evaluations\t{}", estimate.evaluations);
+        },
+
+        OutputFormat::Json => println!("{}", serde_json::json!({
+            "input": input,
+            "variable": var,
+            "from": from,
+            "to": to,
+            "value": estimate.value,
+            "error": estimate.error,
+            "evaluations": estimate.evaluations,
+        })),
+    }
+}
+
+
+/// Print the Taylor polynomial of the function
+fn expand_series(args: &SeriesArgs) {
+
+    let input = args.input_function.as_str();
+    let var = args.variable.as_str();
+
+    let definitions = define_all(args.format, var, &args.define);
+
+    let series = series_input(input, var, &definitions, args.around, args.order)
+        .unwrap_or_else(|error| exit_with_error(args.format, var, input, &error));
+
+    match args.format {
+
+        OutputFormat::Text => println!("{}", series.polynomial),
+
+        OutputFormat::Porcelain => {
+            println!("input\t{}", input.trim());
+            println!("variable\t{}", var);
+            println!("around\t{}", args.around);
+            println!("series\t{}", series.polynomial);
+            for (k, coefficient) in series.coefficients.iter().enumerate() {
+                println!("coefficient\t{}\t{}", k, coefficient);
+            }
+        },
+
+        OutputFormat::Json => println!("{}", serde_json::json!({
+            "input": input,
+            "variable": var,
+            "around": args.around,
+            "series": json::function_to_json(&series.polynomial),
+            "coefficients": series.coefficients.iter().map(|coefficient| coefficient.to_string()).collect::<Vec<_>>(),
+        })),
+    }
+}
+
+
+fn series_input<'a>(input: &'a str, var: &'a str, definitions: &Definitions<'a>, around: f64, order: usize) -> Result<series::Series<'a>, CalcError> {
+
+    if !tokenizer::is_variable(var) {
+        return Err(errors::invalid_input(format!("Series variable `{}` is not a valid variable name", var).as_str()));
+    }
+
+    let function = parse_input(input, definitions)?;
+    abstract_functions::require_defined(&function)?;
+
+    series::taylor(&function, var, around, order)
+}
+
+
+/// Print the limit of the function, along with the indeterminate forms resolved to compute it
+fn compute_limit(args: &LimitArgs) {
+
+    let input = args.input_function.as_str();
+    let var = args.variable.as_str();
+
+    let definitions = define_all(args.format, var, &args.define);
+
+    let limit = limit_input(input, var, &definitions, args.at, args.side)
+        .unwrap_or_else(|error| exit_with_error(args.format, var, input, &error));
+
+    match args.format {
+
+        OutputFormat::Text => {
+            for (i, resolution) in limit.resolutions.iter().enumerate() {
+                println!("{:>3}. {} in {}: {}", i + 1, resolution.form, resolution.expression, resolution.method);
+            }
+            if !limit.resolutions.is_empty() {
+                println!();
+            }
+            if args.side == limits::Side::Both && limit.side != limits::Side::Both && args.at.is_finite() {
+                println!("The function is only defined on the {} of {}, so this is the {} limit", limit.side, args.at, limit.side);
+            }
+            println!("{}", limit.value);
+        },
+
+        OutputFormat::Porcelain => {
+            println!("input\t{}", input.trim());
+            println!("variable\t{}", var);
+            println!("at\t{}", args.at);
+            println!("side\t{}", limit.side);
+            println!("limit\t{}", limit.value);
+            for resolution in &limit.resolutions {
+                println!("resolution\t{}\t{}\t{}", resolution.form, resolution.method, resolution.expression);
+            }
+        },
+
+        OutputFormat::Json => println!("{}", serde_json::json!({
+            "input": input,
+            "variable": var,
+            "at": json::extended_number(args.at),
+            "side": limit.side.to_string(),
+            "limit": json::extended_number(limit.value),
+            "resolutions": limit.resolutions.iter()
+                .map(|resolution| serde_json::json!({
+                    "form": resolution.form,
+                    "expression": resolution.expression,
+                    "method": resolution.method.to_string(),
+                }))
+                .collect::<Vec<_>>(),
+        })),
+    }
+}
+
+
+fn limit_input<'a>(input: &'a str, var: &'a str, definitions: &Definitions<'a>, point: f64, side: limits::Side) -> Result<limits::Limit, CalcError> {
+
+    if !tokenizer::is_variable(var) {
+        return Err(errors::invalid_input(format!("Limit variable `{}` is not a valid variable name", var).as_str()));
+    }
+
+    let function = parse_input(input, definitions)?;
+    abstract_functions::require_defined(&function)?;
+
+    limits::limit(&function, var, point, side)
+}
+
+
+/// Print the derivative of the dependent variable of the equation
+fn differentiate_implicitly(args: &ImplicitArgs) {
+
+    let input = args.input_equation.as_str();
+    let var = args.derivation_variable.as_str();
+    let dependent = args.dependent.as_str();
+
+    let definitions = define_all(args.format, var, &args.define);
+
+    let derivative = implicit_input(input, var, dependent, &definitions)
+        .unwrap_or_else(|error| exit_with_error(args.format, var, input, &error));
+
+    match args.format {
+
+        OutputFormat::Text => println!("d{}/d{} = {}", dependent, var, derivative),
+
+        OutputFormat::Porcelain => {
+            println!("input\t{}", input.trim());
+            println!("variable\t{}", var);
+            println!("dependent\t{}", dependent);
+            println!("derivative\t{}", derivative);
+        },
+
+        OutputFormat::Json => println!("{}", serde_json::json!({
+            "input": input,
+            "variable": var,
+            "dependent": dependent,
+            "derivative": json::function_to_json(&derivative),
+        })),
+    }
+}
+
+
+fn implicit_input<'a>(input: &'a str, var: &'a str, dependent: &'a str, definitions: &Definitions<'a>) -> Result<FunctionTree<'a>, CalcError> {
+
+    for name in [var, dependent] {
+        if !tokenizer::is_variable(name) {
+            return Err(errors::invalid_input(format!("`{}` is not a valid variable name", name).as_str()));
+        }
+    }
+
+    let equation = tokenizer::tokenize(input)?.parse_equation()?;
+    let equation = ast::Equation {
+        left: definitions.expand(&equation.left)?,
+        right: definitions.expand(&equation.right)?,
+    };
+
+    implicit::differentiate(&equation, var, dependent)
+}
+
+
+/// Report the error in the requested output format and terminate the program
+fn exit_with_error(format: OutputFormat, var: &str, input: &str, error: &CalcError) -> ! {
+
+    match format {
+
+        OutputFormat::Text => errors::report(error, input),
+
+        OutputFormat::Porcelain => {
+            println!("input\t{}", input.trim());
+            println!("variable\t{}", var);
+            println!("error\t{}", error.message);
+        },
+
+        OutputFormat::Json => println!("{}", error_to_json(input, var, error)),
+    }
+
+    std::process::exit(1);
+}
+
+
+/// Print the requested derivation stages in a human-readable way.
+/// Without any `--show-*` flag, only the simplified derivative is printed.
+fn print_text(args: &CliParser, derivation: &Derivation) {
+
+    let show_tree = args.show_tree && !args.quiet;
+    let show_unsimplified = args.show_unsimplified && !args.quiet;
+
+    for (i, step) in derivation.explanation.iter().enumerate() {
+        println!("{:>3}. {:indent$}{} on {}: {}", i + 1, "", step.rule, step.expression, step.rule.formula(), indent = step.depth * 2);
+    }
+    if !derivation.explanation.is_empty() {
+        println!();
+    }
+
+    for (i, step) in derivation.simplification.iter().enumerate() {
+        println!("{:>3}. {}: {} -> {}", i + 1, step.rule, step.before, step.after);
+    }
+    if !derivation.simplification.is_empty() {
+        println!();
+    }
+
+    if !show_tree && !show_unsimplified {
+        println!("{}", derivation.simplified);
+        return;
+    }
+
+    if show_tree {
+        println!("Original function tree:\n{:?}\n", derivation.function);
+    }
+
+    if show_unsimplified {
+        if show_tree {
+            println!("Derivative function tree:\n{:?}\n", derivation.derivative);
+        }
+        println!("Derivative function:\n{}\n", derivation.derivative);
     }
 
-    let tokens = tokenizer::tokenize(&args.input_function);
+    if show_tree {
+        println!("Simplified derivative function tree:\n{:?}\n", derivation.simplified);
+    }
+    println!("Simplified derivative function:\n{}", derivation.simplified);
+}
 
-    // println!("{}", tokens);
 
-    let function_tree = tokens.parse();
+/// Print every stage as a tab-separated `key value` line.
+/// The set and order of the keys is stable, regardless of the `--show-*` flags.
+fn print_porcelain(input: &str, dvar: &str, derivation: &Derivation) {
 
-    println!("Original function tree:\n{:?}", function_tree);
+    println!("input\t{}", input.trim());
+    println!("variable\t{}", dvar);
+    println!("derivative\t{}", derivation.derivative);
+    println!("simplified\t{}", derivation.simplified);
+    for step in &derivation.explanation {
+        println!("step\t{}\t{}\t{}", step.depth, step.rule, step.expression);
+    }
+    for step in &derivation.simplification {
+        println!("simplification\t{}\t{}\t{}", step.rule, step.before, step.after);
+    }
+    for kink in &derivation.non_differentiable {
+        println!("not_differentiable\t{}\t{}", notation::format_node(&kink.expression, Notation::Infix), kink.condition());
+    }
+}
 
-    let derivative_tree = derivatives::derive(&function_tree, &args.derivation_variable);
 
-    println!("Derived function tree:\n{:?}", derivative_tree);
+fn derivation_to_json(input: &str, dvar: &str, derivation: &Derivation) -> serde_json::Value {
 
-    println!("\n\nLinear derivative function:\n{}", derivative_tree);
+    let mut object = serde_json::json!({
+        "input": input,
+        "variable": dvar,
+        "function": json::function_to_json(&derivation.function),
+        "derivative": json::function_to_json(&derivation.derivative),
+        "simplified": json::function_to_json(&derivation.simplified),
+    });
 
-    let simplified_derivative = derivative_tree.simplify();
+    if !derivation.explanation.is_empty() {
+        object["explanation"] = derivation.explanation.iter()
+            .map(|step| serde_json::json!({
+                "rule": step.rule.to_string(),
+                "formula": step.rule.formula(),
+                "expression": step.expression,
+                "depth": step.depth,
+            }))
+            .collect();
+    }
 
-    println!("\nSimplified derivative function tree:\n{:?}", simplified_derivative);
+    if !derivation.non_differentiable.is_empty() {
+        object["not_differentiable"] = derivation.non_differentiable.iter()
+            .map(|kink| serde_json::json!({
+                "expression": notation::format_node(&kink.expression, Notation::Infix),
+                "where": kink.condition(),
+                "reason": kink.reason,
+            }))
+            .collect();
+    }
 
-    println!("\n\nLinear simplified derivative function:\n{}", simplified_derivative);
+    if !derivation.simplification.is_empty() {
+        object["simplification"] = derivation.simplification.iter()
+            .map(|step| serde_json::json!({
+                "rule": step.rule.to_string(),
+                "before": step.before,
+                "after": step.after,
+            }))
+            .collect();
+    }
 
+    object
 }
 
+
+fn error_to_json(input: &str, dvar: &str, error: &CalcError) -> serde_json::Value {
+    serde_json::json!({
+        "input": input,
+        "variable": dvar,
+        "error": json::error_to_json(error),
+    })
+}
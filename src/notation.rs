@@ -0,0 +1,463 @@
+use std::fmt::Write;
+
+use crate::ast::{Comparison, FunctionTree, OpNode, OpValue};
+use crate::functions::{Functions, MultiFunctions};
+
+
+/// Ways of writing a function tree as a linear string
+#[derive(Clone, Copy)]
+pub enum Notation {
+
+    /// Infix notation with only the necessary parentheses, e.g. `2 * x ^ 2 + 1`
+    Infix,
+
+    /// Infix notation with every operation in parentheses, e.g. `((2 * (x ^ 2)) + 1)`
+    Parenthesized,
+
+    /// Prefix notation in S-expression form, e.g. `(+ (* 2 (^ x 2)) 1)`
+    Prefix,
+
+    /// LaTeX math mode, e.g. `2 \cdot x^{2} + 1`
+    Latex,
+
+}
+
+impl Notation {
+
+    pub const ALL: [Notation; 4] = [Notation::Infix, Notation::Parenthesized, Notation::Prefix, Notation::Latex];
+
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Notation::Infix => "infix",
+            Notation::Parenthesized => "parenthesized",
+            Notation::Prefix => "prefix",
+            Notation::Latex => "latex",
+        }
+    }
+
+}
+
+
+/// Write `tree` as a string in the given notation
+pub fn format_tree(tree: &FunctionTree, notation: Notation) -> String {
+    format_node(&tree.root, notation)
+}
+
+
+/// Write the subtree as a string in the given notation
+pub fn format_node(node: &OpNode, notation: Notation) -> String {
+
+    let mut output = String::new();
+
+    match notation {
+        Notation::Infix => write_infix(node, &mut output),
+        Notation::Parenthesized => write!(output, "{}", node.value).unwrap(),
+        Notation::Prefix => write_prefix(node, &mut output),
+        Notation::Latex => write_latex(node, &mut output),
+    }
+
+    output
+}
+
+
+/// Binding strength of the operation, used to decide where parentheses are needed
+fn precedence(value: &OpValue) -> u8 {
+    match value {
+        OpValue::Add { .. } |
+        OpValue::Sub { .. }
+            => 1,
+        OpValue::Mul { .. } |
+        OpValue::Div { .. }
+            => 2,
+        // A negative number behaves like a unary minus
+        OpValue::Number(n) if n.is_sign_negative() => 2,
+        OpValue::Pow { .. } => 3,
+        // The quotient of the Leibniz notation is always in parentheses when it is an operand
+        OpValue::Call { derivative, .. } if derivative.is_leibniz() => 0,
+        OpValue::Number(_) |
+        OpValue::Variable(_) |
+        OpValue::Function { .. } |
+        OpValue::Log { .. } |
+        OpValue::MultiFunction { .. } |
+        OpValue::Call { .. } |
+        OpValue::Piecewise { .. }
+            => 4,
+    }
+}
+
+
+fn binary_operands<'n, 'a>(value: &'n OpValue<'a>) -> Option<(&'static str, &'n OpNode<'a>, &'n OpNode<'a>)> {
+    match value {
+        OpValue::Add { left, right } => Some(("+", left, right)),
+        OpValue::Sub { left, right } => Some(("-", left, right)),
+        OpValue::Mul { left, right } => Some(("*", left, right)),
+        OpValue::Div { left, right } => Some(("/", left, right)),
+        OpValue::Pow { left, right } => Some(("^", left, right)),
+        _ => None
+    }
+}
+
+
+fn write_infix_operand(node: &OpNode, needs_parens: bool, output: &mut String) {
+    if needs_parens {
+        output.push('(');
+        write_infix(node, output);
+        output.push(')');
+    } else {
+        write_infix(node, output);
+    }
+}
+
+
+fn write_infix(node: &OpNode, output: &mut String) {
+
+    if let Some((op, left, right)) = binary_operands(&node.value) {
+
+        let prec = precedence(&node.value);
+        let left_prec = precedence(&left.value);
+        let right_prec = precedence(&right.value);
+
+        // Exponentiation is right-associative, all the other operators are left-associative
+        let (left_parens, right_parens) = if matches!(node.value, OpValue::Pow { .. }) {
+            (left_prec <= prec, right_prec < prec)
+        } else {
+            (left_prec < prec, right_prec <= prec)
+        };
+
+        write_infix_operand(left, left_parens, output);
+        write!(output, " {} ", op).unwrap();
+        write_infix_operand(right, right_parens, output);
+        return;
+    }
+
+    match &node.value {
+        OpValue::Number(n) => write!(output, "{}", n).unwrap(),
+        OpValue::Variable(name) => output.push_str(name),
+        OpValue::Function { func, arg } => {
+            write!(output, "{}(", func).unwrap();
+            write_infix(arg, output);
+            output.push(')');
+        },
+        OpValue::Log { base, arg } => {
+            output.push_str("log(");
+            write_infix(base, output);
+            output.push_str(", ");
+            write_infix(arg, output);
+            output.push(')');
+        },
+        OpValue::MultiFunction { func, args } => {
+            write!(output, "{}(", func).unwrap();
+            for (i, arg) in args.iter().enumerate() {
+                if i != 0 {
+                    output.push_str(", ");
+                }
+                write_infix(arg, output);
+            }
+            output.push(')');
+        },
+        OpValue::Call { name, args, derivative } => {
+            if derivative.is_leibniz() {
+                output.push('d');
+                if derivative.order() > 1 {
+                    write!(output, "^{} ", derivative.order()).unwrap();
+                }
+            }
+            write!(output, "{}{}(", name, derivative.suffix(args.len())).unwrap();
+            for (i, arg) in args.iter().enumerate() {
+                if i != 0 {
+                    output.push_str(", ");
+                }
+                write_infix(arg, output);
+            }
+            output.push(')');
+            if derivative.is_leibniz() {
+                output.push_str(" /");
+                for (index, count) in derivative.groups() {
+                    output.push_str(" d");
+                    write_infix_operand(&args[index], !matches!(args[index].value, OpValue::Variable(_)), output);
+                    if count > 1 {
+                        write!(output, "^{}", count).unwrap();
+                    }
+                }
+            }
+        },
+        OpValue::Piecewise { pieces } => {
+            output.push('{');
+            for (i, piece) in pieces.iter().enumerate() {
+                if i != 0 {
+                    output.push_str("; ");
+                }
+                write_infix(&piece.value, output);
+                match &piece.condition {
+                    Some(condition) => {
+                        output.push_str(" if ");
+                        write_infix(&condition.left, output);
+                        write!(output, " {} ", condition.comparison).unwrap();
+                        write_infix(&condition.right, output);
+                    },
+                    None => output.push_str(" otherwise"),
+                }
+            }
+            output.push('}');
+        },
+        _ => unreachable!()
+    }
+}
+
+
+fn write_prefix(node: &OpNode, output: &mut String) {
+
+    if let Some((op, left, right)) = binary_operands(&node.value) {
+        write!(output, "({} ", op).unwrap();
+        write_prefix(left, output);
+        output.push(' ');
+        write_prefix(right, output);
+        output.push(')');
+        return;
+    }
+
+    match &node.value {
+        OpValue::Number(n) => write!(output, "{}", n).unwrap(),
+        OpValue::Variable(name) => output.push_str(name),
+        OpValue::Function { func, arg } => {
+            write!(output, "({} ", func).unwrap();
+            write_prefix(arg, output);
+            output.push(')');
+        },
+        OpValue::Log { base, arg } => {
+            output.push_str("(log ");
+            write_prefix(base, output);
+            output.push(' ');
+            write_prefix(arg, output);
+            output.push(')');
+        },
+        OpValue::MultiFunction { func, args } => {
+            write!(output, "({}", func).unwrap();
+            for arg in args.iter() {
+                output.push(' ');
+                write_prefix(arg, output);
+            }
+            output.push(')');
+        },
+        // S-expressions have no Leibniz notation
+        OpValue::Call { name, args, derivative } => {
+            write!(output, "({}{}", name, derivative.prime_suffix(args.len())).unwrap();
+            for arg in args.iter() {
+                output.push(' ');
+                write_prefix(arg, output);
+            }
+            output.push(')');
+        },
+        // Like the `cond` form of Lisp
+        OpValue::Piecewise { pieces } => {
+            output.push_str("(cond");
+            for piece in pieces.iter() {
+                match &piece.condition {
+                    Some(condition) => {
+                        write!(output, " (({} ", condition.comparison).unwrap();
+                        write_prefix(&condition.left, output);
+                        output.push(' ');
+                        write_prefix(&condition.right, output);
+                        output.push_str(") ");
+                    },
+                    None => output.push_str(" (else "),
+                }
+                write_prefix(&piece.value, output);
+                output.push(')');
+            }
+            output.push(')');
+        },
+        _ => unreachable!()
+    }
+}
+
+
+fn write_latex_operand(node: &OpNode, needs_parens: bool, output: &mut String) {
+    if needs_parens {
+        output.push_str("\\left(");
+        write_latex(node, output);
+        output.push_str("\\right)");
+    } else {
+        write_latex(node, output);
+    }
+}
+
+
+fn write_latex(node: &OpNode, output: &mut String) {
+
+    let prec = precedence(&node.value);
+
+    match &node.value {
+
+        OpValue::Number(n) => write!(output, "{}", n).unwrap(),
+
+        OpValue::Variable(name) => match *name {
+            "pi" => output.push_str("\\pi"),
+            name => output.push_str(name)
+        },
+
+        OpValue::Add { left, right } |
+        OpValue::Sub { left, right } |
+        OpValue::Mul { left, right }
+         => {
+            let op = match node.value {
+                OpValue::Add { .. } => " + ",
+                OpValue::Sub { .. } => " - ",
+                _ => " \\cdot "
+            };
+            write_latex_operand(left, precedence(&left.value) < prec, output);
+            output.push_str(op);
+            write_latex_operand(right, precedence(&right.value) <= prec, output);
+        },
+
+        // The fraction bar already groups its operands
+        OpValue::Div { left, right } => {
+            output.push_str("\\frac{");
+            write_latex(left, output);
+            output.push_str("}{");
+            write_latex(right, output);
+            output.push('}');
+        },
+
+        // The exponent is grouped by the braces
+        OpValue::Pow { left, right } => {
+            write_latex_operand(left, precedence(&left.value) <= prec, output);
+            output.push_str("^{");
+            write_latex(right, output);
+            output.push('}');
+        },
+
+        OpValue::Function { func: Functions::SquareRoot, arg } => {
+            output.push_str("\\sqrt{");
+            write_latex(arg, output);
+            output.push('}');
+        },
+
+        OpValue::Function { func: func @ (Functions::Abs | Functions::Floor | Functions::Ceil), arg } => {
+            let (open, close) = match func {
+                Functions::Abs => ("\\left|", "\\right|"),
+                Functions::Floor => ("\\left\\lfloor ", " \\right\\rfloor"),
+                _ => ("\\left\\lceil ", " \\right\\rceil"),
+            };
+            output.push_str(open);
+            write_latex(arg, output);
+            output.push_str(close);
+        },
+
+        OpValue::Function { func: Functions::CubeRoot, arg } => {
+            output.push_str("\\sqrt[3]{");
+            write_latex(arg, output);
+            output.push('}');
+        },
+
+        OpValue::MultiFunction { func: MultiFunctions::Root, args } => {
+            output.push_str("\\sqrt[");
+            write_latex(&args[0], output);
+            output.push_str("]{");
+            write_latex(&args[1], output);
+            output.push('}');
+        },
+
+        OpValue::Function { func, arg } => {
+            match func.latex_name() {
+                Some(name) => output.push_str(name),
+                None => write!(output, "\\operatorname{{{}}}", func).unwrap()
+            }
+            write_latex_operand(arg, true, output);
+        },
+
+        OpValue::Log { base, arg } => {
+            output.push_str("\\log_{");
+            write_latex(base, output);
+            output.push('}');
+            write_latex_operand(arg, true, output);
+        },
+
+        OpValue::MultiFunction { func, args } => {
+            match func.latex_name() {
+                Some(name) => output.push_str(name),
+                None => write!(output, "\\operatorname{{{}}}", func).unwrap()
+            }
+            output.push_str("\\left(");
+            for (i, arg) in args.iter().enumerate() {
+                if i != 0 {
+                    output.push_str(", ");
+                }
+                write_latex(arg, output);
+            }
+            output.push_str("\\right)");
+        },
+
+        // Single letters are written in italics like variables, longer names upright like the known functions
+        OpValue::Call { name, args, derivative } => {
+            // Partial derivatives are written with ∂, unless the function has a single argument
+            let d = if args.len() == 1 { "d" } else { "\\partial" };
+            if derivative.is_leibniz() {
+                output.push_str("\\frac{");
+                output.push_str(d);
+                if derivative.order() > 1 {
+                    write!(output, "^{{{}}}", derivative.order()).unwrap();
+                }
+                output.push(' ');
+            }
+            if name.chars().count() == 1 {
+                output.push_str(name);
+            } else {
+                write!(output, "\\operatorname{{{}}}", name).unwrap();
+            }
+            let suffix = derivative.suffix(args.len());
+            match suffix.strip_prefix('_') {
+                Some(indices) => write!(output, "_{{{}}}", indices).unwrap(),
+                None => output.push_str(&suffix),
+            }
+            output.push_str("\\left(");
+            for (i, arg) in args.iter().enumerate() {
+                if i != 0 {
+                    output.push_str(", ");
+                }
+                write_latex(arg, output);
+            }
+            output.push_str("\\right)");
+            if derivative.is_leibniz() {
+                output.push_str("}{");
+                for (i, (index, count)) in derivative.groups().into_iter().enumerate() {
+                    if i != 0 {
+                        output.push_str(" \\, ");
+                    }
+                    write!(output, "{} ", d).unwrap();
+                    write_latex_operand(&args[index], !matches!(args[index].value, OpValue::Variable(_)), output);
+                    if count > 1 {
+                        write!(output, "^{{{}}}", count).unwrap();
+                    }
+                }
+                output.push('}');
+            }
+        },
+
+        OpValue::Piecewise { pieces } => {
+            output.push_str("\\begin{cases} ");
+            for (i, piece) in pieces.iter().enumerate() {
+                if i != 0 {
+                    output.push_str(" \\\\ ");
+                }
+                write_latex(&piece.value, output);
+                match &piece.condition {
+                    Some(condition) => {
+                        output.push_str(" & \\text{if } ");
+                        write_latex(&condition.left, output);
+                        output.push_str(match condition.comparison {
+                            Comparison::Less => " < ",
+                            Comparison::LessOrEqual => " \\le ",
+                            Comparison::Greater => " > ",
+                            Comparison::GreaterOrEqual => " \\ge ",
+                        });
+                        write_latex(&condition.right, output);
+                    },
+                    None => output.push_str(" & \\text{otherwise}"),
+                }
+            }
+            output.push_str(" \\end{cases}");
+        },
+    }
+}
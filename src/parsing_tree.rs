@@ -4,8 +4,9 @@ use std::mem;
 use std::fmt;
 
 use crate::tokenizer::{SourceToken, Token, TokenValue};
-use crate::errors;
-use crate::ast::{FunctionTree, OpNode, OpValue};
+use crate::errors::{self, CalcError};
+use crate::ast::{Condition, Equation, FunctionTree, OpNode, OpValue, Piece};
+use crate::abstract_functions::Derivative;
 
 
 pub type Priority = u16;
@@ -16,6 +17,14 @@ pub enum ParsingNodeValue<'a> {
 
     Parsed (OpNode<'a>),
     Unparsed { token: Token<'a>, priority: Priority },
+    /// Comma-separated arguments, only valid in a function call
+    Arguments (Vec<OpNode<'a>>),
+    /// A comparison, only valid as the condition of a piece
+    Condition (Condition<'a>),
+    /// Pieces separated by semicolons, only valid between braces
+    Pieces (Vec<Piece<'a>>),
+    /// Two sides separated by `=`, only valid as the whole input
+    Equation (Equation<'a>),
     
     #[default]
     Placeholder
@@ -23,10 +32,16 @@ pub enum ParsingNodeValue<'a> {
 
 impl<'a> ParsingNodeValue<'a> {
 
-    pub fn get_source(&self) -> &'a SourceToken {
+    pub fn get_source(&self) -> &SourceToken<'a> {
         match self {
             ParsingNodeValue::Parsed(opnode) => &opnode.source,
             ParsingNodeValue::Unparsed { token, priority: _ } => &token.source,
+            // Argument lists always have at least two arguments
+            ParsingNodeValue::Arguments(args) => &args[0].source,
+            ParsingNodeValue::Condition(condition) => &condition.left.source,
+            // There is at least one piece
+            ParsingNodeValue::Pieces(pieces) => &pieces[0].value.source,
+            ParsingNodeValue::Equation(equation) => &equation.left.root.source,
 
             ParsingNodeValue::Placeholder => unreachable!()
         }
@@ -39,6 +54,10 @@ impl fmt::Display for ParsingNodeValue<'_> {
         match self {
             ParsingNodeValue::Parsed (node) => write!(f, "{:?}", node.value),
             ParsingNodeValue::Unparsed { token, priority } => write!(f, "{} (Priority: {})", token.value, priority),
+            ParsingNodeValue::Arguments(args) => write!(f, "Arguments ({})", args.len()),
+            ParsingNodeValue::Condition(condition) => write!(f, "Condition ({})", condition.comparison),
+            ParsingNodeValue::Pieces(pieces) => write!(f, "Pieces ({})", pieces.len()),
+            ParsingNodeValue::Equation(_) => write!(f, "Equation"),
             _ => unreachable!()
         }
     }
@@ -59,18 +78,15 @@ pub struct UnparsedTree<'a> {
 
     first_ptr: *mut ParsingNode<'a>,
     last_ptr: *mut ParsingNode<'a>,
-    // Keeping the source here is a questionable choice, but it's simple and it works
-    source: &'a str,
 
 }
 
 impl<'a> UnparsedTree<'a> {
 
-    pub fn new(source: &'a str) -> Self {
+    pub fn new() -> Self {
         Self {
             first_ptr: ptr::null_mut(),
             last_ptr: ptr::null_mut(),
-            source
         }
     }
 
@@ -101,6 +117,41 @@ impl<'a> UnparsedTree<'a> {
     }
 
 
+    /// The unparsed node with the highest priority, if any is left.
+    /// The pointer stays valid until the node is extracted from the list
+    fn highest_priority(&self) -> Option<*mut ParsingNode<'a>> {
+
+        let mut highest_priority = None;
+        
+        let mut node_ptr = self.first_ptr;
+
+        while let Some(node) = unsafe { node_ptr.as_ref() } {
+
+            let node_priority = if let ParsingNodeValue::Unparsed { token: _, priority } = node.value {
+                priority
+            } else {
+                // The node has already been parsed, it has no priority
+                node_ptr = node.next;
+                continue;
+            };
+
+            // TODO: This branching could be avoided by moving the else branch outside the loop
+            // This is fairly ok, though, because this program does not have to be extremely efficient
+            if let Some((_hp_node, hp)) = highest_priority {
+                if node_priority > hp {
+                    highest_priority = Some((node_ptr, node_priority));
+                }
+            } else {
+                highest_priority = Some((node_ptr, node_priority));
+            }
+
+            node_ptr = node.next;
+        }
+
+        highest_priority.map(|(node, _)| node)
+    }
+
+
     /// Extracts the node from the linked list, assuming it is in the list
     fn extract_node(&mut self, node_ptr: *mut ParsingNode<'a>) -> ParsingNodeValue<'a> {
         unsafe {
@@ -130,24 +181,49 @@ impl<'a> UnparsedTree<'a> {
 
 
     /// Build a parsed tree representation of the function
-    pub fn parse(mut self) -> FunctionTree<'a> {
+    pub fn parse(self) -> Result<FunctionTree<'a>, CalcError> {
+        match self.parse_root()? {
+            ParsingNodeValue::Parsed(opnode) => Ok(FunctionTree { root: Rc::new(opnode) }),
+            ParsingNodeValue::Equation(equation)
+                => Err(errors::parsing_error(&equation.left.root.source, "Expected a function, but this is an equation.")),
+            _ => unreachable!()
+        }
+    }
+
+
+    /// Build the parsed trees of both sides of an equation, like `g(t) = t^2 + 1`
+    pub fn parse_equation(self) -> Result<Equation<'a>, CalcError> {
+        match self.parse_root()? {
+            ParsingNodeValue::Equation(equation) => Ok(equation),
+            ParsingNodeValue::Parsed(opnode)
+                => Err(errors::parsing_error(&opnode.source, "Expected an equation, with its two sides separated by `=`.")),
+            _ => unreachable!()
+        }
+    }
+
+
+    /// Parse the tokens until only the root node is left, which is either a function or an equation
+    fn parse_root(mut self) -> Result<ParsingNodeValue<'a>, CalcError> {
 
         if self.first_ptr.is_null() {
-            errors::invalid_input("Cannot build the AST of an empty function.");
+            return Err(errors::invalid_input("Cannot build the AST of an empty function."));
         }
 
         // The linked list is now guaranteed not to be empty, there's no reason to worry about null pointers
 
-        while let Some(node) = get_highest_priority(self.first_ptr) {
+        while let Some(node_ptr) = self.highest_priority() {
+
+            // The node stays in the list while it's being parsed, only its neighbours are extracted
+            let node = unsafe { &mut *node_ptr };
 
-            // Assume the node hasn't been parsed yet. If it had been parsed, the `get_highest_priority` function should not have returned it
+            // Assume the node hasn't been parsed yet. If it had been parsed, the `highest_priority` method should not have returned it
             let token = if let ParsingNodeValue::Unparsed { token, priority: _ } = &node.value { token } else { unreachable!() };
 
 
             macro_rules! extract_right {
                 (parsed) => {{
                     if node.next.is_null() {
-                        errors::parsing_error(&token.source, self.source, "Expected an operand to the right, but none was found");
+                        return Err(errors::parsing_error(&token.source, "Expected an operand to the right, but none was found"));
                     }
 
                     match self.extract_node(node.next) {
@@ -155,7 +231,34 @@ impl<'a> UnparsedTree<'a> {
                         ParsingNodeValue::Parsed(opnode) => opnode,
 
                         ParsingNodeValue::Unparsed { token, priority: _ }
-                            => errors::parsing_error(&token.source, &self.source, "Invalid syntax, this token was not expected."),
+                            => return Err(errors::parsing_error(&token.source, "Invalid syntax, this token was not expected.")),
+
+                        ParsingNodeValue::Arguments(args)
+                            => return Err(errors::parsing_error(&args[0].source, "Argument lists are only allowed in function calls.")),
+
+                        misplaced @ (ParsingNodeValue::Condition(_) | ParsingNodeValue::Pieces(_) | ParsingNodeValue::Equation(_))
+                            => return Err(misplaced_error(&misplaced)),
+
+                        ParsingNodeValue::Placeholder => unreachable!(),
+                    }
+                }};
+
+                (arguments) => {{
+                    if node.next.is_null() {
+                        return Err(errors::parsing_error(&token.source, "Expected an argument to the right, but none was found"));
+                    }
+
+                    match self.extract_node(node.next) {
+
+                        ParsingNodeValue::Parsed(opnode) => vec![opnode],
+
+                        ParsingNodeValue::Arguments(args) => args,
+
+                        ParsingNodeValue::Unparsed { token, priority: _ }
+                            => return Err(errors::parsing_error(&token.source, "Invalid syntax, this token was not expected.")),
+
+                        misplaced @ (ParsingNodeValue::Condition(_) | ParsingNodeValue::Pieces(_) | ParsingNodeValue::Equation(_))
+                            => return Err(misplaced_error(&misplaced)),
 
                         ParsingNodeValue::Placeholder => unreachable!(),
                     }
@@ -163,27 +266,59 @@ impl<'a> UnparsedTree<'a> {
 
                 (unparsed) => {{
                     if node.next.is_null() {
-                        errors::parsing_error(&token.source, self.source, "Expected an operand to the right, but none was found");
+                        return Err(errors::parsing_error(&token.source, "Expected an operand to the right, but none was found"));
                     }
 
                     match self.extract_node(node.next) {
 
                         ParsingNodeValue::Parsed(opnode)
-                            => errors::parsing_error(&opnode.source, &self.source, "Invalid syntax, this token was not expected."),
+                            => return Err(errors::parsing_error(&opnode.source, "Invalid syntax, this token was not expected.")),
 
                         ParsingNodeValue::Unparsed { token, priority: _ }
                             => token,
 
+                        ParsingNodeValue::Arguments(args)
+                            => return Err(errors::parsing_error(&args[0].source, "Invalid syntax, this token was not expected.")),
+
+                        misplaced @ (ParsingNodeValue::Condition(_) | ParsingNodeValue::Pieces(_) | ParsingNodeValue::Equation(_))
+                            => return Err(misplaced_error(&misplaced)),
+
                         ParsingNodeValue::Placeholder => unreachable!(),
                     }
                 }};
+
+                (condition) => {{
+                    if node.next.is_null() {
+                        return Err(errors::parsing_error(&token.source, "Expected a condition to the right, but none was found"));
+                    }
+
+                    match self.extract_node(node.next) {
+
+                        ParsingNodeValue::Condition(condition) => condition,
+
+                        other => return Err(errors::parsing_error(other.get_source(), "Expected a condition, like `x > 0`.")),
+                    }
+                }};
+
+                (pieces) => {{
+                    if node.next.is_null() {
+                        return Err(errors::parsing_error(&token.source, "Expected a piece to the right, but none was found"));
+                    }
+
+                    match self.extract_node(node.next) {
+
+                        ParsingNodeValue::Pieces(pieces) => pieces,
+
+                        other => return Err(errors::parsing_error(other.get_source(), "Expected a piece, like `x if x > 0` or `0 otherwise`.")),
+                    }
+                }};
             }
 
 
             macro_rules! extract_left {
                 (parsed) => {{
                     if node.prev.is_null() {
-                        errors::parsing_error(&token.source, self.source, "Expected an operand to the left, but none was found");
+                        return Err(errors::parsing_error(&token.source, "Expected an operand to the left, but none was found"));
                     }
 
                     match self.extract_node(node.prev) {
@@ -191,11 +326,52 @@ impl<'a> UnparsedTree<'a> {
                         ParsingNodeValue::Parsed(opnode) => opnode,
 
                         ParsingNodeValue::Unparsed { token, priority: _ }
-                            => errors::parsing_error(&token.source, &self.source, "Invalid syntax, this token was not expected."),
+                            => return Err(errors::parsing_error(&token.source, "Invalid syntax, this token was not expected.")),
+
+                        ParsingNodeValue::Arguments(args)
+                            => return Err(errors::parsing_error(&args[0].source, "Argument lists are only allowed in function calls.")),
+
+                        misplaced @ (ParsingNodeValue::Condition(_) | ParsingNodeValue::Pieces(_) | ParsingNodeValue::Equation(_))
+                            => return Err(misplaced_error(&misplaced)),
 
                         ParsingNodeValue::Placeholder => unreachable!(),
                     }
                 }};
+
+                // A single operand or the arguments already separated by commas
+                (arguments) => {{
+                    if node.prev.is_null() {
+                        return Err(errors::parsing_error(&token.source, "Expected an argument to the left, but none was found"));
+                    }
+
+                    match self.extract_node(node.prev) {
+
+                        ParsingNodeValue::Parsed(opnode) => vec![opnode],
+
+                        ParsingNodeValue::Arguments(args) => args,
+
+                        ParsingNodeValue::Unparsed { token, priority: _ }
+                            => return Err(errors::parsing_error(&token.source, "Invalid syntax, this token was not expected.")),
+
+                        misplaced @ (ParsingNodeValue::Condition(_) | ParsingNodeValue::Pieces(_) | ParsingNodeValue::Equation(_))
+                            => return Err(misplaced_error(&misplaced)),
+
+                        ParsingNodeValue::Placeholder => unreachable!(),
+                    }
+                }};
+
+                (pieces) => {{
+                    if node.prev.is_null() {
+                        return Err(errors::parsing_error(&token.source, "Expected a piece to the left, but none was found"));
+                    }
+
+                    match self.extract_node(node.prev) {
+
+                        ParsingNodeValue::Pieces(pieces) => pieces,
+
+                        other => return Err(errors::parsing_error(other.get_source(), "Expected a piece, like `x if x > 0` or `0 otherwise`.")),
+                    }
+                }};
             }
 
             macro_rules! parse_binary {
@@ -222,28 +398,119 @@ impl<'a> UnparsedTree<'a> {
 
                 TokenValue::ParenOpen => {
                     
-                    let content = extract_right!(parsed);
+                    let mut content = extract_right!(arguments);
 
                     let closing_paren = extract_right!(unparsed);
                     if !matches!(closing_paren.value, TokenValue::ParenClose) {
-                        errors::parsing_error(&closing_paren.source, self.source, "Expected a closing parenthesis.");
+                        return Err(errors::parsing_error(&closing_paren.source, "Expected a closing parenthesis."));
+                    }
+
+                    // The arguments of a function call are kept together until the function is parsed
+                    if content.len() > 1 {
+                        ParsingNodeValue::Arguments(content)
+                    } else {
+                        ParsingNodeValue::Parsed(OpNode {
+                            source: Rc::clone(&token.source),
+                            value: content.remove(0).value, // Drop the parentheses, the tree structure will keep track of the operator hierarchy
+                        })
                     }
-                    
-                    ParsingNodeValue::Parsed(OpNode {
-                        source: Rc::clone(&token.source),
-                        value: content.value, // Drop the parentheses, the tree structure will keep track of the operator hierarchy
-                    })
                 },
 
-                TokenValue::Identifier(name) => {
+                TokenValue::Comma => {
+
+                    let mut args = extract_left!(arguments);
+                    args.push(extract_right!(parsed));
+
+                    ParsingNodeValue::Arguments(args)
+                },
+
+                TokenValue::Equals => {
 
-                    // TODO: Can either be a function or a variable
+                    let left = FunctionTree { root: Rc::new(extract_left!(parsed)) };
+                    let right = FunctionTree { root: Rc::new(extract_right!(parsed)) };
+
+                    ParsingNodeValue::Equation(Equation { left, right })
+                },
+
+                TokenValue::Comparison(comparison) => {
+
+                    let left = Rc::new(extract_left!(parsed));
+                    let right = Rc::new(extract_right!(parsed));
+
+                    ParsingNodeValue::Condition(Condition { left, comparison, right })
+                },
+
+                TokenValue::If => {
+
+                    let value = Rc::new(extract_left!(parsed));
+                    let condition = extract_right!(condition);
+
+                    ParsingNodeValue::Pieces(vec![Piece { value, condition: Some(condition) }])
+                },
+
+                TokenValue::Otherwise => {
+
+                    let value = Rc::new(extract_left!(parsed));
+
+                    ParsingNodeValue::Pieces(vec![Piece { value, condition: None }])
+                },
+
+                TokenValue::Semicolon => {
+
+                    let mut pieces = extract_left!(pieces);
+                    pieces.append(&mut extract_right!(pieces));
+
+                    ParsingNodeValue::Pieces(pieces)
+                },
+
+                TokenValue::BraceOpen => {
+
+                    let pieces = extract_right!(pieces);
+
+                    let closing_brace = extract_right!(unparsed);
+                    if !matches!(closing_brace.value, TokenValue::BraceClose) {
+                        return Err(errors::parsing_error(&closing_brace.source, "Expected a closing brace."));
+                    }
+
+                    // Any piece after one without a condition could never be reached
+                    if let Some(piece) = pieces[..pieces.len() - 1].iter().find(|piece| piece.condition.is_none()) {
+                        return Err(errors::parsing_error(&piece.value.source, "Only the last piece can be `otherwise`."));
+                    }
 
                     ParsingNodeValue::Parsed(OpNode {
                         source: Rc::clone(&token.source),
-                        value: OpValue::Variable(name)
+                        value: OpValue::Piecewise { pieces: pieces.into_boxed_slice() }
                     })
                 },
+
+                TokenValue::Identifier(name) => {
+
+                    // Parentheses have a higher priority, so if the identifier is followed by parentheses, they are already parsed.
+                    // In that case the identifier is a call to a function defined by the user, otherwise it is a variable
+                    let call = unsafe { node.next.as_ref() }.is_some_and(|next| match &next.value {
+                        ParsingNodeValue::Arguments(_) => true,
+                        ParsingNodeValue::Parsed(opnode) => opnode.source.string == "(",
+                        _ => false
+                    });
+
+                    if call {
+                        let args = extract_right!(arguments);
+
+                        ParsingNodeValue::Parsed(OpNode {
+                            source: Rc::clone(&token.source),
+                            value: OpValue::Call {
+                                name,
+                                args: args.into_iter().map(Rc::new).collect(),
+                                derivative: Derivative::default()
+                            }
+                        })
+                    } else {
+                        ParsingNodeValue::Parsed(OpNode {
+                            source: Rc::clone(&token.source),
+                            value: OpValue::Variable(name)
+                        })
+                    }
+                },
                 
                 TokenValue::Number(n)
                  => ParsingNodeValue::Parsed (OpNode {
@@ -253,41 +520,84 @@ impl<'a> UnparsedTree<'a> {
 
                 TokenValue::Function(func) => {
 
-                    let arg = extract_right!(parsed);
+                    let mut args = extract_right!(arguments);
+                    if args.len() != 1 {
+                        return Err(errors::parsing_error(&token.source, errors::arity_message(func.to_string().as_str(), 1, args.len()).as_str()));
+                    }
                     
                     ParsingNodeValue::Parsed(OpNode {
                         source: Rc::clone(&token.source),
                         value: OpValue::Function { 
                             func,
+                            arg: Rc::new(args.remove(0))
+                        }
+                    })
+                },
+
+                TokenValue::MultiFunction(func) => {
+
+                    let args = extract_right!(arguments);
+                    if args.len() != func.arity() {
+                        return Err(errors::parsing_error(&token.source, errors::arity_message(func.to_string().as_str(), func.arity(), args.len()).as_str()));
+                    }
+
+                    ParsingNodeValue::Parsed(OpNode {
+                        source: Rc::clone(&token.source),
+                        value: OpValue::MultiFunction {
+                            func,
+                            args: args.into_iter().map(Rc::new).collect()
+                        }
+                    })
+                },
+
+                TokenValue::Log => {
+
+                    let args = extract_right!(arguments);
+                    let [base, arg]: [OpNode; 2] = args.try_into().map_err(|_|
+                        errors::parsing_error(&token.source, "`log` takes 2 arguments, a base and a number, like `log(2, x)`.")
+                    )?;
+
+                    ParsingNodeValue::Parsed(OpNode {
+                        source: Rc::clone(&token.source),
+                        value: OpValue::Log {
+                            base: Rc::new(base),
                             arg: Rc::new(arg)
                         }
                     })
                 },
                     
+                // Closing parentheses are consumed by their opening parenthesis, so this one has no match
                 TokenValue::ParenClose 
-                    => unreachable!(),
+                    => return Err(errors::parsing_error(&token.source, "Unmatched closing parenthesis.")),
+
+                TokenValue::BraceClose
+                    => return Err(errors::parsing_error(&token.source, "Unmatched closing brace.")),
             };
 
         }
 
         // Convert the parsed tree into a proper ast
 
-        // Assume the pointer is not null because the linked list should never be empty
-        let root = unsafe { Box::from_raw(self.first_ptr) };
-
         // If the pointers are different, there is more than one root node in the tree
         if self.first_ptr != self.last_ptr {
-            errors::parsing_error(root.value.get_source(), self.source, "Expression does not evaluate to a single value");
+            let first = unsafe { &*self.first_ptr };
+            return Err(errors::parsing_error(first.value.get_source(), "Expression does not evaluate to a single value"));
         }
 
-        let root = if let ParsingNodeValue::Parsed(opnode) = root.value {
-            Rc::new(opnode)
-        } else {
-            unreachable!()
-        };
+        // Assume the pointer is not null because the linked list should never be empty
+        let root = unsafe { Box::from_raw(self.first_ptr) };
+
+        // The root node is now owned by the box, make sure it won't be dropped again with the list
+        self.first_ptr = ptr::null_mut();
+        self.last_ptr = ptr::null_mut();
 
-        FunctionTree {
-            root
+        match root.value {
+            root @ (ParsingNodeValue::Parsed(_) | ParsingNodeValue::Equation(_)) => Ok(root),
+            ParsingNodeValue::Arguments(args)
+                => Err(errors::parsing_error(&args[0].source, "Argument lists are only allowed in function calls.")),
+            misplaced @ (ParsingNodeValue::Condition(_) | ParsingNodeValue::Pieces(_))
+                => Err(misplaced_error(&misplaced)),
+            _ => unreachable!()
         }
     }
 
@@ -312,38 +622,13 @@ impl Drop for UnparsedTree<'_> {
 }
 
 
-/// Assumes the passed pointer is not null
-fn get_highest_priority(nodes: *const ParsingNode) -> Option<&mut ParsingNode> {
-
-    let mut highest_priority = None;
-    
-    let mut node_ptr = nodes;
-
-    while let Some(node) = unsafe { node_ptr.as_ref() } {
-
-        let node_priority = if let ParsingNodeValue::Unparsed { token: _, priority } = node.value {
-            priority
-        } else {
-            // The node has already been parsed, it has no priority
-            node_ptr = node.next;
-            continue;
-        };
-
-        // TODO: This branching could be avoided by moving the else branch outside the loop
-        // This is fairly ok, though, because this program does not have to be extremely efficient
-        if let Some((_hp_node, hp)) = highest_priority {
-            if node_priority > hp {
-                highest_priority = Some((node_ptr, node_priority));
-            }
-        } else {
-            highest_priority = Some((node_ptr, node_priority));
-        }
-
-        node_ptr = node.next;
-    }
-
-    highest_priority.map(|(node, _)| 
-        unsafe { &mut *(node as *mut ParsingNode) }
-    )
+/// The error for a condition or pieces found outside of a piecewise function
+fn misplaced_error(value: &ParsingNodeValue) -> CalcError {
+    let message = match value {
+        ParsingNodeValue::Condition(_) => "Comparisons are only allowed as the conditions of a piecewise function, like `{x if x > 0; 0 otherwise}`.",
+        ParsingNodeValue::Pieces(_) => "Pieces are only allowed between braces, like `{x if x > 0; 0 otherwise}`.",
+        _ => "An equation can't be an operand, there can only be one `=`.",
+    };
+    errors::parsing_error(value.get_source(), message)
 }
 
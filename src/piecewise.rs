@@ -0,0 +1,127 @@
+use std::rc::Rc;
+
+use crate::abstract_functions;
+use crate::ast::{self, Condition, FunctionTree, OpNode, OpValue, Piece};
+use crate::bytecode;
+use crate::derivatives::{self, NonDifferentiable};
+use crate::dual;
+use crate::errors::CalcError;
+use crate::functions::Kink;
+use crate::limits::{self, Side};
+
+
+/// The breakpoints of the piecewise function `node` where it may not be differentiable with respect to `dvar`,
+/// that is where one of its conditions changes.
+///
+/// If the condition compares `dvar` with a constant, the breakpoint is located and compared with the one-sided limits of the function
+/// and of its derivative, so it is only reported if the function is actually not differentiable there.
+/// Other breakpoints can't be located, so they are always reported, like the kinks of `abs`.
+pub fn breakpoints<'a>(node: &Rc<OpNode<'a>>, pieces: &[Piece<'a>], dvar: &'a str) -> Vec<NonDifferentiable<'a>> {
+
+    let function = FunctionTree { root: Rc::clone(node) };
+
+    // Limits can only be computed for functions of a single variable, and abstract functions have no value to compute them from
+    let single_variable = abstract_functions::find(node).is_none() && bytecode::compile(&function).variables().iter().all(|var| *var == dvar);
+
+    let mut found: Vec<NonDifferentiable<'a>> = Vec::new();
+
+    for condition in pieces.iter().filter_map(|piece| piece.condition.as_ref()) {
+
+        if !condition.left.contains_variable(dvar) && !condition.right.contains_variable(dvar) {
+            continue;
+        }
+
+        let breakpoint = if single_variable { locate(condition, dvar) } else { None };
+
+        let reason = match breakpoint {
+            Some(point) => match compare_sides(&function, dvar, point) {
+                Ok(None) => continue,
+                Ok(reason) => reason,
+                // The limits couldn't be computed, so the function may not be differentiable
+                Err(_) => None
+            },
+            None => None
+        };
+
+        let argument = difference(node, condition, dvar);
+        if !found.iter().any(|other| ast::same_expression(&other.argument, &argument)) {
+            found.push(NonDifferentiable { expression: Rc::clone(node), argument, kink: Kink::Zero, reason });
+        }
+    }
+
+    found
+}
+
+
+/// The value of `dvar` where the condition changes, if it compares `dvar` with a constant
+fn locate(condition: &Condition, dvar: &str) -> Option<f64> {
+
+    let constant = match (&condition.left.value, &condition.right.value) {
+        (OpValue::Variable(name), _) if *name == dvar => &condition.right,
+        (_, OpValue::Variable(name)) if *name == dvar => &condition.left,
+        _ => return None
+    };
+
+    let program = bytecode::compile(&FunctionTree { root: Rc::clone(constant) });
+    if !program.variables().is_empty() {
+        return None;
+    }
+
+    Some(program.run(&[], &mut program.new_registers())).filter(|point| point.is_finite())
+}
+
+
+/// The sides of the condition subtracted, with the side containing `dvar` first.
+/// The condition changes where the difference is 0
+fn difference<'a>(node: &Rc<OpNode<'a>>, condition: &Condition<'a>, dvar: &str) -> Rc<OpNode<'a>> {
+
+    let (left, right) = if condition.left.contains_variable(dvar) {
+        (&condition.left, &condition.right)
+    } else {
+        (&condition.right, &condition.left)
+    };
+
+    // Comparisons with 0 are the most common, like `x < 0`
+    if matches!(right.value, OpValue::Number(n) if n == 0_f64) {
+        return Rc::clone(left);
+    }
+
+    let difference = FunctionTree {
+        root: Rc::new(OpNode {
+            source: Rc::clone(&node.source),
+            value: OpValue::Sub { left: Rc::clone(left), right: Rc::clone(right) }
+        })
+    };
+
+    difference.simplify().root
+}
+
+
+/// Compare the value of the function at the breakpoint with its limits from both sides, then do the same with its derivative.
+/// Return why the function is not differentiable at the breakpoint, or `None` if it is
+fn compare_sides<'a>(function: &FunctionTree<'a>, dvar: &'a str, point: f64) -> Result<Option<String>, CalcError> {
+
+    let left = limits::limit(function, dvar, point, Side::Left)?.value;
+    let right = limits::limit(function, dvar, point, Side::Right)?.value;
+
+    if !dual::agrees(left, right) {
+        return Ok(Some(format!("since it jumps from {} to {}", left, right)));
+    }
+
+    let program = bytecode::compile(function);
+    let value = program.run(&vec![point; program.variables().len()], &mut program.new_registers());
+
+    if !dual::agrees(value, left) {
+        return Ok(Some(format!("since its value {} differs from its limit {}", value, left)));
+    }
+
+    let derivative = derivatives::derive(function, dvar);
+    let left = limits::limit(&derivative, dvar, point, Side::Left)?.value;
+    let right = limits::limit(&derivative, dvar, point, Side::Right)?.value;
+
+    if !dual::agrees(left, right) {
+        return Ok(Some(format!("since the derivative from the left is {}, but the derivative from the right is {}", left, right)));
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,305 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use clap::ValueEnum;
+
+use crate::ast::FunctionTree;
+use crate::bytecode;
+use crate::errors::{self, CalcError};
+
+
+/// Numerical integration methods
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Method {
+    /// Adaptive Simpson's rule
+    Simpson,
+    /// Adaptive 15-point Gauss–Kronrod rule, with the embedded 7-point Gauss rule for the error estimate
+    GaussKronrod,
+}
+
+
+/// Approximate value of a definite integral
+pub struct Estimate {
+    pub value: f64,
+    /// Estimated upper bound of the absolute error
+    pub error: f64,
+    /// Number of evaluations of the integrand
+    pub evaluations: usize,
+}
+
+
+/// Maximum number of interval bisections, so that singularities and oscillations don't make the integration run forever.
+/// When the limit is reached, the error estimate is returned as is
+const MAX_BISECTIONS: usize = 10_000;
+
+
+/// A part of the integration interval, with the estimate of the integral over it
+struct Interval<T> {
+    a: f64,
+    b: f64,
+    value: f64,
+    error: f64,
+    /// What the rule needs to estimate the integral over the halves of the interval
+    samples: T,
+}
+
+impl<T> PartialEq for Interval<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Interval<T> {}
+
+impl<T> PartialOrd for Interval<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Intervals are ordered by their error, a NaN error being the largest
+impl<T> Ord for Interval<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.error.abs().total_cmp(&other.error.abs())
+    }
+}
+
+
+/// Bisect the interval with the largest error until the sum of the errors of the intervals that can still be bisected is
+/// within the tolerance.
+/// Unlike bisecting every interval until its share of the tolerance is reached, this doesn't spend the whole budget
+/// drilling into an integrable singularity at one end of the interval.
+/// Return the value and the error estimate of the integral
+fn bisect_largest_errors<T>(whole: Interval<T>, tolerance: f64, bisections: &mut usize, mut bisect: impl FnMut(&Interval<T>) -> [Interval<T>; 2]) -> (f64, f64) {
+
+    let mut intervals = BinaryHeap::from([whole]);
+
+    // The estimates of the parts too small to be bisected, whose error can't be reduced anymore
+    let (mut value, mut error) = (0_f64, 0_f64);
+
+    while *bisections != 0 {
+
+        // The error is NaN when the integrand is infinite at a node of an interval, which is then bisected first
+        if intervals.iter().map(|interval| interval.error).sum::<f64>() <= tolerance {
+            break;
+        }
+
+        let Some(interval) = intervals.pop() else {
+            break;
+        };
+
+        // The function is not defined somewhere in the interval, bisecting won't help
+        if interval.value.is_nan() {
+            intervals.push(interval);
+            break;
+        }
+
+        // The midpoint is rounded to one of the ends, so the interval can't be bisected anymore
+        let midpoint = (interval.a + interval.b) / 2_f64;
+        if midpoint == interval.a || midpoint == interval.b {
+            value += interval.value;
+            error += interval.error;
+            continue;
+        }
+
+        *bisections -= 1;
+        let [left, right] = bisect(&interval);
+
+        // The nodes of a half are rounded onto an integrable singularity, like `1 / sqrt(x)` at 0, that is too close to be
+        // resolved. That half is estimated by the difference between the whole interval and the other half, and the
+        // estimate is also counted in the error
+        match (interval.value.is_finite(), left.value.is_infinite(), right.value.is_infinite()) {
+            (true, true, true) => {
+                value += interval.value;
+                error += interval.error;
+            },
+            (true, true, false) => {
+                let rest = interval.value - right.value;
+                value += rest;
+                error += rest.abs() + interval.error;
+                intervals.push(right);
+            },
+            (true, false, true) => {
+                let rest = interval.value - left.value;
+                value += rest;
+                error += rest.abs() + interval.error;
+                intervals.push(left);
+            },
+            _ => intervals.extend([left, right]),
+        }
+    }
+
+    intervals.iter().fold((value, error), |(value, error), interval| (value + interval.value, error + interval.error))
+}
+
+
+/// Integrate the function with respect to `var` from `from` to `to`, with an absolute error of about `tolerance`
+pub fn integrate(function: &FunctionTree, var: &str, from: f64, to: f64, method: Method, tolerance: f64) -> Result<Estimate, CalcError> {
+
+    let program = bytecode::compile(function);
+    let mut registers = program.new_registers();
+
+    // The integrand must not have any other variable
+    let mut args = program.arguments(&[(var.to_string(), 0_f64)])?.into_iter().copied().collect::<Vec<_>>();
+
+    let mut evaluations = 0;
+    let mut integrand = |x: f64| {
+        evaluations += 1;
+        args.fill(x);
+        program.run(&args, &mut registers)
+    };
+
+    let mut bisections = MAX_BISECTIONS;
+
+    let (value, error) = match method {
+        Method::Simpson => simpson(&mut integrand, from, to, tolerance, &mut bisections),
+        Method::GaussKronrod => gauss_kronrod(&mut integrand, from, to, tolerance, &mut bisections),
+    };
+
+    if !value.is_finite() {
+        return Err(errors::invalid_input("The integral does not converge, or the function is not defined on the whole interval"));
+    }
+
+    Ok(Estimate {
+        value,
+        error,
+        evaluations
+    })
+}
+
+
+/// Return the value and the error estimate of the integral
+fn simpson(f: &mut impl FnMut(f64) -> f64, a: f64, b: f64, tolerance: f64, bisections: &mut usize) -> (f64, f64) {
+
+    // The rule evaluates the integrand at the bounds, where it may have an integrable singularity, like `1 / sqrt(x)` at 0.
+    // A single point has no weight in the integral, so a value there that is not finite is left out
+    let bound = |value: f64| if value.is_finite() { value } else { 0_f64 };
+
+    let (fa, fb) = (bound(f(a)), bound(f(b)));
+    let m = (a + b) / 2_f64;
+    let fm = f(m);
+
+    let whole = (b - a) / 6_f64 * (fa + 4_f64 * fm + fb);
+
+    let interval = simpson_interval(f, (a, fa), (m, fm), (b, fb), whole);
+
+    bisect_largest_errors(interval, tolerance, bisections, |interval| {
+        let [fa, flm, fm, frm, fb] = interval.samples.values;
+        let (a, b) = (interval.a, interval.b);
+        let m = (a + b) / 2_f64;
+        let [left, right] = interval.samples.halves;
+        [
+            simpson_interval(f, (a, fa), ((a + m) / 2_f64, flm), (m, fm), left),
+            simpson_interval(f, (m, fm), ((m + b) / 2_f64, frm), (b, fb), right),
+        ]
+    })
+}
+
+
+/// The integrand at the ends, quarters and midpoint of an interval, and Simpson's rule over its halves
+struct SimpsonSamples {
+    values: [f64; 5],
+    halves: [f64; 2],
+}
+
+
+/// Split the interval in half and compare the sum of the halves with the estimate over the whole interval.
+/// By Richardson extrapolation, the error of the halves is about a fifteenth of their difference.
+fn simpson_interval(f: &mut impl FnMut(f64) -> f64, (a, fa): (f64, f64), (m, fm): (f64, f64), (b, fb): (f64, f64), whole: f64) -> Interval<SimpsonSamples> {
+
+    let (lm, rm) = ((a + m) / 2_f64, (m + b) / 2_f64);
+    let (flm, frm) = (f(lm), f(rm));
+
+    let left = (m - a) / 6_f64 * (fa + 4_f64 * flm + fm);
+    let right = (b - m) / 6_f64 * (fm + 4_f64 * frm + fb);
+    let delta = left + right - whole;
+
+    Interval {
+        a,
+        b,
+        value: left + right + delta / 15_f64,
+        error: delta.abs() / 15_f64,
+        samples: SimpsonSamples {
+            values: [fa, flm, fm, frm, fb],
+            halves: [left, right],
+        },
+    }
+}
+
+
+/// Nodes of the 15-point Kronrod rule on `[-1, 1]`, from 1 to 0. The odd ones are the nodes of the 7-point Gauss rule
+const KRONROD_NODES: [f64; 8] = [
+    0.9914553711208126,
+    0.9491079123427585,
+    0.8648644233597691,
+    0.7415311855993945,
+    0.5860872354676911,
+    0.4058451513773972,
+    0.20778495500789848,
+    0_f64,
+];
+
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022935322010529224,
+    0.06309209262997856,
+    0.10479001032225019,
+    0.14065325971552592,
+    0.1690047266392679,
+    0.19035057806478542,
+    0.20443294007529889,
+    0.20948214108472782,
+];
+
+/// Weights of the 7-point Gauss rule, for the nodes `KRONROD_NODES[1]`, `[3]`, `[5]` and `[7]`
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.1294849661688697,
+    0.27970539148927664,
+    0.3818300505051189,
+    0.4179591836734694,
+];
+
+
+/// Return the value and the error estimate of the integral.
+/// The intervals are bisected until the Kronrod and Gauss estimates agree within the tolerance.
+fn gauss_kronrod(f: &mut impl FnMut(f64) -> f64, a: f64, b: f64, tolerance: f64, bisections: &mut usize) -> (f64, f64) {
+    let interval = gauss_kronrod_interval(f, a, b);
+    bisect_largest_errors(interval, tolerance, bisections, |interval| {
+        let center = (interval.a + interval.b) / 2_f64;
+        [gauss_kronrod_interval(f, interval.a, center), gauss_kronrod_interval(f, center, interval.b)]
+    })
+}
+
+
+/// Estimate the integral over the interval with the Kronrod rule, and its error by the difference with the Gauss rule.
+/// The integrand is not evaluated at the ends of the interval
+fn gauss_kronrod_interval(f: &mut impl FnMut(f64) -> f64, a: f64, b: f64) -> Interval<()> {
+
+    let center = (a + b) / 2_f64;
+    let half_length = (b - a) / 2_f64;
+
+    let mut kronrod = 0_f64;
+    let mut gauss = 0_f64;
+
+    for (i, (node, weight)) in KRONROD_NODES.iter().zip(KRONROD_WEIGHTS).enumerate() {
+
+        // The center node is not mirrored
+        let sum = if *node == 0_f64 {
+            f(center)
+        } else {
+            f(center - half_length * node) + f(center + half_length * node)
+        };
+
+        kronrod += weight * sum;
+        if i % 2 == 1 {
+            gauss += GAUSS_WEIGHTS[i / 2] * sum;
+        }
+    }
+
+    Interval {
+        a,
+        b,
+        value: kronrod * half_length,
+        error: ((kronrod - gauss) * half_length).abs(),
+        samples: (),
+    }
+}
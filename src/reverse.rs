@@ -0,0 +1,174 @@
+use crate::ast::{self, FunctionTree, OpNode, OpValue};
+use crate::dual;
+use crate::errors::{self, CalcError};
+
+
+/// A recorded operation: its value and the partial derivative of the value with respect to each operand
+struct TapeEntry {
+    value: f64,
+    /// Tape index of each operand, with the partial derivative with respect to it
+    operands: Vec<(usize, f64)>,
+}
+
+
+/// Record of the evaluation of a function, in evaluation order
+struct Tape<'a> {
+    entries: Vec<TapeEntry>,
+    /// Tape index of each variable
+    variables: Vec<(&'a str, usize)>,
+}
+
+
+/// The value of a function at a point, with all its partial derivatives
+pub struct Gradient<'a> {
+    pub value: f64,
+    /// Partial derivative with respect to each variable, in order of appearance in the function
+    pub partials: Vec<(&'a str, f64)>,
+}
+
+
+/// Compute all the partial derivatives of the function at the given point (reverse-mode automatic differentiation).
+///
+/// The function is evaluated once while recording every operation on a tape, then the tape is swept backwards to
+/// accumulate the derivative of the result with respect to every intermediate value, variables included.
+pub fn gradient<'a>(tree: &FunctionTree<'a>, point: &[(String, f64)]) -> Result<Gradient<'a>, CalcError> {
+
+    let mut tape = Tape {
+        entries: Vec::new(),
+        variables: Vec::new(),
+    };
+
+    let result = tape.record(&tree.root, point)?;
+
+    // Derivative of the result with respect to each entry
+    let mut adjoints = vec![0_f64; tape.entries.len()];
+    adjoints[result] = 1_f64;
+
+    // Operands are always recorded before their operation, so a reverse sweep visits every entry after all its users
+    for (index, entry) in tape.entries.iter().enumerate().rev() {
+        let adjoint = adjoints[index];
+        if adjoint == 0_f64 {
+            continue;
+        }
+        for (operand, partial) in &entry.operands {
+            adjoints[*operand] += adjoint * partial;
+        }
+    }
+
+    Ok(Gradient {
+        value: tape.entries[result].value,
+        partials: tape.variables.iter()
+            .map(|(name, index)| (*name, adjoints[*index]))
+            .collect()
+    })
+}
+
+
+impl<'a> Tape<'a> {
+
+    fn push(&mut self, value: f64, operands: Vec<(usize, f64)>) -> usize {
+        self.entries.push(TapeEntry { value, operands });
+        self.entries.len() - 1
+    }
+
+
+    /// Evaluate the node, recording every operation. Return the tape index of the node's value
+    fn record(&mut self, node: &OpNode<'a>, point: &[(String, f64)]) -> Result<usize, CalcError> {
+
+        let index = match &node.value {
+
+            OpValue::Number(n) => self.push(*n, Vec::new()),
+
+            OpValue::Variable(name) => {
+                if let Some((_, index)) = self.variables.iter().find(|(var, _)| var == name) {
+                    return Ok(*index);
+                }
+                match point.iter().find(|(var, _)| var == name) {
+                    Some((_, value)) => {
+                        let index = self.push(*value, Vec::new());
+                        self.variables.push((name, index));
+                        index
+                    },
+                    None => match ast::constant_value(name) {
+                        Some(value) => self.push(value, Vec::new()),
+                        None => return Err(errors::invalid_input(format!("No value was given for variable `{}`", name).as_str()))
+                    }
+                }
+            },
+
+            OpValue::Add { left, right } => {
+                let (left, right) = (self.record(left, point)?, self.record(right, point)?);
+                let value = self.entries[left].value + self.entries[right].value;
+                self.push(value, vec![(left, 1_f64), (right, 1_f64)])
+            },
+
+            OpValue::Sub { left, right } => {
+                let (left, right) = (self.record(left, point)?, self.record(right, point)?);
+                let value = self.entries[left].value - self.entries[right].value;
+                self.push(value, vec![(left, 1_f64), (right, -1_f64)])
+            },
+
+            OpValue::Mul { left, right } => {
+                let (left, right) = (self.record(left, point)?, self.record(right, point)?);
+                let (a, b) = (self.entries[left].value, self.entries[right].value);
+                self.push(a * b, vec![(left, b), (right, a)])
+            },
+
+            OpValue::Div { left, right } => {
+                let (left, right) = (self.record(left, point)?, self.record(right, point)?);
+                let (a, b) = (self.entries[left].value, self.entries[right].value);
+                self.push(a / b, vec![(left, 1_f64 / b), (right, -a / (b * b))])
+            },
+
+            OpValue::Pow { left, right } => {
+                let (left, right) = (self.record(left, point)?, self.record(right, point)?);
+                let (a, b) = (self.entries[left].value, self.entries[right].value);
+                let value = ast::exec_pow(a, b);
+                // For negative bases the partial derivative with respect to the exponent is NaN.
+                // This is harmless when the exponent is constant, because the NaN only flows into constant entries.
+                self.push(value, vec![(left, b * ast::exec_pow(a, b - 1_f64)), (right, value * a.ln())])
+            },
+
+            OpValue::Function { func, arg } => {
+                let arg = self.record(arg, point)?;
+                let x = self.entries[arg].value;
+                self.push(ast::exec_function(*func, x), vec![(arg, dual::function_derivative(*func, x))])
+            },
+
+            OpValue::MultiFunction { func, args } => {
+                let args = args.iter().map(|arg| self.record(arg, point)).collect::<Result<Vec<_>, _>>()?;
+                let values = args.iter().map(|arg| self.entries[*arg].value).collect::<Vec<_>>();
+                let partials = dual::multi_function_partials(*func, &values);
+                self.push(ast::exec_multi_function(*func, &values), args.into_iter().zip(partials).collect())
+            },
+
+            OpValue::Call { .. } => unreachable!("Functions containing abstract functions are rejected before being evaluated"),
+
+            // The value of the piece whose condition holds, the conditions themselves don't contribute to the derivative
+            OpValue::Piecewise { pieces } => {
+                let mut sides = Vec::new();
+                let active = ast::active_piece(pieces, |condition| {
+                    let (left, right) = (self.record(&condition.left, point)?, self.record(&condition.right, point)?);
+                    sides.extend([left, right]);
+                    Ok::<_, CalcError>(condition.comparison.holds(self.entries[left].value, self.entries[right].value))
+                })?;
+                match active {
+                    Some(piece) => self.record(&piece.value, point)?,
+                    // Undefined, and so are the partial derivatives
+                    None => self.push(f64::NAN, sides.into_iter().map(|side| (side, f64::NAN)).collect())
+                }
+            },
+
+            // log(b, a) = ln(a) / ln(b)
+            OpValue::Log { base, arg } => {
+                let (base, arg) = (self.record(base, point)?, self.record(arg, point)?);
+                let (b, a) = (self.entries[base].value, self.entries[arg].value);
+                let value = ast::exec_log(b, a);
+                self.push(value, vec![(base, -value / (b * b.ln())), (arg, 1_f64 / (a * b.ln()))])
+            },
+        };
+
+        Ok(index)
+    }
+
+}
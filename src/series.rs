@@ -0,0 +1,140 @@
+use std::rc::Rc;
+
+use crate::ast::{FunctionTree, OpNode, OpValue};
+use crate::bytecode;
+use crate::derivatives;
+use crate::errors::{self, CalcError};
+
+
+/// A Taylor polynomial of a function
+pub struct Series<'a> {
+    /// Coefficient of every power of `(x - a)`, from the constant term up to the order of the polynomial
+    pub coefficients: Vec<FunctionTree<'a>>,
+    pub polynomial: FunctionTree<'a>,
+}
+
+
+/// Taylor polynomial of the function around `x = around`, up to the given order:
+/// `Σ f^(k)(a) / k! * (x - a)^k`.
+/// Coefficients are exact when the simplifier can fold them into numbers, and are kept symbolic when they depend on other variables.
+pub fn taylor<'a>(function: &FunctionTree<'a>, var: &'a str, around: f64, order: usize) -> Result<Series<'a>, CalcError> {
+
+    let source = &function.root;
+    let new = |value: OpValue<'a>| Rc::new(OpNode { source: Rc::clone(&source.source), value });
+
+    // Kinks that also depend on other variables can't be located, so they are not checked
+    let point_value = [(var.to_string(), around)];
+    if let Some(kink) = derivatives::non_differentiable(function, var).iter().find(|kink| kink.contains(&point_value).unwrap_or(false)) {
+        return Err(errors::invalid_input(
+            format!("{}, there is no Taylor series around {}", kink, around).as_str()
+        ));
+    }
+
+    let point = new(OpValue::Number(around));
+
+    // x - a, or just x for Maclaurin series
+    let offset = if around == 0_f64 {
+        new(OpValue::Variable(var))
+    } else {
+        new(OpValue::Sub { left: new(OpValue::Variable(var)), right: Rc::clone(&point) })
+    };
+
+    let mut derivative = function.simplify();
+    let mut factorial = Some(1_u64);
+
+    let mut coefficients = Vec::with_capacity(order + 1);
+    let mut polynomial: Option<Rc<OpNode<'a>>> = None;
+
+    for k in 0..=order {
+
+        if k > 0 {
+            derivative = derivatives::derive(&derivative, var).simplify();
+            factorial = factorial.and_then(|factorial| factorial.checked_mul(k as u64));
+        }
+
+        let coefficient = coefficient(&derivative.substitute(var, &point).simplify(), factorial, k, around)?;
+
+        let power = match k {
+            0 => None,
+            1 => Some(Rc::clone(&offset)),
+            _ => Some(new(OpValue::Pow { left: Rc::clone(&offset), right: new(OpValue::Number(k as f64)) })),
+        };
+
+        let term = match (&coefficient.root.value, power) {
+            (OpValue::Number(0_f64), _) => None,
+            (_, None) => Some(Rc::clone(&coefficient.root)),
+            (OpValue::Number(1_f64), Some(power)) => Some(power),
+            (_, Some(power)) => Some(new(OpValue::Mul { left: Rc::clone(&coefficient.root), right: power })),
+        };
+
+        coefficients.push(coefficient);
+
+        if let Some(term) = term {
+            polynomial = Some(match polynomial {
+                Some(polynomial) => new(OpValue::Add { left: polynomial, right: term }),
+                None => term
+            });
+        }
+    }
+
+    Ok(Series {
+        coefficients,
+        // Every coefficient is zero
+        polynomial: FunctionTree { root: polynomial.unwrap_or_else(|| new(OpValue::Number(0_f64))) }
+    })
+}
+
+
+/// The coefficient `f^(k)(a) / k!`, given the value of the derivative at the point.
+/// When the derivative is an integer, the coefficient is kept as a reduced fraction, like `-1 / 6`, instead of a rounded number.
+/// `factorial` is `None` if `k!` is too large to be exact.
+fn coefficient<'a>(derivative: &FunctionTree<'a>, factorial: Option<u64>, k: usize, around: f64) -> Result<FunctionTree<'a>, CalcError> {
+
+    let new = |value: OpValue<'a>| Rc::new(OpNode { source: Rc::clone(&derivative.root.source), value });
+
+    let float_factorial = (1..=k).map(|i| i as f64).product::<f64>();
+
+    let program = bytecode::compile(derivative);
+
+    // The derivative depends on other variables, keep the coefficient symbolic
+    if !program.variables().is_empty() {
+        return Ok(FunctionTree {
+            root: new(OpValue::Div { left: Rc::clone(&derivative.root), right: new(OpValue::Number(float_factorial)) })
+        }.simplify());
+    }
+
+    // Constants like `e` and `pi` are not folded by the simplifier, so evaluate the derivative here
+    let value = program.run(&[], &mut program.new_registers());
+
+    if !value.is_finite() {
+        return Err(errors::invalid_input(
+            format!("The derivative of order {} is not defined at {}, there is no Taylor series around this point", k, around).as_str()
+        ));
+    }
+
+    let root = match factorial {
+
+        // Integers up to 2^53 are exact
+        Some(factorial) if value.fract() == 0_f64 && value.abs() < 2_f64.powi(53) => {
+            let numerator = value.abs() as u64;
+            let divisor = gcd(numerator, factorial);
+            let numerator = value.signum() * (numerator / divisor) as f64;
+            match factorial / divisor {
+                1 => new(OpValue::Number(numerator)),
+                denominator => new(OpValue::Div {
+                    left: new(OpValue::Number(numerator)),
+                    right: new(OpValue::Number(denominator as f64))
+                }),
+            }
+        },
+
+        _ => new(OpValue::Number(value / float_factorial)),
+    };
+
+    Ok(FunctionTree { root })
+}
+
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
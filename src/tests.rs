@@ -1,15 +1,568 @@
+use std::time::Instant;
 
-#[cfg(test)]
-mod tests {
-    use crate::tokenizer;
+use crate::tokenizer;
+use crate::errors::ErrorKind;
+use crate::ast::{self, OpNode, OpValue};
+use crate::derivatives;
+use crate::bytecode;
+use crate::dual;
+use crate::reverse;
+use crate::verification;
+use crate::integrals;
+use crate::quadrature::{self, Method};
+use crate::series;
+use crate::limits::{self, Side};
+use crate::trigonometry::{self, TrigStyle};
+use crate::notation::{self, Notation};
+use crate::batch;
+use crate::codegen::{self, Language};
+use crate::definitions::{self, Definitions};
+use crate::abstract_functions::{self, DerivativeNotation};
+use crate::implicit;
 
 
-    #[test]
-    fn test() {
-        let foo = "1+1";
-        let tokens = tokenizer::tokenize(foo);
-        let _ast = tokens.parse();
+#[test]
+fn test() {
+    let foo = "1+1";
+    let tokens = tokenizer::tokenize(foo).unwrap();
+    let _ast = tokens.parse().unwrap();
+}
+
+
+#[test]
+fn infix_notation_drops_redundant_parentheses() {
+    let tree = tokenizer::tokenize("(2 * (x ^ 2)) - (y - (3 / z))").unwrap().parse().unwrap();
+    assert_eq!(notation::format_tree(&tree, Notation::Infix), "2 * x ^ 2 - (y - 3 / z)");
+}
+
+
+#[test]
+fn parsing_errors_point_to_the_offending_token() {
+    let error = tokenizer::tokenize("(x + 1))").err().unwrap();
+    let token = error.token.unwrap();
+    assert_eq!((token.column, token.end_column()), (8, 9));
+}
+
+
+#[test]
+fn columns_count_leading_whitespace() {
+    let error = tokenizer::tokenize("  x + $").err().unwrap();
+    assert!(matches!(error.kind, ErrorKind::InvalidToken));
+    assert_eq!(error.token.unwrap().column, 7);
+}
+
+
+#[test]
+fn parsing_errors_are_returned_for_every_malformed_input() {
+    for input in ["x y", "x +", "(x + 1", "x)"] {
+        let error = tokenizer::tokenize(input).and_then(|tokens| tokens.parse());
+        assert!(error.is_err(), "`{}` should not parse", input);
+    }
+}
+
+
+#[test]
+fn batch_lines_keep_their_number_and_directive() {
+    let lines = batch::parse_lines(["x^2", "", "# comment", "x*y ; d=y"].into_iter().map(str::to_string));
+    assert_eq!(lines.len(), 2);
+    assert_eq!((lines[1].number, lines[1].function.as_str(), lines[1].variable.as_deref()), (4, "x*y", Some("y")));
+}
+
+
+#[test]
+fn emitted_code_takes_the_free_variables_as_arguments() {
+    let tree = tokenizer::tokenize("sec(y) * x ^ 2 + pi").unwrap().parse().unwrap();
+    assert_eq!(
+        codegen::emit_function(&tree, "f", Language::Javascript),
+        "function f(x, y) {\n    return ((1.0 / Math.cos(y)) * Math.pow(x, 2.0)) + Math.PI;\n}\n"
+    );
+}
+
+
+#[test]
+fn emitted_odd_roots_are_real_for_negative_numbers() {
+    let emit = |input: &str, language: Language| {
+        let tree = tokenizer::tokenize(input).unwrap().parse().unwrap();
+        codegen::emit_function(&tree, "f", language)
+    };
+    assert_eq!(emit("root(3, x)", Language::C), "#include <math.h>\n\ndouble f(double x) {\n    return cbrt(x);\n}\n");
+    assert_eq!(emit("x ^ (-2/3)", Language::Python), "import math\n\n\ndef f(x):\n    return math.pow(math.fabs(x), (-0.6666666666666666))\n");
+    assert_eq!(emit("root(5, x)", Language::Rust), "pub fn f(x: f64) -> f64 {\n    x.abs().powf(0.2_f64).copysign(x)\n}\n");
+}
+
+
+/// Naive tree-walking evaluation, used as a reference for the compiled evaluators
+fn walk(node: &OpNode, x: f64) -> f64 {
+    match &node.value {
+        OpValue::Number(n) => *n,
+        OpValue::Add { left, right } => walk(left, x) + walk(right, x),
+        OpValue::Sub { left, right } => walk(left, x) - walk(right, x),
+        OpValue::Mul { left, right } => walk(left, x) * walk(right, x),
+        OpValue::Div { left, right } => walk(left, x) / walk(right, x),
+        OpValue::Pow { left, right } => ast::exec_pow(walk(left, x), walk(right, x)),
+        OpValue::Variable("x") => x,
+        OpValue::Variable(name) => ast::constant_value(name).unwrap(),
+        OpValue::Function { func, arg } => ast::exec_function(*func, walk(arg, x)),
+        OpValue::Log { base, arg } => ast::exec_log(walk(base, x), walk(arg, x)),
+        OpValue::MultiFunction { func, args } => ast::exec_multi_function(*func, &args.iter().map(|arg| walk(arg, x)).collect::<Vec<_>>()),
+        OpValue::Call { .. } => unreachable!(),
+        OpValue::Piecewise { pieces } => pieces.iter()
+            .find(|piece| piece.condition.as_ref().is_none_or(|condition| condition.comparison.holds(walk(&condition.left, x), walk(&condition.right, x))))
+            .map_or(f64::NAN, |piece| walk(&piece.value, x)),
+    }
+}
+
+
+#[test]
+fn bytecode_computes_common_subexpressions_once() {
+    let tree = tokenizer::tokenize("sin(x) * sin(x) + sin(x) * sin(x)").unwrap().parse().unwrap();
+    let program = bytecode::compile(&tree);
+    let mut registers = program.new_registers();
+    // x, sin(x), sin(x) * sin(x), and the sum
+    assert_eq!(registers.len(), 4);
+    assert_eq!(program.run(&[0.5], &mut registers), walk(&tree.root, 0.5));
+}
+
+
+#[test]
+fn batch_evaluation_matches_pointwise_evaluation() {
+    let tree = tokenizer::tokenize("x * sec(x) - ln(x + 2) ^ 2").unwrap().parse().unwrap();
+    let program = bytecode::compile(&tree);
+    // More points than a single chunk
+    let points = (0..1000).map(|i| i as f64 / 100.0).collect::<Vec<_>>();
+    let mut values = vec![0_f64; points.len()];
+    program.run_batch(&[&points], &mut values);
+    for (x, value) in points.iter().zip(values) {
+        assert_eq!(value, walk(&tree.root, *x));
+    }
+}
+
+
+#[test]
+#[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+fn bench_bytecode_against_tree_walking() {
+
+    let tree = tokenizer::tokenize("sin(x^2) * e^(x/3) / sqrt(x + 1) - atan(x) ^ 3").unwrap().parse().unwrap();
+    let derivative = derivatives::derive(&tree, "x").simplify();
+    let points = (0..1_000_000).map(|i| i as f64 * 1e-6).collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let walked = points.iter().map(|x| walk(&derivative.root, *x)).sum::<f64>();
+    let walking_time = start.elapsed();
+
+    let start = Instant::now();
+    let program = bytecode::compile(&derivative);
+    let mut registers = program.new_registers();
+    let compiled = points.iter().map(|x| program.run(&[*x], &mut registers)).sum::<f64>();
+    let bytecode_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut values = vec![0_f64; points.len()];
+    program.run_batch(&[&points], &mut values);
+    let batch = values.iter().sum::<f64>();
+    let batch_time = start.elapsed();
+
+    println!("tree walking: {:?}, bytecode: {:?}, batch bytecode: {:?}", walking_time, bytecode_time, batch_time);
+    assert_eq!(walked, compiled);
+    assert_eq!(walked, batch);
+}
+
+
+#[test]
+fn symbolic_derivatives_match_automatic_differentiation() {
+
+    let functions = [
+        "x^3 - 2*x^2 + x - 7",
+        "(x^2 + 1) / (x - 3)",
+        "sin(x) * cos(x) + tan(x)",
+        "asin(x / 2) - acos(x / 3) + atan(x)",
+        "sqrt(x^2 + 1) * ln(x + 2)",
+        "sec(2*x) ^ 3",
+        "x ^ x + 2 ^ sin(x)",
+        "(x - 2) ^ (3 - 1)",
+        "exp(x^2) + log10(x + 2) - log2(x + 3)",
+        "log(x + 2, x^2 + 1)",
+        "atan2(x^2 + 1, x - 2) * hypot(x, 2) + max(x^2, x / 2) - min(x, 0.5)",
+        "cbrt(x^2 - 3) + root(5, x) * x^(2/3)",
+        "|x^2 - 0.5| * sign(x) + floor(3 * x) - heaviside(x - 0.5)",
+        "{x^2 if x < 0; sin(x) * x otherwise} + {ln(x) if x >= 0.5; 1 if x > 0}",
+    ];
+
+    for function in functions {
+        let tree = tokenizer::tokenize(function).unwrap().parse().unwrap();
+        let derivative = derivatives::derive(&tree, "x").simplify();
+        for x in [-0.7, 0.3, 0.9] {
+            let automatic = dual::evaluate(&tree, "x", &[("x".to_string(), x)]).unwrap();
+            let symbolic = walk(&derivative.root, x);
+            assert!(dual::agrees(symbolic, automatic.derivative), "d/dx {} at x={}: {} != {}", function, x, symbolic, automatic.derivative);
+        }
+    }
+}
+
+
+#[test]
+fn gradient_matches_symbolic_partial_derivatives() {
+
+    let tree = tokenizer::tokenize("x^2 * y + sin(x * z) / (y + 2) - z ^ y").unwrap().parse().unwrap();
+    let point = [("x".to_string(), 0.4), ("y".to_string(), 1.3), ("z".to_string(), 2.1)];
+
+    let gradient = reverse::gradient(&tree, &point).unwrap();
+    assert_eq!(gradient.partials.iter().map(|(var, _)| *var).collect::<Vec<_>>(), ["x", "y", "z"]);
+
+    for (var, partial) in gradient.partials {
+        let symbolic = derivatives::derive(&tree, var);
+        let program = bytecode::compile(&symbolic);
+        let args = program.arguments(&point).unwrap().into_iter().copied().collect::<Vec<_>>();
+        let value = program.run(&args, &mut program.new_registers());
+        assert!(dual::agrees(value, partial), "d/d{}: {} != {}", var, value, partial);
+    }
+}
+
+
+#[test]
+fn verification_reports_wrong_derivatives() {
+
+    let function = tokenizer::tokenize("x^3").unwrap().parse().unwrap();
+    let right = tokenizer::tokenize("3 * x^2").unwrap().parse().unwrap();
+    let wrong = tokenizer::tokenize("(3 * x)^2").unwrap().parse().unwrap();
+
+    let points = verification::random_points(&function, "x", 10, 5.0, 42);
+    assert_eq!(points.len(), 10);
+
+    assert!(verification::verify(&function, &right, &[], "x", &points, 1e-6).unwrap().mismatches.is_empty());
+    assert_eq!(verification::verify(&function, &wrong, &[], "x", &points, 1e-6).unwrap().mismatches.len(), 10);
+}
+
+
+#[test]
+fn verification_adapts_the_step_near_poles_and_kinks() {
+
+    let tan = tokenizer::tokenize("tan(x)").unwrap().parse().unwrap();
+    let derivative = tokenizer::tokenize("sec(x)^2").unwrap().parse().unwrap();
+    let report = verification::verify(&tan, &derivative, &[], "x", &[vec![("x".to_string(), -7.8788)]], 1e-6).unwrap();
+    assert_eq!((report.checked, report.mismatches.len()), (1, 0));
+
+    // A derivative that is not finite where the function is smooth is wrong
+    let infinite = tokenizer::tokenize("1 / (x - 1)").unwrap().parse().unwrap();
+    let report = verification::verify(&tan, &infinite, &[], "x", &[vec![("x".to_string(), 1.0)]], 1e-6).unwrap();
+    assert_eq!(report.mismatches.len(), 1);
+
+    let function = tokenizer::tokenize("floor(x) * x").unwrap().parse().unwrap();
+    let derivative = derivatives::derive(&function, "x");
+    let non_differentiable = derivatives::non_differentiable(&function, "x");
+    let report = verification::verify(&function, &derivative, &non_differentiable, "x", &[vec![("x".to_string(), 2.0004)]], 1e-6).unwrap();
+    assert_eq!((report.checked, report.mismatches.len()), (1, 0));
+}
+
+
+#[test]
+fn explanation_lists_the_rules_in_derivation_order() {
+    let tree = tokenizer::tokenize("sin(x^2) / y").unwrap().parse().unwrap();
+    let (_, steps) = derivatives::derive_explained(&tree, "x");
+    let rules = steps.iter().map(|step| (step.depth, step.rule.to_string())).collect::<Vec<_>>();
+    assert_eq!(rules, [
+        (0, "quotient rule".to_string()),
+        (1, "chain rule with sin".to_string()),
+        (2, "power rule".to_string()),
+        (3, "variable rule".to_string()),
+        (1, "constant variable rule".to_string()),
+    ]);
+}
+
+
+#[test]
+fn simplification_trace_records_every_rewrite() {
+    let tree = tokenizer::tokenize("(3 + 4) * x * 1 + 0 * y").unwrap().parse().unwrap();
+    let (simplified, steps) = tree.simplify_explained();
+    assert_eq!(notation::format_tree(&simplified, Notation::Infix), "7 * x");
+    let steps = steps.iter().map(|step| (step.rule.to_string(), step.before.as_str(), step.after.as_str())).collect::<Vec<_>>();
+    assert_eq!(steps, [
+        ("constant folding".to_string(), "3 + 4", "7"),
+        ("multiplicative identity".to_string(), "7 * x * 1", "7 * x"),
+        ("zero product".to_string(), "0 * y", "0"),
+        ("additive identity".to_string(), "7 * x + 0", "7 * x"),
+    ]);
+}
+
+
+#[test]
+fn rewritten_operations_are_simplified_again() {
+    let tree = tokenizer::tokenize("(x^3)^(1/3) / (y / z)").unwrap().parse().unwrap();
+    let (simplified, steps) = tree.simplify_explained();
+    // The root of the power is only found once the exponent is folded, and it cancels once it is written as a root
+    assert_eq!(notation::format_tree(&simplified, Notation::Infix), "x / (y / z)");
+    let steps = steps.iter().map(|step| (step.rule.to_string(), step.after.as_str())).collect::<Vec<_>>();
+    assert_eq!(steps, [
+        ("constant folding".to_string(), "0.3333333333333333"),
+        ("nth root".to_string(), "cbrt(x ^ 3)"),
+        ("inverse functions".to_string(), "x"),
+    ]);
+}
+
+
+#[test]
+fn hyperbolic_identity_is_simplified() {
+    let tree = tokenizer::tokenize("x * (cosh(x)^2 - sinh(x)^2)").unwrap().parse().unwrap();
+    assert_eq!(notation::format_tree(&tree.simplify(), Notation::Infix), "x");
+    let tree = tokenizer::tokenize("tanh(x)").unwrap().parse().unwrap();
+    let derivative = derivatives::derive(&tree, "x").simplify();
+    assert_eq!(notation::format_tree(&derivative, Notation::Infix), "1 / cosh(x) ^ 2");
+}
+
+
+#[test]
+fn logarithms_cancel_their_inverse() {
+    let simplify = |input: &str| {
+        let tree = tokenizer::tokenize(input).unwrap().parse().unwrap();
+        notation::format_tree(&tree.simplify(), Notation::Infix)
+    };
+    assert_eq!(simplify("exp(ln(x)) + ln(exp(x))"), "x + x");
+    assert_eq!(simplify("log(y, y^x) * log(y, y)"), "x");
+    assert_eq!(simplify("log10(10^x) - log2(2^x)"), "x - x");
+    assert!(tokenizer::tokenize("log(x)").unwrap().parse().is_err());
+}
+
+
+#[test]
+fn multi_argument_functions_check_their_arity() {
+    let parse = |input: &str| tokenizer::tokenize(input).unwrap().parse().map(|tree| notation::format_tree(&tree, Notation::Infix));
+    assert_eq!(parse("atan2(y, x) + hypot(x, 2 * y)").unwrap(), "atan2(y, x) + hypot(x, 2 * y)");
+    assert!(parse("atan2(x)").is_err());
+    assert!(parse("max(x, 1, 2)").is_err());
+}
+
+
+#[test]
+fn fractional_exponents_are_written_as_roots() {
+    let simplify = |input: &str| {
+        let tree = tokenizer::tokenize(input).unwrap().parse().unwrap();
+        notation::format_tree(&tree.simplify(), Notation::Infix)
+    };
+    assert_eq!(simplify("x^(1/3) + x^0.5 + (x + 1)^(1/5)"), "cbrt(x) + sqrt(x) + root(5, x + 1)");
+    assert_eq!(simplify("root(3, x^3) * root(4, x)^4"), "x * x");
+    // Odd roots of negative numbers are real, whether they are written as roots or as powers
+    assert_eq!(ast::exec_root(3_f64, -8_f64), -2_f64);
+    assert!((ast::exec_pow(-8_f64, 2_f64 / 3_f64) - 4_f64).abs() < 1e-12);
+    assert!(ast::exec_pow(-8_f64, 0.5).is_nan());
+}
+
+
+#[test]
+fn kinks_are_reported_where_the_derivative_does_not_exist() {
+    let function = tokenizer::tokenize("|x - |y|| + floor(x) * max(x, 2)").unwrap().parse().unwrap();
+    assert_eq!(notation::format_tree(&function, Notation::Infix), "abs(x - abs(y)) + floor(x) * max(x, 2)");
+
+    let kinks = derivatives::non_differentiable(&function, "x");
+    let conditions = kinks.iter().map(|kink| kink.condition()).collect::<Vec<_>>();
+    assert_eq!(conditions, ["x - abs(y) = 0", "x is an integer", "x - 2 = 0"]);
+
+    let point = |x: f64| [("x".to_string(), x), ("y".to_string(), -1.5)];
+    assert!(kinks[0].contains(&point(1.5)).unwrap());
+    assert!(!kinks[0].contains(&point(-1.5)).unwrap());
+    assert!(kinks[1].contains(&point(-3_f64)).unwrap());
+
+    // The partial derivatives of min and max select one of the arguments
+    let derive = |input: &str| {
+        let function = tokenizer::tokenize(input).unwrap().parse().unwrap();
+        notation::format_tree(&derivatives::derive(&function, "x").simplify(), Notation::Infix)
+    };
+    assert_eq!(derive("max(x, 2)"), "heaviside(x - 2)");
+    assert_eq!(derive("min(x, 2)"), "heaviside(2 - x)");
+
+    assert!(tokenizer::tokenize("|x| + |(y|)").is_err());
+}
+
+
+#[test]
+fn piecewise_breakpoints_are_checked_with_one_sided_limits() {
+    let reasons = |input: &str| {
+        let function = tokenizer::tokenize(input).unwrap().parse().unwrap();
+        derivatives::non_differentiable(&function, "x").into_iter()
+            .map(|breakpoint| (breakpoint.condition(), breakpoint.reason))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(notation::format_tree(&tokenizer::tokenize("{x^2 if x <= 0; sin(x) otherwise}").unwrap().parse().unwrap(), Notation::Prefix), "(cond ((<= x 0) (^ x 2)) (else (sin x)))");
+
+    assert_eq!(reasons("{x^2 if x < 0; sin(x) otherwise}"), [("x = 0".to_string(), Some("since the derivative from the left is 0, but the derivative from the right is 1".to_string()))]);
+    assert_eq!(reasons("{0 if x < 1; 1 otherwise}"), [("x - 1 = 0".to_string(), Some("since it jumps from 0 to 1".to_string()))]);
+    // Both pieces meet with the same slope
+    assert!(reasons("{x^2 if x < 0; x^3 otherwise}").is_empty());
+    // The breakpoint can't be located, so it is always reported
+    assert_eq!(reasons("{x if x > y; y otherwise}"), [("x - y = 0".to_string(), None)]);
+
+    assert!(tokenizer::tokenize("{x otherwise; 1 if x < 0}").unwrap().parse().is_err());
+    assert!(tokenizer::tokenize("x < 1").unwrap().parse().is_err());
+}
+
+
+#[test]
+fn user_defined_functions_are_expanded() {
+    let sources = ["g(t) = t^2 + 1", "h(x, y) = g(x) - y", "f(x) = k(x)", "k(x) = f(x)"].map(str::to_string);
+    let definitions = Definitions::parse_all(&sources).unwrap();
+    let expand = |input: &str| {
+        let function = tokenizer::tokenize(input).unwrap().parse().unwrap();
+        definitions.expand(&function).map(|expanded| notation::format_tree(&expanded, Notation::Infix))
+    };
+    assert_eq!(expand("g(sin(x))").unwrap(), "sin(x) ^ 2 + 1");
+    // The parameters are replaced simultaneously, so `y` doesn't replace the `x` it was swapped with
+    assert_eq!(expand("h(y, x)").unwrap(), "y ^ 2 + 1 - x");
+    assert!(expand("f(x)").unwrap_err().message.contains("f -> k -> f"));
+    // Functions without a definition are kept as abstract functions
+    assert_eq!(expand("u(g(x))").unwrap(), "u(x ^ 2 + 1)");
+    assert_eq!(expand("g(x, 2)").unwrap_err().message, "`g` takes 1 argument, but 2 were given.");
+    // A function can only be defined once
+    assert!(Definitions::parse_all(&["g(t) = t", "g(t) = 2*t"].map(str::to_string)).is_err());
+
+    assert!(definitions::is_definition("g(t) = t^2"));
+    assert!(!definitions::is_definition("g(t) + 1"));
+    assert!(Definitions::parse_all(&["sin(t) = t".to_string()]).is_err());
+    assert!(Definitions::parse_all(&["g(t, t) = t".to_string()]).is_err());
+}
+
+
+#[test]
+fn abstract_functions_have_symbolic_derivatives() {
+    let derive = |input: &str, notation: DerivativeNotation| {
+        let function = tokenizer::tokenize(input).unwrap().parse().unwrap();
+        let derivative = derivatives::derive(&function, "x").simplify();
+        notation::format_tree(&abstract_functions::rewrite(&derivative, notation), Notation::Infix)
+    };
+    assert_eq!(derive("f(x) * g(x)", DerivativeNotation::Prime), "f'(x) * g(x) + f(x) * g'(x)");
+    assert_eq!(derive("f(x) * g(x)", DerivativeNotation::Leibniz), "(df(x) / dx) * g(x) + f(x) * (dg(x) / dx)");
+    assert_eq!(derive("f(sin(x))", DerivativeNotation::Prime), "f'(sin(x)) * cos(x)");
+    assert_eq!(derive("f(x, y) + f(y, x)", DerivativeNotation::Prime), "f_1(x, y) + f_2(y, x)");
+    assert_eq!(derive("f(y)", DerivativeNotation::Prime), "0");
+
+    let function = tokenizer::tokenize("f(x^2)").unwrap().parse().unwrap();
+    let second = derivatives::derive(&derivatives::derive(&function, "x"), "x").simplify();
+    assert!(notation::format_tree(&second, Notation::Infix).contains("f''(x ^ 2)"));
+    assert_eq!(notation::format_tree(&abstract_functions::rewrite(&second, DerivativeNotation::Leibniz), Notation::Latex).matches("\\frac{d^{2} f").count(), 1);
+
+    assert!(abstract_functions::require_defined(&function).is_err());
+}
+
+
+#[test]
+fn implicit_derivatives_are_solved_for_the_dependent_variable() {
+    let derive = |input: &str| {
+        let equation = tokenizer::tokenize(input).unwrap().parse_equation().unwrap();
+        implicit::differentiate(&equation, "x", "y").map(|derivative| notation::format_tree(&derivative, Notation::Infix))
+    };
+    assert_eq!(derive("x * y = 1").unwrap(), "-1 * y / x");
+    assert_eq!(derive("x^3 + y^3 = 6*x*y").unwrap(), "(6 * y - 3 * x ^ 2) / (3 * y ^ 2 - 6 * x)");
+    assert_eq!(derive("y = f(x)").unwrap(), "f'(x)");
+
+    // The slope of the circle at (3, 4)
+    let equation = tokenizer::tokenize("x^2 + y^2 = 25").unwrap().parse_equation().unwrap();
+    let circle = bytecode::compile(&implicit::differentiate(&equation, "x", "y").unwrap());
+    let args = circle.arguments(&[("x".to_string(), 3_f64), ("y".to_string(), 4_f64)]).unwrap().into_iter().copied().collect::<Vec<_>>();
+    assert_eq!(circle.run(&args, &mut circle.new_registers()), -0.75);
+
+    assert!(derive("x = 3").is_err());
+    assert!(tokenizer::tokenize("x^2 + y^2").unwrap().parse_equation().is_err());
+    assert!(tokenizer::tokenize("x = y = 1").unwrap().parse_equation().is_err());
+}
+
+
+#[test]
+fn reciprocal_trig_functions_follow_the_output_style() {
+    let derive = |input: &str, style: TrigStyle| {
+        let tree = tokenizer::tokenize(input).unwrap().parse().unwrap();
+        let derivative = derivatives::derive(&tree, "x").simplify();
+        notation::format_tree(&trigonometry::rewrite(&derivative, style), Notation::Infix)
+    };
+    assert_eq!(derive("cot(x)", TrigStyle::Reciprocal), "-1 * csc(x) ^ 2");
+    assert_eq!(derive("cot(x)", TrigStyle::Quotient), "-1 * (1 / sin(x)) ^ 2");
+    assert_eq!(derive("ln(sin(x))", TrigStyle::Reciprocal), "cot(x)");
+    assert_eq!(derive("tan(x)", TrigStyle::Quotient), "(1 / cos(x)) ^ 2");
+}
+
+
+#[test]
+fn antiderivatives_differentiate_back_to_the_function() {
+    let integrate = |input: &str| {
+        let function = tokenizer::tokenize(input).unwrap().parse().unwrap();
+        integrals::integrate(&function, "x").map(|antiderivative| notation::format_tree(&antiderivative, Notation::Infix))
+    };
+    assert_eq!(integrate("3*x^2 + 2*x + 1").unwrap(), "3 * (x ^ 3 / 3) + 2 * (x ^ 2 / 2) + x");
+    assert_eq!(integrate("sec(x)^2").unwrap(), "tan(x)");
+    assert_eq!(integrate("1/(1+x^2)").unwrap(), "atan(x)");
+    assert_eq!(integrate("1/sqrt(1-x^2)").unwrap(), "asin(x)");
+    assert_eq!(integrate("2*x*cos(x^2)").unwrap(), "sin(x ^ 2)");
+    assert_eq!(integrate("x*e^x").unwrap(), "x * e ^ x - e ^ x");
+    assert_eq!(integrate("x^2*sin(x)").unwrap(), "x ^ 2 * (-1 * cos(x)) - (2 * x ^ 1 * (-1 * sin(x)) - (2 * x ^ 0 * (-1 * (-1 * cos(x))) - 0))");
+    assert_eq!(integrate("ln(x)").unwrap(), "x * ln(x) - x");
+    // Logarithmic antiderivatives hold on both sides of the poles
+    assert_eq!(integrate("1/x").unwrap(), "ln(abs(x))");
+    assert_eq!(integrate("tan(x)").unwrap(), "-1 * ln(abs(cos(x)))");
+    assert_eq!(integrate("cot(x)").unwrap(), "ln(abs(sin(x)))");
+    assert!(integrate("x^x").is_err());
+}
+
+
+#[test]
+fn quadrature_methods_agree_with_the_exact_integral() {
+    let function = tokenizer::tokenize("x * sin(x)").unwrap().parse().unwrap();
+    // ∫ x sin(x) dx from 0 to pi = pi
+    for method in [Method::Simpson, Method::GaussKronrod] {
+        let estimate = quadrature::integrate(&function, "x", 0_f64, std::f64::consts::PI, method, 1e-10).unwrap();
+        assert!((estimate.value - std::f64::consts::PI).abs() < 1e-9);
+        assert!(estimate.error < 1e-9);
     }
+}
+
+
+#[test]
+fn quadrature_handles_integrable_endpoint_singularities() {
+    // ∫ 1/sqrt(x) dx from 0 to 1 = 2 and ∫ 1/sqrt(1-x^2) dx from -1 to 1 = pi
+    let cases = [("1/sqrt(x)", 0_f64, 1_f64, 2_f64), ("1/sqrt(1-x^2)", -1_f64, 1_f64, std::f64::consts::PI)];
+    for (function, from, to, exact) in cases {
+        let function = tokenizer::tokenize(function).unwrap().parse().unwrap();
+        for method in [Method::Simpson, Method::GaussKronrod] {
+            let estimate = quadrature::integrate(&function, "x", from, to, method, 1e-10).unwrap();
+            assert!((estimate.value - exact).abs() < 1e-7);
+        }
+    }
+}
+
 
+#[test]
+fn taylor_coefficients_are_exact_fractions() {
+    let function = tokenizer::tokenize("sin(x)").unwrap().parse().unwrap();
+    let series = series::taylor(&function, "x", 0_f64, 5).unwrap();
+    let coefficients = series.coefficients.iter()
+        .map(|coefficient| notation::format_tree(coefficient, Notation::Infix))
+        .collect::<Vec<_>>();
+    assert_eq!(coefficients, ["0", "1", "0", "-1 / 6", "0", "1 / 120"]);
+    assert_eq!(notation::format_tree(&series.polynomial, Notation::Infix), "x + -1 / 6 * x ^ 3 + 1 / 120 * x ^ 5");
 }
 
+
+#[test]
+fn limits_resolve_indeterminate_forms() {
+    let limit = |input: &str, point: f64, side: Side| {
+        let function = tokenizer::tokenize(input).unwrap().parse().unwrap();
+        limits::limit(&function, "x", point, side).map(|limit| limit.value)
+    };
+    assert_eq!(limit("sin(x)/x", 0_f64, Side::Both).unwrap(), 1_f64);
+    assert_eq!(limit("x^x", 0_f64, Side::Right).unwrap(), 1_f64);
+    assert!((limit("(1+1/x)^x", f64::INFINITY, Side::Both).unwrap() - std::f64::consts::E).abs() < 1e-12);
+    assert_eq!(limit("1/x", 0_f64, Side::Right).unwrap(), f64::INFINITY);
+    assert!(limit("1/x", 0_f64, Side::Both).is_err());
+    // The rounded limit point doesn't hide the pole
+    assert_eq!(limit("tan(x)", std::f64::consts::FRAC_PI_2, Side::Left).unwrap(), f64::INFINITY);
+    assert!(limit("tan(x)", std::f64::consts::FRAC_PI_2, Side::Both).is_err());
+    // A function defined on one side only has a one-sided limit, which is reported as such
+    let function = tokenizer::tokenize("ln(x)").unwrap().parse().unwrap();
+    let one_sided = limits::limit(&function, "x", 0_f64, Side::Both).unwrap();
+    assert!(one_sided.value == f64::NEG_INFINITY && one_sided.side == Side::Right);
+    // Step functions jump at their kinks
+    assert_eq!(limit("floor(x)", 2_f64, Side::Left).unwrap(), 1_f64);
+    assert_eq!(limit("heaviside(x - 1)", 1_f64, Side::Right).unwrap(), 1_f64);
+    assert!(limit("sign(x)", 0_f64, Side::Both).is_err());
+    // Bounded oscillations are squeezed by a factor going to zero
+    assert_eq!(limit("sin(x)/x", f64::INFINITY, Side::Both).unwrap(), 0_f64);
+    assert_eq!(limit("x*sin(1/x)", 0_f64, Side::Both).unwrap(), 0_f64);
+    assert_eq!(limit("x^2*sin(1/x)", 0_f64, Side::Both).unwrap(), 0_f64);
+    assert!(limit("sin(1/x)", 0_f64, Side::Both).unwrap_err().message.contains("cannot be determined"));
+}
@@ -4,15 +4,16 @@ use std::rc::Rc;
 use regex::Regex;
 use lazy_static::lazy_static;
 
-use crate::errors;
+use crate::errors::{self, CalcError};
 use crate::parsing_tree::{UnparsedTree, Priority};
-use crate::functions::Functions;
+use crate::functions::{Functions, MultiFunctions};
+use crate::ast::Comparison;
 
 
 lazy_static! {
 
     static ref TOKEN_REGEX: Regex = Regex::new(
-        r#"(?m)[_a-zA-Z]\w*|-?\d+[.]\d*|-?[.]?\d+|[-+/*^()]|\S"#
+        r#"(?m)[_a-zA-Z]\w*|-?\d+[.]\d*|-?[.]?\d+|<=|>=|[-+/*^(),|{};<>=]|\S"#
     ).expect("Regex failed to compile");
 
     static ref VARIABLE_REGEX: Regex = Regex::new(
@@ -36,9 +37,26 @@ pub enum TokenValue<'a> {
     Pow,
     ParenOpen,
     ParenClose,
+    Comma,
+    /// Opens a piecewise function, `{x if x > 0; 0 otherwise}`
+    BraceOpen,
+    BraceClose,
+    /// Separates the pieces of a piecewise function
+    Semicolon,
+    /// Gives a piece its condition
+    If,
+    /// Marks the last piece, which has no condition
+    Otherwise,
+    Comparison(Comparison),
+    /// Separates the sides of an equation, like a definition `g(t) = t^2 + 1`
+    Equals,
+    /// The logarithm in an arbitrary base, `log(b, x)`
+    Log,
     Identifier (&'a str),
     Number(f64),
-    Function(Functions)
+    Function(Functions),
+    /// A function of several arguments, like `atan2(y, x)`
+    MultiFunction(MultiFunctions),
 }
 
 impl fmt::Display for TokenValue<'_> {
@@ -51,9 +69,19 @@ impl fmt::Display for TokenValue<'_> {
             TokenValue::Pow => write!(f, "^"),
             TokenValue::ParenOpen => write!(f, "("),
             TokenValue::ParenClose => write!(f, ")"),
+            TokenValue::Comma => write!(f, ","),
+            TokenValue::BraceOpen => write!(f, "{{"),
+            TokenValue::BraceClose => write!(f, "}}"),
+            TokenValue::Semicolon => write!(f, ";"),
+            TokenValue::If => write!(f, "if"),
+            TokenValue::Otherwise => write!(f, "otherwise"),
+            TokenValue::Comparison(comparison) => write!(f, "{}", comparison),
+            TokenValue::Equals => write!(f, "="),
+            TokenValue::Log => write!(f, "log"),
             TokenValue::Identifier(name) => write!(f, "{}", name),
             TokenValue::Number(n) => write!(f, "{}", n),
-            TokenValue::Function(func) => write!(f, "{}", func)
+            TokenValue::Function(func) => write!(f, "{}", func),
+            TokenValue::MultiFunction(func) => write!(f, "{}", func),
         }
     }
 }
@@ -63,15 +91,25 @@ impl TokenValue<'_> {
     pub fn base_priority(&self) -> Priority {
         match self {
             TokenValue::ParenClose => 0, // Doesn't get evaluated
-            TokenValue::Plus => 1,
-            TokenValue::Minus => 1,
-            TokenValue::Mul => 2,
-            TokenValue::Div => 2,
-            TokenValue::Pow => 3,
-            TokenValue::Identifier(_) => 4,
-            TokenValue::Number(_) => 4, // Numbers are evaluated right away because they don't require operands
-            TokenValue::Function(_) => 4,
-            TokenValue::ParenOpen => 5,
+            TokenValue::BraceClose => 0,
+            TokenValue::Equals => 0, // Parsed last, once both sides are parsed
+            TokenValue::Comma => 1, // Separates the arguments after they are parsed
+            TokenValue::Semicolon => 1, // Separates the pieces after they are parsed
+            TokenValue::If => 2,
+            TokenValue::Otherwise => 2,
+            TokenValue::Comparison(_) => 3,
+            TokenValue::Plus => 4,
+            TokenValue::Minus => 4,
+            TokenValue::Mul => 5,
+            TokenValue::Div => 5,
+            TokenValue::Pow => 6,
+            TokenValue::Identifier(_) => 7,
+            TokenValue::Number(_) => 7, // Numbers are evaluated right away because they don't require operands
+            TokenValue::Function(_) => 7,
+            TokenValue::MultiFunction(_) => 7,
+            TokenValue::Log => 7,
+            TokenValue::ParenOpen => 8,
+            TokenValue::BraceOpen => 8,
         }
     }
 
@@ -91,29 +129,34 @@ pub struct Token<'a> {
 
 fn lex<'a>(source: &'a str) -> impl Iterator<Item = SourceToken<'a>> {
 
-    let source = source.trim();
-
     TOKEN_REGEX.find_iter(source)
         .map(|mat| 
-            match mat.as_str() {
-                s => SourceToken {
-                    string: s,
-                    column: mat.start() + 1
-                }
+            SourceToken {
+                string: mat.as_str(),
+                column: mat.start() + 1
             }
-        ).into_iter()
+        )
 }
 
 
-pub fn tokenize<'a>(source: &'a str) -> UnparsedTree<'a> {
+pub fn tokenize<'a>(source: &'a str) -> Result<UnparsedTree<'a>, CalcError> {
 
     let raw_tokens = lex(source);
 
-    let mut tokens = UnparsedTree::new(source);
+    let mut tokens = UnparsedTree::new();
 
     let mut positional_priority: Priority = 0;
 
+    // Positional priority inside every absolute value bar that is still open, and the bar itself
+    let mut bars: Vec<(Priority, Rc<SourceToken>)> = Vec::new();
+
+    // Whether the previous token ends an operand, so that a bar after it closes an absolute value
+    let mut after_operand = false;
+
     for token in raw_tokens {
+
+        let mut operand = false;
+
         match token.string {
 
             "+" => tokens.push_token(
@@ -156,6 +199,67 @@ pub fn tokenize<'a>(source: &'a str) -> UnparsedTree<'a> {
                 positional_priority
             ),
 
+            "," => tokens.push_token(
+                Token {
+                    value: TokenValue::Comma,
+                    source: Rc::new(token)
+                },
+                positional_priority
+            ),
+
+            "=" => tokens.push_token(
+                Token {
+                    value: TokenValue::Equals,
+                    source: Rc::new(token)
+                },
+                positional_priority
+            ),
+
+            ";" => tokens.push_token(
+                Token {
+                    value: TokenValue::Semicolon,
+                    source: Rc::new(token)
+                },
+                positional_priority
+            ),
+
+            "if" => tokens.push_token(
+                Token {
+                    value: TokenValue::If,
+                    source: Rc::new(token)
+                },
+                positional_priority
+            ),
+
+            "otherwise" => tokens.push_token(
+                Token {
+                    value: TokenValue::Otherwise,
+                    source: Rc::new(token)
+                },
+                positional_priority
+            ),
+
+            "<" | "<=" | ">" | ">=" => tokens.push_token(
+                Token {
+                    value: TokenValue::Comparison(match token.string {
+                        "<" => Comparison::Less,
+                        "<=" => Comparison::LessOrEqual,
+                        ">" => Comparison::Greater,
+                        _ => Comparison::GreaterOrEqual,
+                    }),
+                    source: Rc::new(token)
+                },
+                positional_priority
+            ),
+
+            "log" => tokens.push_token(
+                Token {
+                    value: TokenValue::Log,
+                    source: Rc::new(token)
+                },
+                positional_priority
+            ),
+
             "(" => {
                 tokens.push_token(
                     Token {
@@ -168,6 +272,9 @@ pub fn tokenize<'a>(source: &'a str) -> UnparsedTree<'a> {
             },
 
             ")" => {
+                if positional_priority < TokenValue::max_priority() {
+                    return Err(errors::parsing_error(&token, "Unmatched closing parenthesis."));
+                }
                 positional_priority -= TokenValue::max_priority();
                 tokens.push_token(
                     Token {
@@ -176,6 +283,71 @@ pub fn tokenize<'a>(source: &'a str) -> UnparsedTree<'a> {
                     },
                     positional_priority
                 );
+                operand = true;
+            },
+
+            "{" => {
+                tokens.push_token(
+                    Token {
+                        value: TokenValue::BraceOpen,
+                        source: Rc::new(token)
+                    },
+                    positional_priority
+                );
+                positional_priority += TokenValue::max_priority();
+            },
+
+            "}" => {
+                if positional_priority < TokenValue::max_priority() {
+                    return Err(errors::parsing_error(&token, "Unmatched closing brace."));
+                }
+                positional_priority -= TokenValue::max_priority();
+                tokens.push_token(
+                    Token {
+                        value: TokenValue::BraceClose,
+                        source: Rc::new(token)
+                    },
+                    positional_priority
+                );
+                operand = true;
+            },
+
+            // `|x|` is read as `abs(x)`. A bar closes the innermost absolute value if it follows an operand, otherwise it opens a new one
+            "|" => {
+                let source = Rc::new(token);
+
+                if after_operand && !bars.is_empty() {
+                    let (priority, _) = bars.pop().unwrap();
+                    if priority != positional_priority {
+                        return Err(errors::parsing_error(&source, "Unmatched absolute value bar, a parenthesis is still open."));
+                    }
+                    positional_priority -= TokenValue::max_priority();
+                    tokens.push_token(
+                        Token {
+                            value: TokenValue::ParenClose,
+                            source
+                        },
+                        positional_priority
+                    );
+                    operand = true;
+                } else {
+                    tokens.push_token(
+                        Token {
+                            value: TokenValue::Function(Functions::Abs),
+                            source: Rc::clone(&source)
+                        },
+                        positional_priority
+                    );
+                    tokens.push_token(
+                        Token {
+                            value: TokenValue::ParenOpen,
+                            source: Rc::clone(&source)
+                        },
+                        positional_priority
+                    );
+                    positional_priority += TokenValue::max_priority();
+                    bars.push((positional_priority, source));
+                }
             },
 
             string => {
@@ -188,6 +360,7 @@ pub fn tokenize<'a>(source: &'a str) -> UnparsedTree<'a> {
                         },
                         positional_priority
                     );
+                    operand = true;
                 } else if let Some(function) = Functions::from_name(string) {
                     tokens.push_token(
                         Token {
@@ -195,7 +368,14 @@ pub fn tokenize<'a>(source: &'a str) -> UnparsedTree<'a> {
                             source: Rc::new(token)
                         }, positional_priority
                     );
-                } else if TOKEN_REGEX.is_match(string) {
+                } else if let Some(function) = MultiFunctions::from_name(string) {
+                    tokens.push_token(
+                        Token {
+                            value: TokenValue::MultiFunction(function),
+                            source: Rc::new(token)
+                        }, positional_priority
+                    );
+                } else if is_variable(string) {
                     tokens.push_token(
                         Token {
                             value: TokenValue::Identifier(string),
@@ -203,18 +383,25 @@ pub fn tokenize<'a>(source: &'a str) -> UnparsedTree<'a> {
                         },
                         positional_priority
                     );
+                    operand = true;
                 } else {
-                    errors::invalid_token(&token, source, "String is not a valid token.")
+                    return Err(errors::invalid_token(&token, "String is not a valid token."));
                 }
             }
         }
+
+        after_operand = operand;
+    }
+
+    if let Some((_, bar)) = bars.last() {
+        return Err(errors::parsing_error(bar, "Unmatched absolute value bar."));
     }
 
-    tokens
+    Ok(tokens)
 }
 
 
-pub fn is_variable<'a>(var: &'a str) -> bool {
+pub fn is_variable(var: &str) -> bool {
     VARIABLE_REGEX.is_match(var)
 }
 
@@ -0,0 +1,114 @@
+use std::rc::Rc;
+
+use clap::ValueEnum;
+
+use crate::ast::{self, FunctionTree, OpNode, OpValue};
+use crate::functions::Functions;
+
+
+/// How the reciprocal trigonometric functions are written in the output
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TrigStyle {
+    /// `csc(x)`, `sec(x)` and `cot(x)`
+    Reciprocal,
+    /// `1 / sin(x)`, `1 / cos(x)` and `cos(x) / sin(x)`
+    Quotient,
+}
+
+
+/// The reciprocal of `sin`, `cos` and `tan`
+fn reciprocal(func: Functions) -> Option<Functions> {
+    match func {
+        Functions::Sin => Some(Functions::Cosecant),
+        Functions::Cos => Some(Functions::Secant),
+        Functions::Tan => Some(Functions::Cotangent),
+        _ => None
+    }
+}
+
+
+/// Rewrite the reciprocal trigonometric functions in the given style
+pub fn rewrite<'a>(tree: &FunctionTree<'a>, style: TrigStyle) -> FunctionTree<'a> {
+    FunctionTree {
+        root: rewrite_node(&tree.root, style)
+    }
+}
+
+
+/// Rewrite the children of the node first, then the node itself
+fn rewrite_node<'a>(node: &Rc<OpNode<'a>>, style: TrigStyle) -> Rc<OpNode<'a>> {
+
+    let new = |value: OpValue<'a>| Rc::new(OpNode { source: Rc::clone(&node.source), value });
+
+    let value = match &node.value {
+
+        OpValue::Number(_) |
+        OpValue::Variable(_)
+            => return Rc::clone(node),
+
+        OpValue::Add { left, right } => OpValue::Add { left: rewrite_node(left, style), right: rewrite_node(right, style) },
+        OpValue::Sub { left, right } => OpValue::Sub { left: rewrite_node(left, style), right: rewrite_node(right, style) },
+        OpValue::Mul { left, right } => OpValue::Mul { left: rewrite_node(left, style), right: rewrite_node(right, style) },
+        OpValue::Div { left, right } => OpValue::Div { left: rewrite_node(left, style), right: rewrite_node(right, style) },
+        OpValue::Pow { left, right } => OpValue::Pow { left: rewrite_node(left, style), right: rewrite_node(right, style) },
+        OpValue::Function { func, arg } => OpValue::Function { func: *func, arg: rewrite_node(arg, style) },
+        OpValue::Log { base, arg } => OpValue::Log { base: rewrite_node(base, style), arg: rewrite_node(arg, style) },
+        OpValue::MultiFunction { func, args } => OpValue::MultiFunction { func: *func, args: args.iter().map(|arg| rewrite_node(arg, style)).collect() },
+        OpValue::Piecewise { pieces } => OpValue::Piecewise { pieces: pieces.iter().map(|piece| piece.map(|operand| rewrite_node(operand, style))).collect() },
+        OpValue::Call { name, args, derivative } => OpValue::Call { name, args: args.iter().map(|arg| rewrite_node(arg, style)).collect(), derivative: derivative.clone() },
+    };
+
+    let function = |func: Functions, arg: &Rc<OpNode<'a>>| new(OpValue::Function { func, arg: Rc::clone(arg) });
+
+    let value = match (style, value) {
+
+        // csc(u) -> 1 / sin(u), sec(u) -> 1 / cos(u)
+        (TrigStyle::Quotient, OpValue::Function { func: func @ (Functions::Cosecant | Functions::Secant), arg }) => OpValue::Div {
+            left: new(OpValue::Number(1_f64)),
+            right: function(if func == Functions::Cosecant { Functions::Sin } else { Functions::Cos }, &arg)
+        },
+
+        // cot(u) -> cos(u) / sin(u)
+        (TrigStyle::Quotient, OpValue::Function { func: Functions::Cotangent, arg }) => OpValue::Div {
+            left: function(Functions::Cos, &arg),
+            right: function(Functions::Sin, &arg)
+        },
+
+        (TrigStyle::Reciprocal, OpValue::Div { left, right }) => match (&left.value, &right.value) {
+
+            // cos(u) / sin(u) -> cot(u)
+            (OpValue::Function { func: Functions::Cos, arg: a }, OpValue::Function { func: Functions::Sin, arg: b }) if ast::same_expression(a, b)
+                => OpValue::Function { func: Functions::Cotangent, arg: Rc::clone(a) },
+
+            // a / sin(u) -> a * csc(u)
+            (_, OpValue::Function { func, arg }) => match reciprocal(*func) {
+                Some(func) => times(left, function(func, arg)),
+                None => OpValue::Div { left, right }
+            },
+
+            // a / sin(u)^n -> a * csc(u)^n
+            (_, OpValue::Pow { left: base, right: exponent }) => match &base.value {
+                OpValue::Function { func, arg } => match reciprocal(*func) {
+                    Some(func) => times(left, new(OpValue::Pow { left: function(func, arg), right: Rc::clone(exponent) })),
+                    None => OpValue::Div { left, right }
+                },
+                _ => OpValue::Div { left, right }
+            },
+
+            _ => OpValue::Div { left, right }
+        },
+
+        (_, value) => value
+    };
+
+    new(value)
+}
+
+
+/// `a * b`, or just `b` if `a` is 1
+fn times<'a>(a: Rc<OpNode<'a>>, b: Rc<OpNode<'a>>) -> OpValue<'a> {
+    match a.value {
+        OpValue::Number(1_f64) => b.value.clone(),
+        _ => OpValue::Mul { left: a, right: b }
+    }
+}
@@ -0,0 +1,212 @@
+use crate::ast::FunctionTree;
+use crate::bytecode::{self, Program};
+use crate::derivatives::NonDifferentiable;
+use crate::errors::CalcError;
+
+
+/// Number of steps tried by the numeric derivative, each a quarter of the previous one
+const STEPS: i32 = 8;
+
+
+/// A point where the symbolic and numeric derivatives disagree
+pub struct Mismatch {
+    pub point: Vec<(String, f64)>,
+    pub symbolic: f64,
+    pub numeric: f64,
+}
+
+
+pub struct Report {
+    /// Number of points where the derivatives were compared
+    pub checked: usize,
+    /// Number of points where the numeric derivative did not converge, so the symbolic derivative couldn't be checked
+    pub inconclusive: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+
+/// Compare the symbolic derivative of `function` with a numeric derivative at every point.
+/// Points where the function is not finite are outside the domain, and points where it is not differentiable,
+/// at one of the `non_differentiable` subexpressions, are not checked.
+/// A symbolic derivative that is not finite where the numeric derivative is finite is a mismatch.
+pub fn verify(function: &FunctionTree, derivative: &FunctionTree, non_differentiable: &[NonDifferentiable], dvar: &str, points: &[Vec<(String, f64)>], tolerance: f64) -> Result<Report, CalcError> {
+
+    let function = bytecode::compile(function);
+    let derivative = bytecode::compile(derivative);
+
+    let mut report = Report {
+        checked: 0,
+        inconclusive: 0,
+        mismatches: Vec::new(),
+    };
+
+    for point in points {
+
+        if !run_at(&function, point)?.is_finite() {
+            continue;
+        }
+
+        if non_differentiable.iter().map(|kink| kink.contains(point)).collect::<Result<Vec<_>, _>>()?.contains(&true) {
+            continue;
+        }
+
+        let Some(numeric) = numeric_derivative(&function, non_differentiable, dvar, point, tolerance)? else {
+            report.inconclusive += 1;
+            continue;
+        };
+        let symbolic = run_at(&derivative, point)?;
+
+        report.checked += 1;
+
+        if !symbolic.is_finite() || !close(symbolic, numeric, tolerance) {
+            report.mismatches.push(Mismatch {
+                point: point.clone(),
+                symbolic,
+                numeric
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+
+/// Check that two functions have the same value at every point where both are finite
+pub fn same_values(left: &FunctionTree, right: &FunctionTree, points: &[Vec<(String, f64)>], tolerance: f64) -> Result<bool, CalcError> {
+
+    let left = bytecode::compile(left);
+    let right = bytecode::compile(right);
+
+    for point in points {
+
+        let (left, right) = (run_at(&left, point)?, run_at(&right, point)?);
+
+        if left.is_finite() && right.is_finite() && !close(left, right, tolerance) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+
+/// Whether the values are equal up to the relative tolerance
+fn close(left: f64, right: f64, tolerance: f64) -> bool {
+    (left - right).abs() <= tolerance * 1_f64.max(left.abs()).max(right.abs())
+}
+
+
+fn run_at(program: &Program, point: &[(String, f64)]) -> Result<f64, CalcError> {
+    let args = program.arguments(point)?.into_iter().copied().collect::<Vec<_>>();
+    Ok(program.run(&args, &mut program.new_registers()))
+}
+
+
+/// The point with `dvar` set to `x`
+fn shifted(point: &[(String, f64)], dvar: &str, x: f64) -> Vec<(String, f64)> {
+    let mut shifted = point.to_vec();
+    match shifted.iter_mut().find(|(var, _)| var == dvar) {
+        Some((_, value)) => *value = x,
+        None => shifted.push((dvar.to_string(), x))
+    }
+    shifted
+}
+
+
+/// Richardson extrapolation of the central difference quotient, accurate to O(h^4).
+/// A fixed step is too large near poles and steep regions, so the step is divided by 4 until two successive estimates agree
+/// within the tolerance. Steps whose stencil crosses a non-differentiable point, or leaves the domain, are skipped.
+/// Return `None` if the estimates never agree.
+fn numeric_derivative(function: &Program, non_differentiable: &[NonDifferentiable], dvar: &str, point: &[(String, f64)], tolerance: f64) -> Result<Option<f64>, CalcError> {
+
+    let x = point.iter()
+        .find(|(var, _)| var == dvar)
+        .map_or(0_f64, |(_, value)| *value);
+
+    let evaluate_at = |x: f64| run_at(function, &shifted(point, dvar, x));
+
+    let central_difference = |h: f64| -> Result<f64, CalcError> {
+        Ok((evaluate_at(x + h)? - evaluate_at(x - h)?) / (2_f64 * h))
+    };
+
+    let mut previous = None;
+
+    for step in 0..STEPS {
+
+        let h = 1e-3 * 1_f64.max(x.abs()) / 4_f64.powi(step);
+
+        let (from, to) = (shifted(point, dvar, x - h), shifted(point, dvar, x + h));
+        if non_differentiable.iter().map(|kink| kink.crossed(&from, &to)).collect::<Result<Vec<_>, _>>()?.contains(&true) {
+            previous = None;
+            continue;
+        }
+
+        let coarse = central_difference(h)?;
+        let fine = central_difference(h / 2_f64)?;
+        let estimate = (4_f64 * fine - coarse) / 3_f64;
+
+        if !estimate.is_finite() {
+            previous = None;
+            continue;
+        }
+
+        if previous.is_some_and(|previous| close(previous, estimate, tolerance)) {
+            return Ok(Some(estimate));
+        }
+
+        previous = Some(estimate);
+    }
+
+    Ok(None)
+}
+
+
+/// Generate `count` pseudo-random points with every variable of the function in `[-range, range]`.
+/// Points where the function is not finite are outside its domain and are discarded, so fewer points may be returned.
+/// The derivative is not checked, so that points where it is wrongly not finite are still verified.
+pub fn random_points(function: &FunctionTree, dvar: &str, count: usize, range: f64, seed: u64) -> Vec<Vec<(String, f64)>> {
+
+    let function = bytecode::compile(function);
+
+    let mut variables = function.variables().iter().map(|var| var.to_string()).collect::<Vec<_>>();
+    if !variables.iter().any(|var| var == dvar) {
+        variables.push(dvar.to_string());
+    }
+
+    let mut random = XorShift(seed.max(1));
+    let mut points = Vec::with_capacity(count);
+
+    // Give up after a while, the domain of the function may be very small or empty
+    for _ in 0..count * 100 {
+
+        if points.len() == count {
+            break;
+        }
+
+        let point = variables.iter()
+            .map(|var| (var.clone(), (random.next_f64() * 2_f64 - 1_f64) * range))
+            .collect::<Vec<_>>();
+
+        if run_at(&function, &point).is_ok_and(f64::is_finite) {
+            points.push(point);
+        }
+    }
+
+    points
+}
+
+
+/// Small deterministic pseudo-random number generator, so that verification runs are reproducible
+struct XorShift(u64);
+
+impl XorShift {
+
+    /// Uniformly distributed in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+}